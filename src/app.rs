@@ -0,0 +1,258 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+    guard,
+    middleware::Logger,
+    App, Error,
+};
+use paperclip::actix::{
+    web::{delete, get, post, put, resource, ServiceConfig},
+    OpenApiExt,
+};
+
+use crate::{
+    config::RateLimit,
+    endpoints,
+    middleware::{AuthenticationService, RateLimitService, RequireRole},
+    structs::role::Permission,
+    types::{
+        FullConfig, FullDatabase, FullHasher, FullMailer, FullNotifier, FullSigner, RateLimiter,
+        RiskScorer,
+    },
+};
+
+/// Build the application, this is shared between the server and the tests. The
+/// parts are created once by the caller, so every worker shares them.
+#[allow(clippy::too_many_arguments)]
+pub fn create_app(
+    database: FullDatabase,
+    config: FullConfig,
+    hasher: FullHasher,
+    scorer: RiskScorer,
+    signer: FullSigner,
+    mailer: FullMailer,
+    notifier: FullNotifier,
+    limiter: RateLimiter,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    // let cors = Cors::default()
+    //     .allowed_origin("http://localhost:80")
+    //     .allowed_methods(vec!["GET", "POST", "DELETE"])
+    //     .max_age(3600);
+
+    App::new()
+        .wrap_api()
+        // .wrap(cors)
+        .wrap(Logger::default())
+        .app_data(database.clone())
+        .app_data(config.clone())
+        .app_data(hasher)
+        .app_data(signer.clone())
+        .app_data(mailer)
+        .app_data(notifier)
+        .configure(|cfg| configure(cfg, database, config, scorer, signer, limiter))
+        // OpenAPI spec:
+        .with_json_spec_at("/spec/v2")
+        .with_json_spec_v3_at("/spec/v3")
+        .build()
+}
+
+/// Register all API resources.
+pub fn configure(
+    cfg: &mut ServiceConfig,
+    database: FullDatabase,
+    config: FullConfig,
+    scorer: RiskScorer,
+    signer: FullSigner,
+    limiter: RateLimiter,
+) {
+    let authenticated = || {
+        AuthenticationService::new(
+            database.clone(),
+            config.clone(),
+            scorer.clone(),
+            signer.clone(),
+        )
+    };
+    let allowed = RequireRole::permission;
+    let limits = &config.rate_limit;
+    let limited =
+        |name, limit: &RateLimit| RateLimitService::new(name, limit.clone(), limiter.clone());
+
+    cfg.service(
+        resource("/register")
+            .wrap(limited("register", &limits.register))
+            .route(post().to(endpoints::register)),
+    )
+    .service(
+        resource("/login")
+            .wrap(limited("login", &limits.login))
+            .route(post().to(endpoints::add_login)),
+    )
+    .service(
+        resource("/login/magic")
+            .wrap(limited("login", &limits.login))
+            .route(post().to(endpoints::start_magic_login))
+            .route(get().to(endpoints::check_magic_login)),
+    )
+    .service(
+        resource("/login/magic/confirm")
+            .wrap(limited("login", &limits.login))
+            .route(post().to(endpoints::complete_magic_login)),
+    )
+    .service(
+        resource("/login/mfa")
+            .wrap(limited("login", &limits.login))
+            .route(post().to(endpoints::complete_mfa_login)),
+    )
+    .service(
+        resource("/login/passkey/challenge")
+            .wrap(limited("login", &limits.login))
+            .route(post().to(endpoints::start_passkey_login)),
+    )
+    .service(
+        resource("/login/passkey")
+            .wrap(limited("login", &limits.login))
+            .route(post().to(endpoints::complete_passkey_login)),
+    )
+    .service(resource("/oauth/{provider}/start").route(get().to(endpoints::start_oauth)))
+    .service(
+        resource("/oauth/{provider}/link")
+            .wrap(authenticated())
+            .route(get().to(endpoints::link_oauth)),
+    )
+    .service(resource("/oauth/{provider}/callback").route(get().to(endpoints::oauth_callback)))
+    .service(
+        resource("/password/forgot")
+            .wrap(limited("password_reset", &limits.password_reset))
+            .route(post().to(endpoints::forgot_password)),
+    )
+    .service(
+        resource("/password/reset")
+            .wrap(limited("password_reset", &limits.password_reset))
+            .route(post().to(endpoints::reset_password)),
+    )
+    .service(
+        resource("/me")
+            .wrap(authenticated())
+            .route(delete().to(endpoints::delete_account))
+            .route(get().to(endpoints::get_account)),
+    )
+    .service(
+        resource("/me/totp")
+            .wrap(authenticated())
+            .route(post().to(endpoints::start_totp_enrollment))
+            .route(delete().to(endpoints::remove_totp)),
+    )
+    .service(
+        resource("/me/totp/confirm")
+            .wrap(authenticated())
+            .route(post().to(endpoints::confirm_totp_enrollment)),
+    )
+    .service(
+        resource("/me/passkeys")
+            .wrap(authenticated())
+            .route(post().to(endpoints::start_passkey_registration)),
+    )
+    .service(
+        resource("/me/passkeys/confirm")
+            .wrap(authenticated())
+            .route(post().to(endpoints::confirm_passkey_registration)),
+    )
+    .service(
+        resource("/me/password")
+            .wrap(authenticated())
+            .route(post().to(endpoints::change_password)),
+    )
+    .service(
+        resource("/logout")
+            .wrap(authenticated())
+            .route(delete().to(endpoints::logout)),
+    )
+    .service(
+        resource("/sessions")
+            .wrap(authenticated())
+            .route(get().to(endpoints::list_sessions)),
+    )
+    .service(
+        resource("/sessions/current")
+            .wrap(authenticated())
+            .route(delete().to(endpoints::revoke_current_session)),
+    )
+    .service(
+        resource("/sessions/{id}")
+            .wrap(authenticated())
+            .route(delete().to(endpoints::revoke_session)),
+    )
+    .service(
+        resource("/verify")
+            .wrap(limited("verify", &limits.verify))
+            .route(get().to(endpoints::verify_user)),
+    )
+    .service(
+        resource("/verify/resend")
+            .wrap(limited("verify", &limits.verify))
+            .wrap(authenticated())
+            .route(post().to(endpoints::resend_verification)),
+    )
+    .service(
+        resource("/admin/users")
+            .wrap(allowed(Permission::ViewUsers))
+            .wrap(authenticated())
+            .route(get().to(endpoints::list_users)),
+    )
+    // The next two resources have the same path, the method picks the one with the
+    // permission it needs.
+    .service(
+        resource("/admin/users/{id}")
+            .guard(guard::Get())
+            .wrap(allowed(Permission::ViewUsers))
+            .wrap(authenticated())
+            .route(get().to(endpoints::get_user)),
+    )
+    .service(
+        resource("/admin/users/{id}")
+            .guard(guard::Delete())
+            .wrap(allowed(Permission::DeleteUsers))
+            .wrap(authenticated())
+            .route(delete().to(endpoints::delete_user)),
+    )
+    .service(
+        resource("/admin/users/{id}/verify")
+            .wrap(allowed(Permission::VerifyUsers))
+            .wrap(authenticated())
+            .route(post().to(endpoints::force_verify_user)),
+    )
+    .service(
+        resource("/admin/users/{id}/roles")
+            .wrap(allowed(Permission::ManageRoles))
+            .wrap(authenticated())
+            .route(put().to(endpoints::set_user_roles)),
+    )
+    .service(
+        resource("/admin/users/{id}/sessions")
+            .wrap(allowed(Permission::RevokeSessions))
+            .wrap(authenticated())
+            .route(delete().to(endpoints::revoke_user_sessions)),
+    )
+    .service(
+        resource("/admin/users/{id}/disabled")
+            .wrap(allowed(Permission::DisableUsers))
+            .wrap(authenticated())
+            .route(post().to(endpoints::disable_user))
+            .route(delete().to(endpoints::enable_user)),
+    )
+    .service(
+        resource("/authentication/{method}")
+            .wrap(authenticated())
+            .route(delete().to(endpoints::remove_authentication_method))
+            .route(put().to(endpoints::update_authentication_method)),
+    );
+}
@@ -0,0 +1,983 @@
+// The configuration of the service, loaded once at startup.
+//
+// Values are read from a TOML file (`config.toml` or the file in `XILER_CONFIG`)
+// and can be overridden with environment variables named `XILER_<SECTION>_<KEY>`,
+// e.g. `XILER_SCYLLA_PASSWORD` overrides `password` in the `[scylla]` section.
+// Nested tables join their keys as well, `XILER_OAUTH_PROVIDERS_GITHUB_CLIENT_SECRET`
+// overrides `client_secret` in `[oauth.providers.github]`.
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashSet},
+    env, fs,
+    net::ToSocketAddrs,
+};
+
+use lettre::message::Mailbox;
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::{
+    constants::{
+        IDLE_TTL, SESSION_KEY, SITE_BASE_URL, TOTP_AUTHENTICATION, TTL, WEBAUTHN_AUTHENTICATION,
+    },
+    errors::ConfigError,
+    util::{
+        data::{PersistentStorageKind, TemporaryStorageKind},
+        mail::MailerKind,
+        math::is_power_of_two,
+        rate_limit::{RateLimitKey, RateLimitStoreKind},
+    },
+};
+
+const ENV_PREFIX: &str = "XILER_";
+const CONFIG_PATH_ENV: &str = "XILER_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub storage: StorageConfig,
+    pub scylla: ScyllaConfig,
+    pub firefly: FireflyConfig,
+    pub session: SessionConfig,
+    pub site: SiteConfig,
+    pub hashing: HashingConfig,
+    pub risk: RiskConfig,
+    pub tokens: TokenConfig,
+    pub mail: MailConfig,
+    pub smtp: SmtpConfig,
+    pub verification: VerificationConfig,
+    pub password_reset: PasswordResetConfig,
+    pub magic_link: MagicLinkConfig,
+    pub lockout: LockoutConfig,
+    pub mfa: MfaConfig,
+    pub webauthn: WebauthnConfig,
+    pub oauth: OauthConfig,
+    pub rate_limit: RateLimitConfig,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// The address the HTTP server binds to.
+    pub bind: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub persistent: PersistentStorageKind,
+    pub temporary: TemporaryStorageKind,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ScyllaConfig {
+    pub uri: String,
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct FireflyConfig {
+    pub address: String,
+    /// The amount of connections that are opened to Firefly.
+    pub connections: usize,
+    /// The largest reply that is read from Firefly in bytes, longer values can not
+    /// be read. It has to fit the session index of `session.max_sessions` sessions.
+    pub max_buffer: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// The name of the cookie that contains the session token.
+    pub key: String,
+    /// The maximum amount of seconds a session lives, even when it is used.
+    pub ttl: usize,
+    /// The amount of seconds a session lives without being used.
+    pub idle_timeout: usize,
+    /// The amount of sessions a user can have, signing in once more signs out the
+    /// oldest session.
+    pub max_sessions: usize,
+    /// The domain the session cookie is valid for, defaults to the host of the API.
+    pub cookie_domain: Option<String>,
+    /// Only send the session cookie over HTTPS.
+    pub secure: bool,
+    /// When browsers send the session cookie along with cross-site requests.
+    pub same_site: SameSitePolicy,
+}
+
+/// The `SameSite` attribute of the session cookie.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SameSitePolicy {
+    Strict,
+    Lax,
+    None,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SiteConfig {
+    /// The URL of the accounts website, used to build links.
+    pub base_url: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct HashingConfig {
+    /// The amount of memory Argon2 uses, in KiB.
+    pub memory_cost: u32,
+    /// The amount of passes Argon2 makes over the memory.
+    pub time_cost: u32,
+    /// The degree of parallelism.
+    pub lanes: u32,
+    /// A server-side secret that is mixed into every password hash.
+    pub pepper: Option<String>,
+    /// The amount of passwords that are hashed at the same time.
+    pub workers: usize,
+    /// The amount of passwords that may wait for a worker, before requests get rejected.
+    pub queue: usize,
+}
+
+/// How browser sessions that are used by another client are handled. Every part of
+/// the fingerprint that does not match costs its weight, the score of a session
+/// is the share of the total weight that does match.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RiskConfig {
+    pub ip: f64,
+    pub platform_name: f64,
+    pub platform_version: f64,
+    pub platform_details: f64,
+    pub extension: f64,
+    /// Sessions that score at or below this require the user to sign in again.
+    pub step_up_threshold: f64,
+    /// Sessions that score at or below this are removed.
+    pub revoke_threshold: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct TokenConfig {
+    /// The id of the key in `keys` that signs new session tokens.
+    pub signing_key: Option<String>,
+    /// The secrets that session tokens are signed with, by key id. Old keys should
+    /// be kept until the sessions they signed have expired.
+    pub keys: BTreeMap<String, String>,
+    /// Accept the unsigned `s1` browser tokens, while they are migrated. Only meant
+    /// for the upgrade, `s1` tokens are not accepted anymore from the next release.
+    pub accept_s1: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MailConfig {
+    /// How mail is delivered.
+    pub kind: MailerKind,
+    /// The sender of all mail, e.g. `Xiler <no-reply@xiler.net>`.
+    pub from: String,
+    /// The directory the outbox writes mail to, it only keeps mail in memory when
+    /// this is not set.
+    pub outbox: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Upgrade the connection with STARTTLS, only disable this for a local relay.
+    pub starttls: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct VerificationConfig {
+    /// The amount of seconds a verification link can be used.
+    pub ttl: usize,
+    /// The amount of seconds a user has to wait before another link is sent.
+    pub resend_interval: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PasswordResetConfig {
+    /// The amount of seconds a reset link can be used.
+    pub ttl: usize,
+    /// The amount of seconds before another reset link is sent to the same user.
+    pub resend_interval: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MagicLinkConfig {
+    /// Allow users to sign in with a link that is mailed to them.
+    pub enabled: bool,
+    /// The amount of seconds a sign in link can be used.
+    pub ttl: usize,
+    /// The amount of seconds before another sign in link is sent to the same user.
+    pub resend_interval: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct LockoutConfig {
+    /// The amount of seconds a failed sign in is remembered.
+    pub window: usize,
+    /// The amount of failed sign ins before every next attempt has to wait.
+    pub backoff_after: usize,
+    /// The amount of seconds of the first wait, it doubles with every failure.
+    pub backoff_base: usize,
+    pub max_backoff: usize,
+    /// The amount of failed sign ins before the account is locked.
+    pub account_threshold: usize,
+    /// The amount of failed sign ins from one IP address before it is locked.
+    pub ip_threshold: usize,
+    /// The amount of seconds an account or IP address stays locked.
+    pub duration: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MfaConfig {
+    /// The name authenticator apps show for the account.
+    pub issuer: String,
+    /// The amount of seconds a user has to provide the second factor after the password.
+    pub pending_ttl: usize,
+    /// The amount of wrong codes before the user has to sign in again.
+    pub max_attempts: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WebauthnConfig {
+    /// The domain passkeys are bound to, the origin must be on this domain.
+    pub rp_id: String,
+    /// The name authenticators show for the passkey.
+    pub rp_name: String,
+    /// The origin of the website that creates and uses passkeys.
+    pub origin: String,
+    /// The amount of seconds a challenge can be signed.
+    pub challenge_ttl: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct OauthConfig {
+    /// The public URL of the API, providers send users back to
+    /// `<redirect_base_url>/oauth/<provider>/callback`.
+    pub redirect_base_url: String,
+    /// The amount of seconds a user has to sign in at the provider.
+    pub state_ttl: usize,
+    /// The platforms users can sign in with, by the name that is used in the URLs.
+    pub providers: BTreeMap<String, OauthProviderConfig>,
+}
+
+/// An OAuth 2.0 or OpenID Connect provider, e.g. `[oauth.providers.google]`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct OauthProviderConfig {
+    /// The authentication method the account at the provider is stored as, e.g. 1
+    /// for Google and 2 for GitHub.
+    pub method: i16,
+    pub client_id: String,
+    pub client_secret: String,
+    pub authorize_url: String,
+    pub token_url: String,
+    /// The endpoint that returns the claims of the user, for OpenID Connect this is
+    /// the userinfo endpoint.
+    pub userinfo_url: String,
+    pub scopes: Vec<String>,
+    /// The claim with the id of the user at the provider, it never changes.
+    pub subject_claim: String,
+    pub email_claim: String,
+    /// The claim with the name new accounts get, when it is still available.
+    pub username_claim: String,
+    /// The claim that tells if the provider verified the email address.
+    pub email_verified_claim: String,
+    /// Treat every email address of the provider as verified, for providers that
+    /// only share verified addresses.
+    pub trust_email: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Where the token buckets are kept.
+    pub store: RateLimitStoreKind,
+    pub register: RateLimit,
+    /// Shared by every way to sign in.
+    pub login: RateLimit,
+    /// Verifying an email address and sending a new verification link.
+    pub verify: RateLimit,
+    /// Asking for a password reset link and using it.
+    pub password_reset: RateLimit,
+}
+
+/// A limit on the amount of requests a client can make.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RateLimit {
+    /// The amount of requests that can be made at once, 0 turns the limit off.
+    pub requests: u32,
+    /// The amount of seconds it takes to be able to make all requests again.
+    pub per: usize,
+    /// Who the requests are counted for.
+    pub key: RateLimitKey,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:8080".to_string(),
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            persistent: PersistentStorageKind::Scylla,
+            temporary: TemporaryStorageKind::Firefly,
+        }
+    }
+}
+
+impl Default for ScyllaConfig {
+    fn default() -> Self {
+        Self {
+            uri: "0.0.0.0:9042".to_string(),
+            username: "cassandra".to_string(),
+            password: "cassandra".to_string(),
+        }
+    }
+}
+
+impl Default for FireflyConfig {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:46600".to_string(),
+            connections: 8,
+            max_buffer: 8 * 1024,
+        }
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            key: SESSION_KEY.to_string(),
+            ttl: TTL,
+            idle_timeout: IDLE_TTL,
+            max_sessions: 25,
+            cookie_domain: None,
+            secure: true,
+            same_site: SameSitePolicy::Lax,
+        }
+    }
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        Self {
+            base_url: SITE_BASE_URL.to_string(),
+        }
+    }
+}
+
+impl Default for HashingConfig {
+    fn default() -> Self {
+        Self {
+            memory_cost: 19 * 1024,
+            time_cost: 2,
+            lanes: 1,
+            pepper: None,
+            workers: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            queue: 64,
+        }
+    }
+}
+
+impl Default for RiskConfig {
+    fn default() -> Self {
+        Self {
+            ip: 1.0,
+            platform_name: 1.0,
+            platform_version: 1.0,
+            platform_details: 1.0,
+            extension: 1.0,
+            step_up_threshold: 0.8,
+            revoke_threshold: 0.6,
+        }
+    }
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            kind: MailerKind::Smtp,
+            from: "Xiler <no-reply@xiler.net>".to_string(),
+            outbox: None,
+        }
+    }
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 587,
+            username: None,
+            password: None,
+            starttls: true,
+        }
+    }
+}
+
+impl Default for VerificationConfig {
+    fn default() -> Self {
+        Self {
+            ttl: 60 * 60 * 24, // 1 day
+            resend_interval: 60,
+        }
+    }
+}
+
+impl Default for PasswordResetConfig {
+    fn default() -> Self {
+        Self {
+            ttl: 60 * 60, // 1 hour
+            resend_interval: 60,
+        }
+    }
+}
+
+impl Default for MagicLinkConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl: 60 * 10, // 10 minutes
+            resend_interval: 60,
+        }
+    }
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        Self {
+            window: 60 * 15, // 15 minutes
+            backoff_after: 3,
+            backoff_base: 1,
+            max_backoff: 60,
+            account_threshold: 10,
+            ip_threshold: 50,
+            duration: 60 * 15, // 15 minutes
+        }
+    }
+}
+
+impl Default for MfaConfig {
+    fn default() -> Self {
+        Self {
+            issuer: "Xiler".to_string(),
+            pending_ttl: 5 * 60,
+            max_attempts: 5,
+        }
+    }
+}
+
+impl Default for WebauthnConfig {
+    fn default() -> Self {
+        Self {
+            rp_id: "accounts.xiler.net".to_string(),
+            rp_name: "Xiler".to_string(),
+            origin: SITE_BASE_URL.to_string(),
+            challenge_ttl: 5 * 60,
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            store: RateLimitStoreKind::Memory,
+            register: RateLimit {
+                requests: 5,
+                per: 60 * 60, // 1 hour
+                key: RateLimitKey::Ip,
+            },
+            login: RateLimit {
+                requests: 20,
+                per: 60,
+                key: RateLimitKey::Ip,
+            },
+            verify: RateLimit {
+                requests: 10,
+                per: 60,
+                key: RateLimitKey::Ip,
+            },
+            password_reset: RateLimit {
+                requests: 10,
+                per: 15 * 60, // 15 minutes
+                key: RateLimitKey::Ip,
+            },
+        }
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            requests: 10,
+            per: 60,
+            key: RateLimitKey::Ip,
+        }
+    }
+}
+
+impl Default for OauthConfig {
+    fn default() -> Self {
+        Self {
+            redirect_base_url: SITE_BASE_URL.to_string(),
+            state_ttl: 10 * 60,
+            providers: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for OauthProviderConfig {
+    fn default() -> Self {
+        Self {
+            method: 0,
+            client_id: String::new(),
+            client_secret: String::new(),
+            authorize_url: String::new(),
+            token_url: String::new(),
+            userinfo_url: String::new(),
+            scopes: vec![
+                "openid".to_string(),
+                "email".to_string(),
+                "profile".to_string(),
+            ],
+            subject_claim: "sub".to_string(),
+            email_claim: "email".to_string(),
+            username_claim: "preferred_username".to_string(),
+            email_verified_claim: "email_verified".to_string(),
+            trust_email: false,
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration from the configuration file and the environment.
+    /// The default file is optional, a file that is set explicitly must exist.
+    pub fn load() -> Result<Config, ConfigError> {
+        let (path, required) = match env::var(CONFIG_PATH_ENV) {
+            Ok(path) => (path, true),
+            Err(_) => (DEFAULT_CONFIG_PATH.to_string(), false),
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) if !required => String::new(),
+            Err(e) => return Err(ConfigError::Io(format!("{}: {}", path, e))),
+        };
+
+        Self::from_sources(&contents, env::vars())
+    }
+
+    /// Build the configuration from the contents of a TOML file and a set of
+    /// environment variables.
+    pub fn from_sources(
+        contents: &str,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Config, ConfigError> {
+        let file: Table =
+            toml::from_str(contents).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        let mut table = match Value::try_from(Config::default()) {
+            Ok(Value::Table(table)) => table,
+            _ => unreachable!("the default configuration is always a table"),
+        };
+
+        merge(&mut table, file);
+        apply_env_overrides(&mut table, vars)?;
+
+        let config: Config = Value::Table(table)
+            .try_into()
+            .map_err(|e: toml::de::Error| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Make sure that the configuration can be used to run the service.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        fn invalid(message: &str) -> Result<(), ConfigError> {
+            Err(ConfigError::Invalid(message.to_string()))
+        }
+
+        if self.server.bind.to_socket_addrs().is_err() {
+            return invalid("server.bind must be a valid address, e.g. 127.0.0.1:8080");
+        }
+
+        if self.firefly.connections == 0 {
+            return invalid("firefly.connections must be at least 1");
+        }
+
+        // Every session id takes 67 bytes in the index.
+        if self.firefly.max_buffer < 512 + self.session.max_sessions * 67 {
+            return invalid("firefly.max_buffer is too small for session.max_sessions");
+        }
+
+        if self.session.key.is_empty()
+            || !self
+                .session
+                .key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return invalid("session.key must be a non-empty cookie name");
+        }
+
+        if self.session.ttl == 0 {
+            return invalid("session.ttl must be more than 0 seconds");
+        }
+
+        if self.session.idle_timeout == 0 {
+            return invalid("session.idle_timeout must be more than 0 seconds");
+        }
+
+        if self.session.max_sessions == 0 {
+            return invalid("session.max_sessions must be at least 1");
+        }
+
+        if self.session.same_site == SameSitePolicy::None && !self.session.secure {
+            return invalid("session.same_site can only be none when session.secure is enabled");
+        }
+
+        if matches!(&self.session.cookie_domain, Some(domain) if domain.is_empty()) {
+            return invalid("session.cookie_domain must not be empty when it is set");
+        }
+
+        if !self.site.base_url.starts_with("https://") && !self.site.base_url.starts_with("http://")
+        {
+            return invalid("site.base_url must be an http(s) URL");
+        }
+
+        if self.hashing.lanes == 0 || self.hashing.time_cost == 0 {
+            return invalid("hashing.lanes and hashing.time_cost must be at least 1");
+        }
+
+        if self.hashing.memory_cost < 8 * self.hashing.lanes {
+            return invalid("hashing.memory_cost must be at least 8 KiB per lane");
+        }
+
+        if self.hashing.workers == 0 {
+            return invalid("hashing.workers must be at least 1");
+        }
+
+        if matches!(&self.hashing.pepper, Some(pepper) if pepper.is_empty()) {
+            return invalid("hashing.pepper must not be empty when it is set");
+        }
+
+        let risk = &self.risk;
+        let weights = [
+            risk.ip,
+            risk.platform_name,
+            risk.platform_version,
+            risk.platform_details,
+            risk.extension,
+        ];
+        if weights
+            .iter()
+            .any(|weight| !weight.is_finite() || *weight < 0.0)
+        {
+            return invalid("risk weights must be 0 or more");
+        }
+
+        if !(0.0..=1.0).contains(&risk.revoke_threshold)
+            || !(0.0..=1.0).contains(&risk.step_up_threshold)
+        {
+            return invalid("risk thresholds must be between 0 and 1");
+        }
+
+        if risk.revoke_threshold > risk.step_up_threshold {
+            return invalid("risk.revoke_threshold must not be above risk.step_up_threshold");
+        }
+
+        let tokens = &self.tokens;
+        if tokens
+            .keys
+            .keys()
+            .any(|id| id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()))
+        {
+            return invalid("tokens.keys must have alphanumeric ids");
+        }
+
+        if tokens.keys.values().any(|secret| secret.len() < 32) {
+            return invalid("tokens.keys must be at least 32 characters long");
+        }
+
+        match &tokens.signing_key {
+            Some(id) if !tokens.keys.contains_key(id) => {
+                return invalid("tokens.signing_key must be one of tokens.keys");
+            }
+            None if !tokens.keys.is_empty() => {
+                return invalid("tokens.signing_key must be set when there are tokens.keys");
+            }
+            _ => {}
+        }
+
+        if self.mail.from.parse::<Mailbox>().is_err() {
+            return invalid("mail.from must be an email address, e.g. Xiler <no-reply@xiler.net>");
+        }
+
+        if matches!(&self.mail.outbox, Some(outbox) if outbox.is_empty()) {
+            return invalid("mail.outbox must not be empty when it is set");
+        }
+
+        if self.smtp.host.is_empty() || self.smtp.port == 0 {
+            return invalid("smtp.host and smtp.port must be set");
+        }
+
+        if self.smtp.username.is_some() != self.smtp.password.is_some() {
+            return invalid("smtp.username and smtp.password must be set together");
+        }
+
+        if self.verification.ttl == 0 {
+            return invalid("verification.ttl must be more than 0 seconds");
+        }
+
+        if self.password_reset.ttl == 0 {
+            return invalid("password_reset.ttl must be more than 0 seconds");
+        }
+
+        if self.magic_link.ttl == 0 {
+            return invalid("magic_link.ttl must be more than 0 seconds");
+        }
+
+        let lockout = &self.lockout;
+        if lockout.window == 0 || lockout.duration == 0 {
+            return invalid("lockout.window and lockout.duration must be more than 0 seconds");
+        }
+
+        if lockout.account_threshold == 0 || lockout.ip_threshold == 0 {
+            return invalid(
+                "lockout.account_threshold and lockout.ip_threshold must be at least 1",
+            );
+        }
+
+        if lockout.backoff_base > lockout.max_backoff {
+            return invalid("lockout.backoff_base can not be more than lockout.max_backoff");
+        }
+
+        if self.mfa.issuer.is_empty() || self.mfa.issuer.contains(':') {
+            return invalid("mfa.issuer must be set and can not contain a colon");
+        }
+
+        if self.mfa.pending_ttl == 0 || self.mfa.max_attempts == 0 {
+            return invalid("mfa.pending_ttl and mfa.max_attempts must be at least 1");
+        }
+
+        let webauthn = &self.webauthn;
+        let host = webauthn
+            .origin
+            .strip_prefix("https://")
+            .or_else(|| webauthn.origin.strip_prefix("http://"))
+            .map(|host| host.split(':').next().unwrap_or(host));
+        match host {
+            Some(host)
+                if !webauthn.rp_id.is_empty()
+                    && (host == webauthn.rp_id
+                        || host.ends_with(&format!(".{}", webauthn.rp_id))) => {}
+            _ => {
+                return invalid(
+                    "webauthn.origin must be an http(s) origin on the domain of webauthn.rp_id",
+                )
+            }
+        }
+
+        if webauthn.challenge_ttl == 0 {
+            return invalid("webauthn.challenge_ttl must be more than 0 seconds");
+        }
+
+        let oauth = &self.oauth;
+        let is_url = |url: &str| url.starts_with("https://") || url.starts_with("http://");
+        if !is_url(&oauth.redirect_base_url) {
+            return invalid("oauth.redirect_base_url must be an http(s) URL");
+        }
+
+        if oauth.state_ttl == 0 {
+            return invalid("oauth.state_ttl must be more than 0 seconds");
+        }
+
+        let mut methods = HashSet::new();
+        for (name, provider) in &oauth.providers {
+            let invalid = |message: &str| {
+                Err(ConfigError::Invalid(format!(
+                    "oauth.providers.{}.{}",
+                    name, message
+                )))
+            };
+
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return invalid("the name can only contain letters, digits, - and _");
+            }
+
+            if provider.method <= 0
+                || !is_power_of_two(provider.method)
+                || [TOTP_AUTHENTICATION, WEBAUTHN_AUTHENTICATION].contains(&provider.method)
+                || !methods.insert(provider.method)
+            {
+                return invalid("method must be a power of two that is not used by another method");
+            }
+
+            if provider.client_id.is_empty() || provider.subject_claim.is_empty() {
+                return invalid("client_id and subject_claim must be set");
+            }
+
+            if ![
+                &provider.authorize_url,
+                &provider.token_url,
+                &provider.userinfo_url,
+            ]
+            .iter()
+            .all(|url| is_url(url))
+            {
+                return invalid("authorize_url, token_url and userinfo_url must be http(s) URLs");
+            }
+        }
+
+        let rate_limit = &self.rate_limit;
+        for (name, limit) in [
+            ("register", &rate_limit.register),
+            ("login", &rate_limit.login),
+            ("verify", &rate_limit.verify),
+            ("password_reset", &rate_limit.password_reset),
+        ] {
+            if limit.per == 0 {
+                return Err(ConfigError::Invalid(format!(
+                    "rate_limit.{}.per must be more than 0 seconds",
+                    name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Recursively merge the `from` table into the `into` table.
+fn merge(into: &mut Table, from: Table) {
+    for (key, value) in from {
+        match (into.get_mut(&key), value) {
+            (Some(Value::Table(existing)), Value::Table(value)) => merge(existing, value),
+            (_, value) => {
+                into.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Override values with the `XILER_<SECTION>_<KEY>` variables. Nested tables are
+/// reached by joining their keys as well, e.g. `XILER_RATE_LIMIT_LOGIN_REQUESTS`.
+fn apply_env_overrides(
+    table: &mut Table,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<(), ConfigError> {
+    for (name, raw) in vars {
+        let name = match name.strip_prefix(ENV_PREFIX) {
+            Some(name) => name.to_lowercase(),
+            None => continue,
+        };
+
+        let path = match env_path(table, &name, true) {
+            Some(path) => path,
+            None => continue,
+        };
+
+        let (key, parents) = path.split_last().unwrap();
+        let mut values = &mut *table;
+        for parent in parents {
+            values = match values.get_mut(parent) {
+                Some(Value::Table(next)) => next,
+                _ => unreachable!("the path only passes through tables"),
+            };
+        }
+
+        let value = parse_env_value(values.get(key), &raw).ok_or_else(|| {
+            ConfigError::Invalid(format!(
+                "{}{} has an invalid value",
+                ENV_PREFIX,
+                name.to_uppercase()
+            ))
+        })?;
+        values.insert(key.clone(), value);
+    }
+
+    Ok(())
+}
+
+/// The keys that lead to the value a variable overrides, `None` if it does not
+/// belong to the configuration. Keys can contain underscores themselves, so the
+/// name is matched against the keys of each table, the longest first. Values that
+/// are not set, like optional ones or the entries of a map, are added to the
+/// deepest table that matched, but never to the top level. Tables that are not set,
+/// like an OAuth provider, have to be in the file.
+fn env_path(table: &Table, name: &str, top_level: bool) -> Option<Vec<String>> {
+    match table.get(name) {
+        Some(Value::Table(_)) => return None,
+        Some(_) => return Some(vec![name.to_string()]),
+        None => {}
+    }
+
+    let mut keys: Vec<&String> = table.keys().collect();
+    keys.sort_by_key(|key| Reverse(key.len()));
+    let mut nested = false;
+    for key in keys {
+        let rest = match name
+            .strip_prefix(key.as_str())
+            .and_then(|rest| rest.strip_prefix('_'))
+        {
+            Some(rest) => rest,
+            None => continue,
+        };
+        if let Some(Value::Table(values)) = table.get(key) {
+            nested = true;
+            if let Some(mut path) = env_path(values, rest, false) {
+                path.insert(0, key.clone());
+                return Some(path);
+            }
+        }
+    }
+
+    if top_level || nested {
+        None
+    } else {
+        Some(vec![name.to_string()])
+    }
+}
+
+/// Parse an environment variable using the type of the value it overrides.
+fn parse_env_value(existing: Option<&Value>, raw: &str) -> Option<Value> {
+    match existing {
+        Some(Value::String(_)) => Some(Value::String(raw.to_string())),
+        Some(Value::Integer(_)) => raw.parse().ok().map(Value::Integer),
+        Some(Value::Float(_)) => raw.parse().ok().map(Value::Float),
+        Some(Value::Boolean(_)) => raw.parse().ok().map(Value::Boolean),
+        // Optional values that are not set are always strings.
+        _ => Some(Value::String(raw.to_string())),
+    }
+}
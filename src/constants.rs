@@ -1,4 +1,12 @@
 pub const SESSION_KEY: &str = "xiler-session";
 pub const PASSWORD_AUTHENTICATION: i16 = 0;
+pub const TOTP_AUTHENTICATION: i16 = 256;
+pub const WEBAUTHN_AUTHENTICATION: i16 = 512;
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+pub const MAX_PASSWORD_LENGTH: usize = 128;
 pub const TTL: usize = 60 * 60 * 24 * 30; // 30 days
+pub const IDLE_TTL: usize = 60 * 60 * 24 * 7; // 7 days
+pub const BROWSER_SESSION_PREFIX: &str = "s2";
+pub const LEGACY_BROWSER_SESSION_PREFIX: &str = "s1";
+pub const API_SESSION_PREFIX: &str = "a1";
 pub const SITE_BASE_URL: &str = "https://accounts.xiler.net";
@@ -1,15 +1,33 @@
+mod admin;
 mod authentication;
 mod delete;
 mod get;
 mod login;
 mod logout;
+mod oauth;
+mod password;
 mod register;
+mod sessions;
+mod totp;
 mod verify;
+mod webauthn;
 
+pub use admin::{
+    delete_user, disable_user, enable_user, force_verify_user, get_user, list_users,
+    revoke_user_sessions, set_user_roles,
+};
 pub use authentication::{remove_authentication_method, update_authentication_method};
 pub use delete::delete_account;
 pub use get::get_account;
-pub use login::add_login;
+pub use login::{
+    add_login, check_magic_login, complete_magic_login, complete_mfa_login, complete_passkey_login,
+    start_magic_login, start_passkey_login,
+};
 pub use logout::logout;
+pub use oauth::{link_oauth, oauth_callback, start_oauth};
+pub use password::{change_password, forgot_password, reset_password};
 pub use register::register;
-pub use verify::verify_user;
+pub use sessions::{list_sessions, revoke_current_session, revoke_session};
+pub use totp::{confirm_totp_enrollment, remove_totp, start_totp_enrollment};
+pub use verify::{resend_verification, verify_user};
+pub use webauthn::{confirm_passkey_registration, start_passkey_registration};
@@ -0,0 +1,208 @@
+use actix_web::web::{Json, Query};
+use paperclip::actix::{api_v2_operation, Apiv2Schema};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    errors::HttpError,
+    structs::{
+        admin::{AdminUser, UserList},
+        role::{Role, Roles},
+        user::FullUser,
+        Status,
+    },
+    types::FullDatabase,
+    util::{actix::Path, sessions::store},
+};
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 100;
+
+#[derive(Deserialize, Apiv2Schema)]
+pub struct UserSearch {
+    /// Only list users whose username or email contains this, ignoring case.
+    pub search: Option<String>,
+    /// The `next` of the previous page.
+    pub after: Option<String>,
+    /// The amount of users on a page, at most 100.
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, Apiv2Schema)]
+pub struct RoleUpdate {
+    /// Every role the user should have, this replaces their current roles.
+    pub roles: Vec<Role>,
+}
+
+async fn find_user(db: &FullDatabase, id: &str) -> Result<FullUser, HttpError> {
+    let id = Uuid::parse_str(id).map_err(|_| HttpError::NotFound())?;
+
+    db.persistent
+        .get_user_by_id(id)
+        .await?
+        .ok_or(HttpError::NotFound())
+}
+
+/// The user an operator wants to change. Operators change their own account through
+/// `/me`, and can only change users whose roles are below their own, so peers can
+/// not take over each other.
+async fn managed_user(
+    db: &FullDatabase,
+    operator: &FullUser,
+    id: &str,
+) -> Result<FullUser, HttpError> {
+    let user = find_user(db, id).await?;
+
+    if user.id == operator.id {
+        return Err(HttpError::BadRequest(Status {
+            message: "Manage your own account through /me.".to_string(),
+        }));
+    }
+
+    if user.role_set().highest() >= operator.role_set().highest() {
+        return Err(HttpError::Forbidden(Status {
+            message: "You can only manage users with a lower role than your own.".to_string(),
+        }));
+    }
+
+    Ok(user)
+}
+
+/// List users
+#[api_v2_operation]
+pub async fn list_users(
+    db: FullDatabase,
+    query: Query<UserSearch>,
+) -> Result<Json<UserList>, HttpError> {
+    let after = match &query.after {
+        Some(after) => Some(Uuid::parse_str(after).map_err(|_| {
+            HttpError::BadRequest(Status {
+                message: "Invalid page cursor.".to_string(),
+            })
+        })?),
+        None => None,
+    };
+    let search = query
+        .search
+        .as_deref()
+        .map(str::trim)
+        .filter(|search| !search.is_empty())
+        .map(str::to_string);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let page = db.persistent.list_users(search, after, limit).await?;
+
+    Ok(Json(UserList {
+        users: page.users.iter().map(FullUser::to_admin_user).collect(),
+        next: page.next.map(|next| next.to_string()),
+    }))
+}
+
+/// Get a user
+#[api_v2_operation]
+pub async fn get_user(db: FullDatabase, id: Path<String>) -> Result<Json<AdminUser>, HttpError> {
+    Ok(Json(find_user(&db, &id).await?.to_admin_user()))
+}
+
+/// Verify the email of a user
+#[api_v2_operation]
+pub async fn force_verify_user(
+    db: FullDatabase,
+    operator: FullUser,
+    id: Path<String>,
+) -> Result<Json<Status>, HttpError> {
+    let user = managed_user(&db, &operator, &id).await?;
+    db.persistent.verify_user(user.id).await?;
+
+    Ok(Json(Status {
+        message: "Successfully verified the user".to_string(),
+    }))
+}
+
+/// Replace the roles of a user
+#[api_v2_operation]
+pub async fn set_user_roles(
+    db: FullDatabase,
+    operator: FullUser,
+    id: Path<String>,
+    body: Json<RoleUpdate>,
+) -> Result<Json<AdminUser>, HttpError> {
+    let mut user = managed_user(&db, &operator, &id).await?;
+    let roles: Roles = body.roles.iter().copied().collect();
+
+    if roles.highest() > operator.role_set().highest() {
+        return Err(HttpError::Forbidden(Status {
+            message: "You can not give a higher role than your own.".to_string(),
+        }));
+    }
+
+    db.persistent.set_roles(user.id, roles.bits()).await?;
+    user.roles = roles.bits();
+
+    Ok(Json(user.to_admin_user()))
+}
+
+/// Sign out every session of a user
+#[api_v2_operation]
+pub async fn revoke_user_sessions(
+    db: FullDatabase,
+    operator: FullUser,
+    id: Path<String>,
+) -> Result<Json<Status>, HttpError> {
+    let user = managed_user(&db, &operator, &id).await?;
+    store::revoke_all(&db, user.id).await?;
+
+    Ok(Json(Status {
+        message: "Successfully signed out every session of the user".to_string(),
+    }))
+}
+
+/// Disable a user, this signs out every session of the user
+#[api_v2_operation]
+pub async fn disable_user(
+    db: FullDatabase,
+    operator: FullUser,
+    id: Path<String>,
+) -> Result<Json<Status>, HttpError> {
+    let user = managed_user(&db, &operator, &id).await?;
+    db.persistent.set_disabled(user.id, true).await?;
+    store::revoke_all(&db, user.id).await?;
+
+    Ok(Json(Status {
+        message: "Successfully disabled the user".to_string(),
+    }))
+}
+
+/// Enable a disabled user
+#[api_v2_operation]
+pub async fn enable_user(
+    db: FullDatabase,
+    operator: FullUser,
+    id: Path<String>,
+) -> Result<Json<Status>, HttpError> {
+    let user = managed_user(&db, &operator, &id).await?;
+    db.persistent.set_disabled(user.id, false).await?;
+
+    Ok(Json(Status {
+        message: "Successfully enabled the user".to_string(),
+    }))
+}
+
+/// Delete a user
+#[api_v2_operation]
+pub async fn delete_user(
+    db: FullDatabase,
+    operator: FullUser,
+    id: Path<String>,
+) -> Result<Json<Status>, HttpError> {
+    let user = managed_user(&db, &operator, &id).await?;
+    db.persistent.delete_user(user.id).await?;
+    store::revoke_all(&db, user.id).await?;
+
+    Ok(Json(Status {
+        message: "Successfully deleted the user".to_string(),
+    }))
+}
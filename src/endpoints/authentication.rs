@@ -3,10 +3,11 @@ use paperclip::actix::{api_v2_operation, Apiv2Schema};
 use serde::Deserialize;
 
 use crate::{
+    config::Config,
+    constants::{PASSWORD_AUTHENTICATION, TOTP_AUTHENTICATION, WEBAUTHN_AUTHENTICATION},
     errors::HttpError,
     structs::{user::FullUser, Status},
-    traits::PersistentStorageProvider,
-    types::FullDatabase,
+    types::{FullConfig, FullDatabase},
     util::{actix::Path, math::is_power_of_two},
 };
 
@@ -15,9 +16,21 @@ pub struct AuthenticationMethodValue {
     pub value: String,
 }
 
+/// If a method can be used to sign in on its own, a second factor can not.
+fn is_primary(config: &Config, method: i16) -> bool {
+    method == PASSWORD_AUTHENTICATION
+        || method == WEBAUTHN_AUTHENTICATION
+        || config
+            .oauth
+            .providers
+            .values()
+            .any(|provider| provider.method == method)
+}
+
 #[api_v2_operation]
 pub async fn remove_authentication_method(
     db: FullDatabase,
+    config: FullConfig,
     full_user: FullUser,
     method: Path<i16>,
 ) -> Result<Json<Status>, HttpError> {
@@ -25,11 +38,17 @@ pub async fn remove_authentication_method(
         return Err(HttpError::NotFound());
     }
 
-    let persistent = db.persistent.lock().unwrap();
-    let methods = match persistent.get_authentication_methods(full_user.id).await {
-        Ok(methods) => methods,
-        Err(message) => return Err(HttpError::InternalServerError(Status { message })),
-    };
+    // Whoever holds a session could turn off the second factor otherwise.
+    if *method == TOTP_AUTHENTICATION {
+        return Err(HttpError::BadRequest(Status {
+            message: "Use DELETE /me/totp to remove the authenticator app.".to_string(),
+        }));
+    }
+
+    let methods = db
+        .persistent
+        .get_authentication_methods(full_user.id)
+        .await?;
 
     if methods.len() == 1 {
         return Err(HttpError::BadRequest(Status {
@@ -37,16 +56,23 @@ pub async fn remove_authentication_method(
         }));
     }
 
-    if methods.iter().any(|m| *m == *method) {
-        return match persistent
+    if !methods
+        .iter()
+        .any(|other| other != &*method && is_primary(&config, *other))
+    {
+        return Err(HttpError::BadRequest(Status {
+            message: "Cannot remove the last method that can sign in on its own".to_string(),
+        }));
+    }
+
+    if methods.contains(&method) {
+        db.persistent
             .remove_authentication_method(full_user.id, *method)
-            .await
-        {
-            Ok(_) => Ok(Json(Status {
-                message: "Successfully removed authentication method".to_string(),
-            })),
-            Err(message) => Err(HttpError::InternalServerError(Status { message })),
-        };
+            .await?;
+
+        return Ok(Json(Status {
+            message: "Successfully removed authentication method".to_string(),
+        }));
     }
 
     Err(HttpError::NotFound())
@@ -55,26 +81,53 @@ pub async fn remove_authentication_method(
 #[api_v2_operation]
 pub async fn update_authentication_method(
     db: FullDatabase,
+    config: FullConfig,
     full_user: FullUser,
     method: Path<i16>,
     value: Json<AuthenticationMethodValue>,
 ) -> Result<Json<Status>, HttpError> {
+    // The value would be stored as is, instead of as a hash of the password.
+    if *method == PASSWORD_AUTHENTICATION {
+        return Err(HttpError::BadRequest(Status {
+            message: "Use POST /me/password to change your password.".to_string(),
+        }));
+    }
+
+    if *method == TOTP_AUTHENTICATION {
+        return Err(HttpError::BadRequest(Status {
+            message: "Use POST /me/totp to set up an authenticator app.".to_string(),
+        }));
+    }
+
+    if *method == WEBAUTHN_AUTHENTICATION {
+        return Err(HttpError::BadRequest(Status {
+            message: "Use POST /me/passkeys to add a passkey.".to_string(),
+        }));
+    }
+
+    // Anyone could claim an account at the provider otherwise.
+    if let Some((name, _)) = config
+        .oauth
+        .providers
+        .iter()
+        .find(|(_, provider)| provider.method == *method)
+    {
+        return Err(HttpError::BadRequest(Status {
+            message: format!("Use GET /oauth/{}/link to link {}.", name, name),
+        }));
+    }
+
     if !is_power_of_two(*method) {
         return Err(HttpError::BadRequest(Status {
             message: "All authentication methods must be a power of two.".to_string(),
         }));
     }
 
-    let persistent = db.persistent.lock().unwrap();
-    let res = persistent
+    db.persistent
         .update_authentication_method_value(full_user.id, *method, &value.value)
-        .await;
-    drop(persistent);
-
-    match res {
-        Ok(_) => Ok(Json(Status {
-            message: "Successfully updated authentication method".to_string(),
-        })),
-        Err(message) => Err(HttpError::InternalServerError(Status { message })),
-    }
+        .await?;
+
+    Ok(Json(Status {
+        message: "Successfully updated authentication method".to_string(),
+    }))
 }
@@ -1,41 +1,29 @@
-use paperclip::actix::{api_v2_operation, web::Json, Apiv2Schema};
-use serde::Serialize;
+use paperclip::actix::{api_v2_operation, web::Json};
 
 use crate::{
     errors::HttpError,
-    structs::{
-        session::Session,
-        user::{FullUser, User},
-        Status,
+    structs::{user::FullUser, Status},
+    types::{FullConfig, FullDatabase},
+    util::{
+        actix::WithCookie,
+        sessions::{removal_cookie, store},
     },
-    traits::{PersistentStorageProvider, TemporaryStorageProvider},
-    types::FullDatabase,
 };
 
-/// Merge the user with the session details
-#[derive(Serialize, Apiv2Schema)]
-pub struct UserRegistrationResponse {
-    pub user: User,
-    pub session: Session,
-}
-
 /// Delete your account
 #[api_v2_operation]
-pub async fn delete_account(db: FullDatabase, user: FullUser) -> Result<Json<Status>, HttpError> {
-    let persistent = db.persistent.lock().unwrap();
-    let res = persistent.delete_user(user.id).await;
-    drop(persistent);
-
-    let temporary = db.temporary.lock().unwrap();
-    temporary.drop_all(user.id.to_string()).await;
-    drop(temporary);
+pub async fn delete_account(
+    db: FullDatabase,
+    config: FullConfig,
+    user: FullUser,
+) -> Result<WithCookie<Json<Status>>, HttpError> {
+    db.persistent.delete_user(user.id).await?;
+    store::revoke_all(&db, user.id).await?;
 
-    match res {
-        Ok(_) => Ok(Json(Status {
+    Ok(WithCookie(
+        Json(Status {
             message: "success".to_string(),
-        })),
-        Err(e) => Err(HttpError::BadRequest(Status {
-            message: e.to_string(),
-        })),
-    }
+        }),
+        Some(removal_cookie(&config.session)),
+    ))
 }
@@ -1,60 +1,421 @@
-use actix_web::{web::Json, HttpRequest};
-use argon2::verify_encoded;
-use paperclip::actix::api_v2_operation;
+use actix_web::{
+    rt,
+    web::{Json, Query},
+    HttpRequest,
+};
+use paperclip::actix::{api_v2_operation, AcceptedJson, Apiv2Schema};
+use serde::Deserialize;
 
 use crate::{
-    constants::{PASSWORD_AUTHENTICATION, TTL},
+    constants::{PASSWORD_AUTHENTICATION, WEBAUTHN_AUTHENTICATION},
     errors::HttpError,
     structs::{
-        session::Session,
+        mail::Mail,
+        mfa::{MfaChallenge, MfaLogin},
+        session::{Session, SessionKind},
         user::{FullUser, UserLogin},
+        webauthn::{PasskeyAssertion, PasskeyRequestOptions},
         Status,
     },
-    traits::{PersistentStorageProvider, TemporaryStorageProvider},
-    types::FullDatabase,
-    util::sessions::create_browser_session,
+    types::{FullConfig, FullDatabase, FullHasher, FullMailer, FullNotifier, FullSigner},
+    util::{
+        actix::{Either, WithCookie},
+        codes::{self, CodePurpose},
+        hashing::PasswordVerification,
+        lockout::{self, Attempt},
+        mfa,
+        sessions::{create_session, session_cookie, store},
+        webauthn::{self, WebauthnMethod},
+    },
+};
+
+pub(super) type SessionResponse = WithCookie<Json<Session>>;
+type LoginResult = Result<Either<SessionResponse, AcceptedJson<MfaChallenge>>, HttpError>;
+
+/// Sign in links. Opening the link only checks the code, as mail scanners open links
+/// too. The code is used once the browser confirms, and the session is created for
+/// that browser.
+const MAGIC_LINK: CodePurpose = CodePurpose {
+    prefix: "magic-link",
+    path: "/login/magic",
+    ttl: |config| config.magic_link.ttl,
+    resend_interval: |config| config.magic_link.resend_interval,
+    mail: sign_in_mail,
 };
 
-type LoginResult = Result<Json<Session>, HttpError>;
+fn sign_in_mail(user: &FullUser, link: &str, ttl: usize) -> Mail {
+    let minutes = ttl.div_ceil(60);
+
+    Mail {
+        to: user.email.clone(),
+        subject: "Sign in to Xiler".to_string(),
+        body: format!(
+            "Hi {},\n\nSomebody asked to sign in to your Xiler account. Sign in by opening this link in the browser you want to use:\n\n{}\n\nThe link can be used once and expires in {} minute{}. If you did not ask for this, you can ignore this email.",
+            user.username,
+            link,
+            minutes,
+            if minutes == 1 { "" } else { "s" }
+        ),
+    }
+}
+
+#[derive(Deserialize, Apiv2Schema)]
+pub struct MagicLinkRequest {
+    /// The username or email of the account.
+    pub username: String,
+}
+
+#[derive(Deserialize, Apiv2Schema)]
+pub struct MagicLinkLogin {
+    /// The code from the sign in link.
+    pub code: String,
+}
+
+/// Create a session for a user that is signed in, unless their account is disabled.
+pub(super) async fn start_session(
+    db: &FullDatabase,
+    config: &FullConfig,
+    signer: &FullSigner,
+    kind: SessionKind,
+    user: &FullUser,
+    data: &HttpRequest,
+) -> Result<SessionResponse, HttpError> {
+    if user.disabled {
+        return Err(HttpError::Forbidden(Status {
+            message: "This account has been disabled.".to_string(),
+        }));
+    }
+
+    let token = create_session(kind, data, signer)?;
+
+    let ttl = store::start(db, &config.session, signer, &token, kind, user.id, data).await?;
+
+    let cookie = session_cookie(&config.session, kind, token.clone());
 
+    Ok(WithCookie(Json(Session { token, ttl }), cookie))
+}
+
+/// Sign in with a username or email and a password. Users with a second factor get
+/// a token for `/login/mfa` instead of a session. Failed attempts slow down further
+/// attempts on the account and from the IP address, until they are locked.
 #[api_v2_operation]
-pub async fn add_login(db: FullDatabase, body: Json<UserLogin>, data: HttpRequest) -> LoginResult {
+pub async fn add_login(
+    db: FullDatabase,
+    config: FullConfig,
+    hasher: FullHasher,
+    signer: FullSigner,
+    notifier: FullNotifier,
+    body: Json<UserLogin>,
+    data: HttpRequest,
+) -> LoginResult {
     fn no_match() -> LoginResult {
         Err(HttpError::Unauthorized(Status {
             message: "Could not find match.".to_string(),
         }))
     }
-    let persistent = db.persistent.lock().unwrap();
-    let mut user: Option<FullUser> = persistent.get_user_by_username(body.username.clone()).await;
+    let mut user: Option<FullUser> = db
+        .persistent
+        .get_user_by_username(body.username.clone())
+        .await?;
 
     if user.is_none() {
-        user = persistent.get_user_by_email(body.username.clone()).await;
+        user = db
+            .persistent
+            .get_user_by_email(body.username.clone())
+            .await?;
     }
-    drop(persistent);
 
-    if user.is_none() {
-        return no_match();
+    let attempt = Attempt::new(user.as_ref(), &body.username, &data);
+    let reservation = lockout::reserve(&db, &attempt).await?;
+
+    // The reservation is released however the attempt ends.
+    let result: LoginResult = async {
+        let user = match user {
+            Some(user) => user,
+            None => {
+                lockout::fail(&db, &config.lockout, &attempt).await?;
+                return no_match();
+            }
+        };
+
+        let password = match user.authentication.get(&PASSWORD_AUTHENTICATION) {
+            Some(password) => password,
+            None => {
+                return Err(HttpError::BadRequest(Status {
+                    message:
+                        "Password authentication is not a viable authentication for this user."
+                            .to_string(),
+                }))
+            }
+        };
+
+        match hasher.verify(password, &body.password).await? {
+            PasswordVerification::Invalid => {
+                let notifier = notifier.get_ref().as_ref();
+                lockout::fail_user(&db, &config.lockout, &attempt, &user, notifier).await?;
+                return no_match();
+            }
+            PasswordVerification::Valid => {}
+            PasswordVerification::NeedsRehash => {
+                // Upgrade the stored hash to the current parameters, the login
+                // itself should not fail when this does not succeed.
+                match hasher.hash(&body.password).await {
+                    Ok(rehashed) => {
+                        if let Err(e) = db
+                            .persistent
+                            .update_authentication_method_value(
+                                user.id,
+                                PASSWORD_AUTHENTICATION,
+                                &rehashed,
+                            )
+                            .await
+                        {
+                            log::warn!("Could not rehash password of {}: {}", user.id, e);
+                        }
+                    }
+                    Err(e) => log::warn!("Could not rehash password of {}: {}", user.id, e),
+                }
+            }
+        }
+
+        // The failures are forgotten once the second factor was provided as well.
+        if !mfa::methods(&user).is_empty() {
+            let challenge = mfa::start(&db, &config.mfa, &user, body.session).await?;
+            return Ok(Either::Right(AcceptedJson(challenge)));
+        }
+
+        lockout::clear(&db, user.id).await?;
+        let session = start_session(&db, &config, &signer, body.session, &user, &data).await?;
+        Ok(Either::Left(session))
     }
+    .await;
 
-    let user = user.unwrap();
-    let password = match user.authentication.get(&PASSWORD_AUTHENTICATION) {
-        Some(password) => password,
+    lockout::release(&db, reservation).await?;
+    result
+}
+
+/// Finish signing in with a second factor. Wrong codes count as failed sign ins
+/// of the account, like wrong passwords.
+#[api_v2_operation]
+pub async fn complete_mfa_login(
+    db: FullDatabase,
+    config: FullConfig,
+    signer: FullSigner,
+    notifier: FullNotifier,
+    body: Json<MfaLogin>,
+    data: HttpRequest,
+) -> Result<SessionResponse, HttpError> {
+    fn expired() -> Result<SessionResponse, HttpError> {
+        Err(HttpError::Unauthorized(Status {
+            message: "This sign in has expired, please sign in again.".to_string(),
+        }))
+    }
+
+    let user_id = match mfa::get(&db, &body.mfa_token).await? {
+        Some(pending) => pending.user_id,
+        None => return expired(),
+    };
+
+    let user = match db.persistent.get_user_by_id(user_id).await? {
+        Some(user) => user,
         None => {
-            return Err(HttpError::BadRequest(Status {
-                message: "Password authentication is not a viable authentication for this user."
-                    .to_string(),
-            }))
+            mfa::finish(&db, &body.mfa_token).await?;
+            return Err(HttpError::Unauthorized(Status {
+                message: "User does not exist anymore.".to_string(),
+            }));
         }
     };
 
-    if !verify_encoded(&password, body.password.as_bytes()).unwrap() {
-        return no_match();
+    let attempt = Attempt::new(Some(&user), &user.username, &data);
+    let reservation = lockout::reserve(&db, &attempt).await?;
+
+    // The reservation is released however the attempt ends.
+    let result = async {
+        // Read again now that no other code of the account is being checked.
+        let pending = match mfa::get(&db, &body.mfa_token).await? {
+            Some(pending) => pending,
+            None => return expired(),
+        };
+
+        if !mfa::check_code(&db, &user, &body.code).await? {
+            mfa::fail(&db, &config.mfa, &body.mfa_token, pending).await?;
+            let notifier = notifier.get_ref().as_ref();
+            lockout::fail_user(&db, &config.lockout, &attempt, &user, notifier).await?;
+            return Err(HttpError::Unauthorized(Status {
+                message: "Invalid code.".to_string(),
+            }));
+        }
+
+        mfa::finish(&db, &body.mfa_token).await?;
+        lockout::clear(&db, user.id).await?;
+        start_session(&db, &config, &signer, pending.session, &user, &data).await
+    }
+    .await;
+
+    lockout::release(&db, reservation).await?;
+    result
+}
+
+/// Start signing in with a passkey, the browser signs the returned challenge.
+#[api_v2_operation]
+pub async fn start_passkey_login(
+    db: FullDatabase,
+    config: FullConfig,
+) -> Result<Json<PasskeyRequestOptions>, HttpError> {
+    let options = webauthn::start_login(&db, &config.webauthn).await?;
+
+    Ok(Json(options))
+}
+
+/// Sign in with a challenge that was signed with a passkey. The authenticator
+/// verified the user, so no second factor is asked. Adding a passkey requires the
+/// password or a second factor, and a password reset removes them.
+#[api_v2_operation]
+pub async fn complete_passkey_login(
+    db: FullDatabase,
+    config: FullConfig,
+    signer: FullSigner,
+    body: Json<PasskeyAssertion>,
+    data: HttpRequest,
+) -> Result<SessionResponse, HttpError> {
+    fn no_match() -> HttpError {
+        HttpError::Unauthorized(Status {
+            message: "Could not find match.".to_string(),
+        })
+    }
+
+    let client_data_json = webauthn::decode(&body.client_data_json)?;
+    let challenge =
+        webauthn::client_challenge(&config.webauthn, &client_data_json, "webauthn.get")?;
+    if !webauthn::take_challenge(&db, &challenge, None).await? {
+        return Err(HttpError::Unauthorized(Status {
+            message: "This sign in has expired, please sign in again.".to_string(),
+        }));
+    }
+
+    let user_id = webauthn::user_of_handle(&body.user_handle)?;
+    let user = db
+        .persistent
+        .get_user_by_id(user_id)
+        .await?
+        .ok_or_else(no_match)?;
+
+    let mut method = WebauthnMethod::of(&user)?;
+    let passkey = method.find_mut(&body.id).ok_or_else(no_match)?;
+    passkey.sign_count =
+        webauthn::verify_assertion(&config.webauthn, passkey, &client_data_json, &body)?;
+
+    db.persistent
+        .update_authentication_method_value(user.id, WEBAUTHN_AUTHENTICATION, &method.to_value()?)
+        .await?;
+
+    start_session(&db, &config, &signer, body.session, &user, &data).await
+}
+
+/// Mail a sign in link to the owner of an account. The response is the same whether
+/// the account exists or not.
+#[api_v2_operation]
+pub async fn start_magic_login(
+    db: FullDatabase,
+    config: FullConfig,
+    mailer: FullMailer,
+    body: Json<MagicLinkRequest>,
+) -> Result<AcceptedJson<Status>, HttpError> {
+    if !config.magic_link.enabled {
+        return Err(HttpError::NotFound());
     }
 
-    let token = create_browser_session(data)?;
+    let mut user: Option<FullUser> = db
+        .persistent
+        .get_user_by_username(body.username.clone())
+        .await?;
+
+    if user.is_none() {
+        user = db
+            .persistent
+            .get_user_by_email(body.username.clone())
+            .await?;
+    }
+
+    if let Some(user) = user {
+        // Sent after responding and failures are logged instead of returned, the
+        // response time or an error would reveal the account.
+        let mailer = mailer.get_ref().clone();
+        rt::spawn(async move {
+            if let Err(e) =
+                codes::send_link(&db, mailer.as_ref(), &config, &MAGIC_LINK, &user).await
+            {
+                log::warn!("Could not send the sign in email of {}: {}", user.id, e);
+            }
+        });
+    }
 
-    let temporary = db.temporary.lock().unwrap();
-    temporary.set(token.clone(), user.id.to_string()).await;
+    Ok(AcceptedJson(Status {
+        message: "If the account exists, a sign in link has been sent to its email address."
+            .to_string(),
+    }))
+}
+
+/// Check the code of a sign in link that was opened, this does not use it up. Mail
+/// scanners open links as well, the browser signs in with `/login/magic/confirm`.
+#[api_v2_operation]
+pub async fn check_magic_login(
+    db: FullDatabase,
+    config: FullConfig,
+    query: Query<MagicLinkLogin>,
+) -> Result<Json<Status>, HttpError> {
+    if !config.magic_link.enabled {
+        return Err(HttpError::NotFound());
+    }
+
+    if codes::peek(&db, &MAGIC_LINK, &query.code).await?.is_none() {
+        return Err(HttpError::Unauthorized(Status {
+            message: "Invalid or expired sign in link.".to_string(),
+        }));
+    }
+
+    Ok(Json(Status {
+        message: "Confirm to sign in with this link.".to_string(),
+    }))
+}
+
+/// Sign in with the code from a sign in link. The browser session is created for
+/// the browser that confirms, users with a second factor get a token for
+/// `/login/mfa` instead.
+#[api_v2_operation]
+pub async fn complete_magic_login(
+    db: FullDatabase,
+    config: FullConfig,
+    signer: FullSigner,
+    body: Json<MagicLinkLogin>,
+    data: HttpRequest,
+) -> LoginResult {
+    fn invalid() -> LoginResult {
+        Err(HttpError::Unauthorized(Status {
+            message: "Invalid or expired sign in link.".to_string(),
+        }))
+    }
+
+    if !config.magic_link.enabled {
+        return Err(HttpError::NotFound());
+    }
+
+    let user_id = match codes::redeem(&db, &MAGIC_LINK, &body.code).await? {
+        Some(user_id) => user_id,
+        None => return invalid(),
+    };
+
+    let user = match db.persistent.get_user_by_id(user_id).await? {
+        Some(user) => user,
+        None => return invalid(),
+    };
+
+    // The link only proves access to the mailbox, like a password.
+    if !mfa::methods(&user).is_empty() {
+        let challenge = mfa::start(&db, &config.mfa, &user, SessionKind::Browser).await?;
+        return Ok(Either::Right(AcceptedJson(challenge)));
+    }
 
-    Ok(Json(Session { token, ttl: TTL }))
+    let session = start_session(&db, &config, &signer, SessionKind::Browser, &user, &data).await?;
+    Ok(Either::Left(session))
 }
@@ -4,23 +4,25 @@ use paperclip::actix::api_v2_operation;
 use crate::{
     errors::HttpError,
     structs::{user::FullUser, Status},
-    traits::TemporaryStorageProvider,
-    types::FullDatabase,
+    types::{FullConfig, FullDatabase},
+    util::{
+        actix::WithCookie,
+        sessions::{removal_cookie, store},
+    },
 };
 
 #[api_v2_operation]
-pub async fn logout(db: FullDatabase, full_user: FullUser) -> Result<Json<Status>, HttpError> {
-    let temp = db.temporary.lock().unwrap();
-    let success = temp.drop_all(full_user.id.to_string()).await;
-    drop(temp);
+pub async fn logout(
+    db: FullDatabase,
+    config: FullConfig,
+    full_user: FullUser,
+) -> Result<WithCookie<Json<Status>>, HttpError> {
+    store::revoke_all(&db, full_user.id).await?;
 
-    if success {
-        return Ok(Json(Status {
+    Ok(WithCookie(
+        Json(Status {
             message: "Successfully logged out".to_string(),
-        }));
-    }
-
-    Err(HttpError::InternalServerError(Status {
-        message: "Failed to log out".to_string(),
-    }))
+        }),
+        Some(removal_cookie(&config.session)),
+    ))
 }
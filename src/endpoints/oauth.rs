@@ -0,0 +1,261 @@
+use actix_web::{
+    web::{Json, Query},
+    HttpRequest,
+};
+use chrono::{Duration, Utc};
+use paperclip::actix::{api_v2_operation, AcceptedJson, Apiv2Schema};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::login::{start_session, SessionResponse};
+use crate::{
+    config::{Config, OauthProviderConfig},
+    errors::HttpError,
+    structs::{mfa::MfaChallenge, session::SessionKind, user::FullUser, Status},
+    types::{FullConfig, FullDatabase, FullSigner},
+    util::{
+        actix::{Either, Path, Redirect, WithCookie},
+        mfa,
+        oauth::{self, Identity},
+        random::random_string,
+        Database,
+    },
+};
+
+const MAX_USERNAME_LENGTH: usize = 64;
+
+#[derive(Deserialize, Apiv2Schema)]
+pub struct OauthStart {
+    /// The kind of session that is created when the user signs in.
+    #[serde(default)]
+    pub session: SessionKind,
+}
+
+#[derive(Deserialize, Apiv2Schema)]
+pub struct OauthCallback {
+    pub state: String,
+    pub code: Option<String>,
+    /// Set by the provider when the user did not allow the sign in.
+    pub error: Option<String>,
+}
+
+fn provider<'a>(config: &'a Config, name: &str) -> Result<&'a OauthProviderConfig, HttpError> {
+    config
+        .oauth
+        .providers
+        .get(name)
+        .ok_or(HttpError::NotFound())
+}
+
+/// Sign in with another platform, this sends the user to the provider.
+#[api_v2_operation]
+pub async fn start_oauth(
+    db: FullDatabase,
+    config: FullConfig,
+    name: Path<String>,
+    query: Query<OauthStart>,
+) -> Result<WithCookie<Redirect>, HttpError> {
+    let provider = provider(&config, &name)?;
+    let authorization = oauth::start(
+        &db,
+        &config.session,
+        &config.oauth,
+        &name,
+        provider,
+        None,
+        query.session,
+    )
+    .await?;
+
+    Ok(WithCookie(
+        Redirect(authorization.url),
+        Some(authorization.cookie),
+    ))
+}
+
+/// Link another platform to the account, this sends the user to the provider.
+#[api_v2_operation]
+pub async fn link_oauth(
+    db: FullDatabase,
+    config: FullConfig,
+    full_user: FullUser,
+    name: Path<String>,
+) -> Result<WithCookie<Redirect>, HttpError> {
+    let provider = provider(&config, &name)?;
+    if full_user.authentication.contains_key(&provider.method) {
+        return Err(HttpError::BadRequest(Status {
+            message: format!("{} is already linked to your account.", *name),
+        }));
+    }
+
+    let authorization = oauth::start(
+        &db,
+        &config.session,
+        &config.oauth,
+        &name,
+        provider,
+        Some(full_user.id),
+        SessionKind::Browser,
+    )
+    .await?;
+
+    Ok(WithCookie(
+        Redirect(authorization.url),
+        Some(authorization.cookie),
+    ))
+}
+
+/// Where the provider sends the user back to. This links the platform when the
+/// sign in was started from an account, otherwise it signs the user in and
+/// creates an account for users that are new. Users with a second factor get a
+/// challenge instead of a session, like with `/login`.
+#[api_v2_operation]
+pub async fn oauth_callback(
+    db: FullDatabase,
+    config: FullConfig,
+    signer: FullSigner,
+    name: Path<String>,
+    query: Query<OauthCallback>,
+    data: HttpRequest,
+) -> Result<Either<Either<SessionResponse, AcceptedJson<MfaChallenge>>, Json<Status>>, HttpError> {
+    let provider = provider(&config, &name)?;
+
+    let pending = match oauth::take(&db, &query.state).await? {
+        Some(pending) if pending.provider == *name => pending,
+        _ => {
+            return Err(HttpError::Unauthorized(Status {
+                message: "This sign in has expired, please try again.".to_string(),
+            }))
+        }
+    };
+
+    if !oauth::is_same_browser(&config.session, &pending, &data) {
+        return Err(HttpError::Unauthorized(Status {
+            message: "This sign in was started in another browser.".to_string(),
+        }));
+    }
+
+    let code = match (&query.code, &query.error) {
+        (Some(code), None) => code,
+        _ => {
+            return Err(HttpError::Unauthorized(Status {
+                message: format!("The sign in was cancelled at {}.", *name),
+            }))
+        }
+    };
+
+    let identity = oauth::identify(&config.oauth, &name, provider, code, &pending.verifier).await?;
+    let linked = db
+        .persistent
+        .get_user_by_authentication(provider.method, identity.subject.clone())
+        .await?;
+
+    if let Some(user_id) = pending.user_id {
+        if matches!(&linked, Some(linked) if linked.id != user_id) {
+            return Err(HttpError::BadRequest(Status {
+                message: format!("This {} account is linked to another user.", *name),
+            }));
+        }
+
+        db.persistent
+            .update_authentication_method_value(user_id, provider.method, &identity.subject)
+            .await?;
+
+        return Ok(Either::Right(Json(Status {
+            message: format!("Successfully linked {}", *name),
+        })));
+    }
+
+    let user = match linked {
+        Some(user) => user,
+        None => sign_up(&db, &name, provider, identity).await?,
+    };
+
+    if !mfa::methods(&user).is_empty() {
+        let challenge = mfa::start(&db, &config.mfa, &user, pending.session).await?;
+        return Ok(Either::Left(Either::Right(AcceptedJson(challenge))));
+    }
+
+    let session = start_session(&db, &config, &signer, pending.session, &user, &data).await?;
+    Ok(Either::Left(Either::Left(session)))
+}
+
+/// Create an account for a user that signs in with a platform for the first time.
+async fn sign_up(
+    db: &Database,
+    name: &str,
+    provider: &OauthProviderConfig,
+    identity: Identity,
+) -> Result<FullUser, HttpError> {
+    let email = match identity.email {
+        Some(email) if email.len() <= 64 => email,
+        _ => {
+            return Err(HttpError::BadRequest(Status {
+                message: format!("{} did not share a usable email address.", name),
+            }))
+        }
+    };
+
+    // The address would be taken from its owner by whoever typed it in at the
+    // provider.
+    if !identity.email_verified {
+        return Err(HttpError::BadRequest(Status {
+            message: format!(
+                "{} did not verify your email address, verify it there or register instead.",
+                name
+            ),
+        }));
+    }
+
+    // The account is not linked on its own, as whoever controls the account at the
+    // provider would be able to sign in to it.
+    if db.persistent.does_email_exist(email.clone()).await? {
+        return Err(HttpError::BadRequest(Status {
+            message: format!(
+                "An account with this email address already exists, sign in and link {} to it instead.",
+                name
+            ),
+        }));
+    }
+
+    let base = identity
+        .username
+        .or_else(|| email.split('@').next().map(str::to_string))
+        .unwrap_or_default();
+    let username = available_username(db, base.trim()).await?;
+
+    let full_user = FullUser {
+        id: Uuid::new_v4(),
+        username,
+        email,
+        created_at: Duration::seconds(Utc::now().timestamp()),
+        roles: 0,
+        authentication: [(provider.method, identity.subject)].into_iter().collect(),
+        verification_token: None,
+        disabled: false,
+    };
+    db.persistent.register_user(full_user.clone()).await?;
+
+    Ok(full_user)
+}
+
+/// The name of the user at the provider, with a random suffix when it is taken.
+async fn available_username(db: &Database, base: &str) -> Result<String, HttpError> {
+    let base: String = base.chars().take(MAX_USERNAME_LENGTH - 5).collect();
+    let base = if base.is_empty() { "user" } else { &base };
+
+    if !db.persistent.does_username_exist(base.to_string()).await? {
+        return Ok(base.to_string());
+    }
+
+    for _ in 0..5 {
+        let username = format!("{}-{}", base, random_string(4).to_lowercase());
+        if !db.persistent.does_username_exist(username.clone()).await? {
+            return Ok(username);
+        }
+    }
+
+    Err(HttpError::BadRequest(Status {
+        message: "Could not find an available username, please register instead.".to_string(),
+    }))
+}
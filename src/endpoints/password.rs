@@ -0,0 +1,215 @@
+use actix_web::{rt, web::Json, HttpRequest};
+use paperclip::actix::{api_v2_operation, AcceptedJson, Apiv2Schema};
+use serde::Deserialize;
+
+use crate::{
+    constants::{PASSWORD_AUTHENTICATION, WEBAUTHN_AUTHENTICATION},
+    errors::HttpError,
+    structs::{mail::Mail, session::CurrentSession, user::FullUser, Status},
+    types::{FullConfig, FullDatabase, FullHasher, FullMailer, FullNotifier},
+    util::{
+        codes::{self, CodePurpose},
+        hashing::PasswordVerification,
+        lockout::{self, Attempt},
+        sessions::store,
+        validation,
+    },
+};
+
+/// Password reset links, a reset also lifts a lockout of the account.
+const PASSWORD_RESET: CodePurpose = CodePurpose {
+    prefix: "password-reset",
+    path: "/password/reset",
+    ttl: |config| config.password_reset.ttl,
+    resend_interval: |config| config.password_reset.resend_interval,
+    mail: reset_mail,
+};
+
+fn reset_mail(user: &FullUser, link: &str, ttl: usize) -> Mail {
+    let minutes = ttl.div_ceil(60);
+
+    Mail {
+        to: user.email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hi {},\n\nSomebody asked to reset the password of your Xiler account. Choose a new password by opening this link:\n\n{}\n\nThe link expires in {} minute{}. If you did not ask for this, you can ignore this email, your password stays the same.",
+            user.username,
+            link,
+            minutes,
+            if minutes == 1 { "" } else { "s" }
+        ),
+    }
+}
+
+#[derive(Deserialize, Apiv2Schema)]
+pub struct ForgotPassword {
+    /// The username or email of the account.
+    pub username: String,
+}
+
+#[derive(Deserialize, Apiv2Schema)]
+pub struct ResetPassword {
+    /// The code from the reset link.
+    pub code: String,
+    /// The new password.
+    pub password: String,
+}
+
+#[derive(Deserialize, Apiv2Schema)]
+pub struct ChangePassword {
+    pub current_password: String,
+    pub new_password: String,
+    /// Sign out every other session of the user.
+    #[serde(default)]
+    pub sign_out_others: bool,
+}
+
+/// Mail a password reset link to the owner of an account. The response is the same
+/// whether the account exists or not.
+#[api_v2_operation]
+pub async fn forgot_password(
+    db: FullDatabase,
+    config: FullConfig,
+    mailer: FullMailer,
+    body: Json<ForgotPassword>,
+) -> Result<AcceptedJson<Status>, HttpError> {
+    let mut user: Option<FullUser> = db
+        .persistent
+        .get_user_by_username(body.username.clone())
+        .await?;
+
+    if user.is_none() {
+        user = db
+            .persistent
+            .get_user_by_email(body.username.clone())
+            .await?;
+    }
+
+    if let Some(user) = user {
+        // Sent after responding and failures are logged instead of returned, the
+        // response time or an error would reveal the account.
+        let mailer = mailer.get_ref().clone();
+        rt::spawn(async move {
+            if let Err(e) =
+                codes::send_link(&db, mailer.as_ref(), &config, &PASSWORD_RESET, &user).await
+            {
+                log::warn!(
+                    "Could not send the password reset email of {}: {}",
+                    user.id,
+                    e
+                );
+            }
+        });
+    }
+
+    Ok(AcceptedJson(Status {
+        message: "If the account exists, a reset link has been sent to its email address."
+            .to_string(),
+    }))
+}
+
+/// Set a new password with the code from a reset link, this signs out every session,
+/// removes the passkeys and lifts a lockout of the account.
+#[api_v2_operation]
+pub async fn reset_password(
+    db: FullDatabase,
+    hasher: FullHasher,
+    body: Json<ResetPassword>,
+) -> Result<Json<Status>, HttpError> {
+    fn invalid() -> Result<Json<Status>, HttpError> {
+        Err(HttpError::Unauthorized(Status {
+            message: "Invalid or expired reset code.".to_string(),
+        }))
+    }
+
+    // Checked first, so a mistake does not use up the code.
+    validation::password_length(&body.password)?;
+
+    let user_id = match codes::redeem(&db, &PASSWORD_RESET, &body.code).await? {
+        Some(user_id) => user_id,
+        None => return invalid(),
+    };
+
+    let user = match db.persistent.get_user_by_id(user_id).await? {
+        Some(user) => user,
+        None => return invalid(),
+    };
+
+    let password = hasher.hash(&body.password).await?;
+    db.persistent
+        .update_authentication_method_value(user_id, PASSWORD_AUTHENTICATION, &password)
+        .await?;
+
+    // A passkey signs in without the password, one added by someone else would
+    // outlast the reset.
+    if user.authentication.contains_key(&WEBAUTHN_AUTHENTICATION) {
+        db.persistent
+            .remove_authentication_method(user_id, WEBAUTHN_AUTHENTICATION)
+            .await?;
+    }
+
+    store::revoke_all(&db, user_id).await?;
+    lockout::clear(&db, user_id).await?;
+
+    Ok(Json(Status {
+        message: "Password changed, please sign in again.".to_string(),
+    }))
+}
+
+/// Change the password of the current user, this requires the current password. A
+/// wrong password counts as a failed sign in of the account.
+#[allow(clippy::too_many_arguments)]
+#[api_v2_operation]
+pub async fn change_password(
+    db: FullDatabase,
+    config: FullConfig,
+    hasher: FullHasher,
+    notifier: FullNotifier,
+    full_user: FullUser,
+    current: CurrentSession,
+    body: Json<ChangePassword>,
+    data: HttpRequest,
+) -> Result<Json<Status>, HttpError> {
+    let password = match full_user.authentication.get(&PASSWORD_AUTHENTICATION) {
+        Some(password) => password,
+        None => {
+            return Err(HttpError::BadRequest(Status {
+                message: "Password authentication is not a viable authentication for this user."
+                    .to_string(),
+            }))
+        }
+    };
+
+    let attempt = Attempt::new(Some(&full_user), &full_user.username, &data);
+    let reservation = lockout::reserve(&db, &attempt).await?;
+
+    // The reservation is released however the attempt ends.
+    let result = async {
+        if hasher.verify(password, &body.current_password).await? == PasswordVerification::Invalid {
+            let notifier = notifier.get_ref().as_ref();
+            lockout::fail_user(&db, &config.lockout, &attempt, &full_user, notifier).await?;
+            return Err(HttpError::Unauthorized(Status {
+                message: "The current password is not correct.".to_string(),
+            }));
+        }
+
+        validation::password_length(&body.new_password)?;
+
+        let password = hasher.hash(&body.new_password).await?;
+        db.persistent
+            .update_authentication_method_value(full_user.id, PASSWORD_AUTHENTICATION, &password)
+            .await?;
+
+        if body.sign_out_others {
+            store::revoke_others(&db, full_user.id, &current.id).await?;
+        }
+
+        Ok(Json(Status {
+            message: "Password changed".to_string(),
+        }))
+    }
+    .await;
+
+    lockout::release(&db, reservation).await?;
+    result
+}
@@ -6,17 +6,22 @@ use paperclip::actix::{api_v2_operation, web::Json, Apiv2Schema, CreatedJson};
 use serde::Serialize;
 use uuid::Uuid;
 
+use super::verify::VERIFICATION;
 use crate::{
-    constants::TTL,
     errors::HttpError,
     structs::{
         session::Session,
         user::{FullUser, User, UserRegistration},
         Status,
     },
-    traits::{PersistentStorageProvider, TemporaryStorageProvider},
-    types::FullDatabase,
-    util::{hashing::argon2_hash, random::random_string, sessions::create_browser_session},
+    types::{FullConfig, FullDatabase, FullHasher, FullMailer, FullSigner},
+    util::{
+        actix::WithCookie,
+        codes,
+        random::random_string,
+        sessions::{create_session, session_cookie, store},
+        validation,
+    },
 };
 
 /// Merge the user with the session details
@@ -31,23 +36,28 @@ pub struct UserRegistrationResponse {
 #[api_v2_operation]
 pub async fn register(
     db: FullDatabase,
+    config: FullConfig,
+    hasher: FullHasher,
+    signer: FullSigner,
+    mailer: FullMailer,
     body: Json<UserRegistration>,
     data: HttpRequest,
-) -> Result<CreatedJson<UserRegistrationResponse>, HttpError> {
-    if body.username.is_empty() || body.email.is_empty() || body.password.len() < 8 {
+) -> Result<WithCookie<CreatedJson<UserRegistrationResponse>>, HttpError> {
+    if body.username.is_empty() || body.email.is_empty() {
         return Err(HttpError::BadRequest(Status {
-            message: "Username and email are required. Your password length must also be more than 8. (are you messing with the API? The checks should be handled by the frontend and a basic SHA hash should also be performed there?)".to_string(),
+            message: "Username and email are required.".to_string(),
         }));
-    } else if body.username.len() > 64 || body.email.len() > 64 || body.password.len() > 128 {
+    } else if body.username.len() > 64 || body.email.len() > 64 {
         return Err(HttpError::BadRequest(Status {
-            message: "Username, email and password must be less than 64, 64 and 128 characters respectively.".to_string(),
+            message: "Username and email must be less than 64 characters.".to_string(),
         }));
     }
+    validation::password_length(&body.password)?;
 
     let created_at = Duration::seconds(Utc::now().timestamp());
     let id = Uuid::new_v4();
     let mut authentication = HashMap::new();
-    let password = argon2_hash(&body.password);
+    let password = hasher.hash(&body.password).await?;
     authentication.insert(0, password);
 
     let full_user = FullUser {
@@ -58,24 +68,36 @@ pub async fn register(
         roles: 0,
         authentication,
         verification_token: Some(random_string(64)),
+        disabled: false,
     };
 
-    let persistent = db.persistent.lock().unwrap();
-    let res = persistent.register_user(full_user.clone()).await;
-    drop(persistent);
-    if let Err(e) = res {
-        if e.contains("Failed") {
-            return Err(HttpError::InternalServerError(Status { message: e }));
-        }
-        return Err(HttpError::BadRequest(Status { message: e }));
+    db.persistent.register_user(full_user.clone()).await?;
+
+    // The user can ask for another link, so registering does not fail on this.
+    let mailer = mailer.get_ref().as_ref();
+    if let Err(e) = codes::send_link(&db, mailer, &config, &VERIFICATION, &full_user).await {
+        log::warn!("Could not send the verification email of {}: {}", id, e);
     }
 
-    let token = create_browser_session(data)?;
-    let temporary = db.temporary.lock().unwrap();
-    temporary.set(token.clone(), id.to_string()).await;
+    let token = create_session(body.session, &data, &signer)?;
+    let ttl = store::start(
+        &db,
+        &config.session,
+        &signer,
+        &token,
+        body.session,
+        id,
+        &data,
+    )
+    .await?;
+
+    let cookie = session_cookie(&config.session, body.session, token.clone());
 
-    Ok(CreatedJson(UserRegistrationResponse {
-        user: full_user.to_user(),
-        session: Session { token, ttl: TTL },
-    }))
+    Ok(WithCookie(
+        CreatedJson(UserRegistrationResponse {
+            user: full_user.to_user(),
+            session: Session { token, ttl },
+        }),
+        cookie,
+    ))
 }
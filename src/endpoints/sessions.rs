@@ -0,0 +1,83 @@
+use actix_web::web::Json;
+use paperclip::actix::api_v2_operation;
+
+use crate::{
+    errors::HttpError,
+    structs::{
+        session::{ActiveSession, CurrentSession},
+        user::FullUser,
+        Status,
+    },
+    types::{FullConfig, FullDatabase},
+    util::{
+        actix::{Path, WithCookie},
+        sessions::{removal_cookie, store},
+    },
+};
+
+/// List your active sessions
+#[api_v2_operation]
+pub async fn list_sessions(
+    db: FullDatabase,
+    user: FullUser,
+    current: CurrentSession,
+) -> Result<Json<Vec<ActiveSession>>, HttpError> {
+    let sessions = store::list(&db, user.id)
+        .await?
+        .into_iter()
+        .map(|(id, details)| ActiveSession {
+            current: id == current.id,
+            id,
+            kind: details.kind,
+            created_at: details.created_at,
+            last_used_at: details.last_used_at,
+            expires_at: details.expires_at,
+            platforms: details.platforms,
+            ip: details.ip,
+        })
+        .collect();
+
+    Ok(Json(sessions))
+}
+
+/// Sign out a single session
+#[api_v2_operation]
+pub async fn revoke_session(
+    db: FullDatabase,
+    config: FullConfig,
+    user: FullUser,
+    current: CurrentSession,
+    id: Path<String>,
+) -> Result<WithCookie<Json<Status>>, HttpError> {
+    if !store::revoke(&db, user.id, &id).await? {
+        return Err(HttpError::NotFound());
+    }
+
+    // Signing out the current session also removes it from the browser.
+    let cookie = (*id == current.id).then(|| removal_cookie(&config.session));
+
+    Ok(WithCookie(
+        Json(Status {
+            message: "Successfully signed out the session".to_string(),
+        }),
+        cookie,
+    ))
+}
+
+/// Sign out the session that makes this request
+#[api_v2_operation]
+pub async fn revoke_current_session(
+    db: FullDatabase,
+    config: FullConfig,
+    user: FullUser,
+    current: CurrentSession,
+) -> Result<WithCookie<Json<Status>>, HttpError> {
+    store::revoke(&db, user.id, &current.id).await?;
+
+    Ok(WithCookie(
+        Json(Status {
+            message: "Successfully logged out".to_string(),
+        }),
+        Some(removal_cookie(&config.session)),
+    ))
+}
@@ -0,0 +1,99 @@
+use actix_web::{web::Json, HttpRequest};
+use paperclip::actix::api_v2_operation;
+
+use crate::{
+    constants::TOTP_AUTHENTICATION,
+    errors::HttpError,
+    structs::{
+        mfa::{Reauthentication, RecoveryCodes, TotpConfirmation, TotpEnrollment},
+        user::FullUser,
+        Status,
+    },
+    types::{FullConfig, FullDatabase, FullHasher, FullNotifier},
+    util::{
+        mfa,
+        totp::{self, TotpMethod},
+    },
+};
+
+/// Start setting up an authenticator app, it is enabled once a first code is confirmed.
+#[api_v2_operation]
+pub async fn start_totp_enrollment(
+    db: FullDatabase,
+    config: FullConfig,
+    full_user: FullUser,
+) -> Result<Json<TotpEnrollment>, HttpError> {
+    if full_user.authentication.contains_key(&TOTP_AUTHENTICATION) {
+        return Err(HttpError::BadRequest(Status {
+            message: "An authenticator app is already set up.".to_string(),
+        }));
+    }
+
+    let secret = totp::start_enrollment(&db, full_user.id).await?;
+    let uri = totp::provisioning_uri(&config.mfa.issuer, &full_user.username, &secret);
+
+    Ok(Json(TotpEnrollment { secret, uri }))
+}
+
+/// Enable the authenticator app with a first code, this returns the recovery codes.
+#[api_v2_operation]
+pub async fn confirm_totp_enrollment(
+    db: FullDatabase,
+    full_user: FullUser,
+    body: Json<TotpConfirmation>,
+) -> Result<Json<RecoveryCodes>, HttpError> {
+    let secret = match totp::pending_enrollment(&db, full_user.id).await? {
+        Some(secret) => secret,
+        None => {
+            return Err(HttpError::BadRequest(Status {
+                message: "Start setting up the authenticator app first.".to_string(),
+            }))
+        }
+    };
+
+    if !totp::check(&db, full_user.id, &secret, body.code.trim()).await? {
+        return Err(HttpError::Unauthorized(Status {
+            message: "Invalid code.".to_string(),
+        }));
+    }
+
+    let (recovery_codes, hashes) = totp::generate_recovery_codes();
+    let method = TotpMethod {
+        secret,
+        recovery_codes: hashes,
+    };
+    db.persistent
+        .update_authentication_method_value(full_user.id, TOTP_AUTHENTICATION, &method.to_value()?)
+        .await?;
+    totp::finish_enrollment(&db, full_user.id).await?;
+
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+/// Remove the authenticator app, this requires a code from it or the password. Wrong
+/// ones count as failed sign ins of the account.
+#[api_v2_operation]
+pub async fn remove_totp(
+    db: FullDatabase,
+    config: FullConfig,
+    hasher: FullHasher,
+    notifier: FullNotifier,
+    full_user: FullUser,
+    body: Json<Reauthentication>,
+    data: HttpRequest,
+) -> Result<Json<Status>, HttpError> {
+    if !full_user.authentication.contains_key(&TOTP_AUTHENTICATION) {
+        return Err(HttpError::NotFound());
+    }
+
+    let notifier = notifier.get_ref().as_ref();
+    mfa::reauthenticate(&db, &config, &hasher, notifier, &full_user, &body, &data).await?;
+
+    db.persistent
+        .remove_authentication_method(full_user.id, TOTP_AUTHENTICATION)
+        .await?;
+
+    Ok(Json(Status {
+        message: "Successfully removed the authenticator app".to_string(),
+    }))
+}
@@ -4,43 +4,103 @@ use serde::Deserialize;
 
 use crate::{
     errors::HttpError,
-    structs::{user::FullUser, Status},
-    traits::PersistentStorageProvider,
-    types::FullDatabase,
+    structs::{mail::Mail, user::FullUser, RetryStatus, Status},
+    types::{FullConfig, FullDatabase, FullMailer},
+    util::codes::{self, CodePurpose},
 };
 
+/// Email verification links, also sent when a user registers.
+pub(super) const VERIFICATION: CodePurpose = CodePurpose {
+    prefix: "verification",
+    path: "/verify",
+    ttl: |config| config.verification.ttl,
+    resend_interval: |config| config.verification.resend_interval,
+    mail: verification_mail,
+};
+
+fn verification_mail(user: &FullUser, link: &str, ttl: usize) -> Mail {
+    let hours = ttl.div_ceil(3600);
+
+    Mail {
+        to: user.email.clone(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Hi {},\n\nPlease confirm your email address by opening this link:\n\n{}\n\nThe link expires in {} hour{}. If you did not create a Xiler account, you can ignore this email.",
+            user.username,
+            link,
+            hours,
+            if hours == 1 { "" } else { "s" }
+        ),
+    }
+}
+
 #[derive(Deserialize, Apiv2Schema)]
 pub struct VerifyUser {
     /// The verification code
     code: String,
 }
 
+/// Verify the email address of a user with the code from the verification link.
+/// This does not require a session, as the link may be opened in another browser.
 #[api_v2_operation]
 pub async fn verify_user(
     db: FullDatabase,
-    full_user: FullUser,
     query: Query<VerifyUser>,
 ) -> Result<Json<Status>, HttpError> {
+    fn invalid() -> Result<Json<Status>, HttpError> {
+        Err(HttpError::Unauthorized(Status {
+            message: "Invalid or expired verification code.".to_string(),
+        }))
+    }
+
+    let user_id = match codes::redeem(&db, &VERIFICATION, &query.code).await? {
+        Some(user_id) => user_id,
+        None => return invalid(),
+    };
+
+    let full_user = match db.persistent.get_user_by_id(user_id).await? {
+        Some(full_user) => full_user,
+        None => return invalid(),
+    };
+
     if full_user.verification_token.is_none() {
         return Err(HttpError::BadRequest(Status {
             message: "User is already verified.".to_string(),
         }));
     }
 
-    if query.code != full_user.verification_token.unwrap() {
-        return Err(HttpError::Unauthorized(Status {
-            message: "Invalid verification code.".to_string(),
+    db.persistent.verify_user(full_user.id).await?;
+
+    Ok(Json(Status {
+        message: "User verified".to_string(),
+    }))
+}
+
+/// Send a new verification link, the previous link stops working.
+#[api_v2_operation]
+pub async fn resend_verification(
+    db: FullDatabase,
+    config: FullConfig,
+    mailer: FullMailer,
+    full_user: FullUser,
+) -> Result<Json<Status>, HttpError> {
+    if full_user.verification_token.is_none() {
+        return Err(HttpError::BadRequest(Status {
+            message: "User is already verified.".to_string(),
         }));
     }
 
-    let persistent = db.persistent.lock().unwrap();
-    let res = persistent.verify_user(full_user.id).await;
-    drop(persistent);
-
-    match res {
-        Ok(_) => Ok(Json(Status {
-            message: "User verified".to_string(),
-        })),
-        Err(message) => Err(HttpError::InternalServerError(Status { message })),
+    if let Some(retry_after) = codes::retry_after(&db, &VERIFICATION, full_user.id).await? {
+        return Err(HttpError::TooManyRequests(RetryStatus {
+            message: "A verification email was sent recently, try again later.".to_string(),
+            retry_after,
+        }));
     }
+
+    let mailer = mailer.get_ref().as_ref();
+    codes::send_link(&db, mailer, &config, &VERIFICATION, &full_user).await?;
+
+    Ok(Json(Status {
+        message: "Verification email sent".to_string(),
+    }))
 }
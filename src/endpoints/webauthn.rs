@@ -0,0 +1,79 @@
+use actix_web::{web::Json, HttpRequest};
+use paperclip::actix::api_v2_operation;
+
+use crate::{
+    constants::WEBAUTHN_AUTHENTICATION,
+    errors::HttpError,
+    structs::{
+        mfa::Reauthentication,
+        user::FullUser,
+        webauthn::{PasskeyCreationOptions, PasskeyCredential},
+        Status,
+    },
+    types::{FullConfig, FullDatabase, FullHasher, FullNotifier},
+    util::{
+        mfa,
+        webauthn::{self, WebauthnMethod},
+    },
+};
+
+/// Start adding a passkey, the browser creates it with the returned options. A
+/// passkey signs in on its own, so this requires a code from the authenticator app or
+/// the password. Wrong ones count as failed sign ins of the account.
+#[api_v2_operation]
+pub async fn start_passkey_registration(
+    db: FullDatabase,
+    config: FullConfig,
+    hasher: FullHasher,
+    notifier: FullNotifier,
+    full_user: FullUser,
+    body: Json<Reauthentication>,
+    data: HttpRequest,
+) -> Result<Json<PasskeyCreationOptions>, HttpError> {
+    let notifier = notifier.get_ref().as_ref();
+    mfa::reauthenticate(&db, &config, &hasher, notifier, &full_user, &body, &data).await?;
+
+    let options = webauthn::start_registration(&db, &config.webauthn, &full_user).await?;
+
+    Ok(Json(options))
+}
+
+/// Add the passkey the browser created.
+#[api_v2_operation]
+pub async fn confirm_passkey_registration(
+    db: FullDatabase,
+    config: FullConfig,
+    full_user: FullUser,
+    body: Json<PasskeyCredential>,
+) -> Result<Json<Status>, HttpError> {
+    let client_data_json = webauthn::decode(&body.client_data_json)?;
+    let challenge =
+        webauthn::client_challenge(&config.webauthn, &client_data_json, "webauthn.create")?;
+    if !webauthn::take_challenge(&db, &challenge, Some(full_user.id)).await? {
+        return Err(HttpError::BadRequest(Status {
+            message: "Start adding a passkey first.".to_string(),
+        }));
+    }
+
+    let passkey = webauthn::verify_registration(&config.webauthn, &body)?;
+
+    let mut method = WebauthnMethod::of(&full_user)?;
+    if method.find_mut(&passkey.id).is_some() {
+        return Err(HttpError::BadRequest(Status {
+            message: "This passkey was already added.".to_string(),
+        }));
+    }
+    method.passkeys.push(passkey);
+
+    db.persistent
+        .update_authentication_method_value(
+            full_user.id,
+            WEBAUTHN_AUTHENTICATION,
+            &method.to_value()?,
+        )
+        .await?;
+
+    Ok(Json(Status {
+        message: "Successfully added passkey".to_string(),
+    }))
+}
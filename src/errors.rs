@@ -4,19 +4,26 @@ use actix_web::{HttpResponse, ResponseError};
 use enum_display_derive::Display;
 use paperclip::actix::api_v2_errors;
 
-use crate::structs::Status;
+use crate::{
+    structs::{RetryStatus, Status},
+    util::workers::WorkerError,
+};
 
 #[api_v2_errors(
     code = 400,
     description = "Bad request",
     code = 401,
     description = "Unauthorized",
-    // code = 403,
-    // description = "Forbidden",
+    code = 403,
+    description = "Forbidden",
     code = 404,
     description = "Not found"
+    code = 429,
+    description = "Too many requests",
     code = 500,
     description = "Internal server error",
+    code = 503,
+    description = "Service unavailable",
     // code = 501,
     // description = "Not implemented"
 )]
@@ -24,9 +31,11 @@ use crate::structs::Status;
 pub enum HttpError {
     BadRequest(Status),
     Unauthorized(Status),
-    // Forbidden(Status),
+    Forbidden(Status),
     NotFound(),
+    TooManyRequests(RetryStatus),
     InternalServerError(Status),
+    ServiceUnavailable(Status),
 }
 
 impl ResponseError for HttpError {
@@ -34,11 +43,205 @@ impl ResponseError for HttpError {
         match self {
             HttpError::BadRequest(status) => HttpResponse::BadRequest().json(status),
             HttpError::Unauthorized(status) => HttpResponse::Unauthorized().json(status),
-            // HttpError::Forbidden(status) => HttpResponse::Forbidden().json(status),
+            HttpError::Forbidden(status) => HttpResponse::Forbidden().json(status),
             HttpError::NotFound() => HttpResponse::NotFound().finish(),
+            HttpError::TooManyRequests(status) => HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", status.retry_after.to_string()))
+                .json(status),
             HttpError::InternalServerError(status) => {
                 HttpResponse::InternalServerError().json(status)
-            } // HttpError::NotImplemented(status) => HttpResponse::NotImplemented().json(status),
+            }
+            // HttpError::NotImplemented(status) => HttpResponse::NotImplemented().json(status),
+            HttpError::ServiceUnavailable(status) => HttpResponse::ServiceUnavailable()
+                .insert_header(("Retry-After", "1"))
+                .json(status),
+        }
+    }
+}
+
+/// An error in the configuration of the service.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file could not be read.
+    Io(String),
+    /// The configuration is not valid TOML or has values of the wrong type.
+    Parse(String),
+    /// The configuration contains values that can not be used.
+    Invalid(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(reason) => write!(f, "Could not read configuration: {}", reason),
+            ConfigError::Parse(reason) => write!(f, "Could not parse configuration: {}", reason),
+            ConfigError::Invalid(reason) => write!(f, "Invalid configuration: {}", reason),
+        }
+    }
+}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+/// An error returned by a storage provider.
+#[derive(Debug)]
+pub enum StorageError {
+    /// The username is already used by another user.
+    UsernameTaken,
+    /// The email is already used by another user.
+    EmailTaken,
+    /// The record that was requested or modified does not exist.
+    NotFound,
+    /// The provider could not be reached or failed to execute the query.
+    Unavailable(String),
+    /// The data in the provider is not in the state that was expected.
+    Conflict(String),
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::UsernameTaken => write!(f, "User already exists"),
+            StorageError::EmailTaken => write!(f, "Email already exists"),
+            StorageError::NotFound => write!(f, "Record does not exist"),
+            StorageError::Unavailable(reason) => write!(f, "Storage unavailable: {}", reason),
+            StorageError::Conflict(reason) => write!(f, "Storage conflict: {}", reason),
+        }
+    }
+}
+
+impl From<StorageError> for HttpError {
+    fn from(error: StorageError) -> Self {
+        match error {
+            StorageError::UsernameTaken | StorageError::EmailTaken => {
+                HttpError::BadRequest(Status {
+                    message: error.to_string(),
+                })
+            }
+            StorageError::NotFound => HttpError::NotFound(),
+            StorageError::Unavailable(_) | StorageError::Conflict(_) => {
+                log::error!("{}", error);
+                HttpError::InternalServerError(Status {
+                    message: "Failed to access storage.".to_string(),
+                })
+            }
+        }
+    }
+}
+
+pub type MailResult<T> = Result<T, MailError>;
+
+/// An error returned by a mailer.
+#[derive(Debug)]
+pub enum MailError {
+    /// The address of the sender or the recipient can not be used.
+    InvalidAddress(String),
+    /// The mail could not be delivered to the mail server or outbox.
+    Unavailable(String),
+}
+
+impl Display for MailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MailError::InvalidAddress(reason) => write!(f, "Invalid email address: {}", reason),
+            MailError::Unavailable(reason) => write!(f, "Mail unavailable: {}", reason),
+        }
+    }
+}
+
+impl From<MailError> for HttpError {
+    fn from(error: MailError) -> Self {
+        match error {
+            MailError::InvalidAddress(_) => HttpError::BadRequest(Status {
+                message: error.to_string(),
+            }),
+            MailError::Unavailable(_) => {
+                log::error!("{}", error);
+                HttpError::ServiceUnavailable(Status {
+                    message: "Could not send the email, try again later.".to_string(),
+                })
+            }
+        }
+    }
+}
+
+pub type WebauthnResult<T> = Result<T, WebauthnError>;
+
+/// A passkey or a signature of a passkey that could not be verified.
+#[derive(Debug)]
+pub enum WebauthnError {
+    /// The data of the authenticator could not be decoded.
+    Malformed(String),
+    /// The data is well formed, but it does not prove what it should.
+    Rejected(String),
+}
+
+impl Display for WebauthnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebauthnError::Malformed(reason) => write!(f, "Malformed passkey data: {}", reason),
+            WebauthnError::Rejected(reason) => write!(f, "Passkey rejected: {}", reason),
+        }
+    }
+}
+
+impl From<WebauthnError> for HttpError {
+    fn from(error: WebauthnError) -> Self {
+        match error {
+            WebauthnError::Malformed(_) => HttpError::BadRequest(Status {
+                message: error.to_string(),
+            }),
+            WebauthnError::Rejected(_) => HttpError::Unauthorized(Status {
+                message: error.to_string(),
+            }),
+        }
+    }
+}
+
+pub type OauthResult<T> = Result<T, OauthError>;
+
+/// An error while signing in with another platform.
+#[derive(Debug)]
+pub enum OauthError {
+    /// The provider did not accept the sign in, or did not share what is needed.
+    Rejected(String),
+    /// The provider could not be reached or sent a response that can not be used.
+    Unavailable(String),
+}
+
+impl Display for OauthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OauthError::Rejected(reason) => write!(f, "Sign in rejected: {}", reason),
+            OauthError::Unavailable(reason) => write!(f, "Provider unavailable: {}", reason),
+        }
+    }
+}
+
+impl From<OauthError> for HttpError {
+    fn from(error: OauthError) -> Self {
+        match error {
+            OauthError::Rejected(_) => HttpError::Unauthorized(Status {
+                message: error.to_string(),
+            }),
+            OauthError::Unavailable(_) => {
+                log::error!("{}", error);
+                HttpError::ServiceUnavailable(Status {
+                    message: "Could not reach the provider, try again later.".to_string(),
+                })
+            }
+        }
+    }
+}
+
+impl From<WorkerError> for HttpError {
+    fn from(error: WorkerError) -> Self {
+        match error {
+            WorkerError::QueueFull => HttpError::ServiceUnavailable(Status {
+                message: "The server is busy, try again later.".to_string(),
+            }),
+            WorkerError::Failed => HttpError::InternalServerError(Status {
+                message: error.to_string(),
+            }),
         }
     }
 }
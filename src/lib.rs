@@ -0,0 +1,10 @@
+pub mod app;
+pub mod config;
+pub mod constants;
+pub mod endpoints;
+pub mod errors;
+pub mod middleware;
+pub mod structs;
+pub mod traits;
+pub mod types;
+pub mod util;
@@ -1,78 +1,73 @@
 use std::sync::Arc;
 
-// use actix_cors::Cors;
-use actix_web::{middleware::Logger, web::Data, App, HttpServer};
+use actix_web::{web::Data, HttpServer};
 use env_logger::Env;
-use middleware::AuthenticationService;
-use paperclip::actix::{
-    web::{delete, get, post, put, resource},
-    OpenApiExt,
-};
-use types::FullDatabase;
-use util::Database;
-
-use crate::util::data::{PersistentStorage, TemporaryStorage};
 
-mod constants;
-mod endpoints;
-mod errors;
-mod middleware;
-mod structs;
-mod traits;
-mod types;
-mod util;
+use accounts_rest_api::{
+    app::create_app,
+    config::Config,
+    traits::LockoutNotifier,
+    types::{
+        FullConfig, FullDatabase, FullHasher, FullMailer, FullNotifier, FullSigner, RiskScorer,
+    },
+    util::{
+        hashing::PasswordHasher, lockout::MailLockoutNotifier, risk::WeightedRiskScorer,
+        signing::TokenSigner, Database,
+    },
+};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    env_logger::init_from_env(Env::default().default_filter_or("info"));
+
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mailer: FullMailer = match config.mail.kind.connect(&config) {
+        Ok(mailer) => Data::new(mailer),
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let notifier: Arc<dyn LockoutNotifier> =
+        Arc::new(MailLockoutNotifier::new(mailer.get_ref().clone()));
+    let notifier: FullNotifier = Data::new(notifier);
+
     let database = Database::new(
-        PersistentStorage::new().await,
-        TemporaryStorage::new().await,
+        config.storage.persistent.connect(&config).await,
+        config.storage.temporary.connect(&config).await,
     );
-    let thread_db: FullDatabase = Data::new(Arc::new(database));
+    let hasher = PasswordHasher::new(&config.hashing);
+    let scorer: RiskScorer = Arc::new(WeightedRiskScorer::new(&config.risk));
+    // Shared between the workers, so a generated key is the same for all of them.
+    let signer: FullSigner = Data::new(Arc::new(TokenSigner::new(&config.tokens)));
+    let bind = config.server.bind.clone();
 
-    env_logger::init_from_env(Env::default().default_filter_or("info"));
+    let thread_db: FullDatabase = Data::new(Arc::new(database));
+    let thread_config: FullConfig = Data::new(Arc::new(config));
+    let thread_hasher: FullHasher = Data::new(Arc::new(hasher));
+    let limiter = thread_config.rate_limit.store.connect(&thread_db);
 
     HttpServer::new(move || {
-        // let cors = Cors::default()
-        //     .allowed_origin("http://localhost:80")
-        //     .allowed_methods(vec!["GET", "POST", "DELETE"])
-        //     .max_age(3600);
-
-        App::new()
-            .wrap_api()
-            // .wrap(cors)
-            .wrap(Logger::default())
-            .app_data(thread_db.clone())
-            .service(resource("/register").route(post().to(endpoints::register)))
-            .service(resource("/login").route(post().to(endpoints::add_login)))
-            .service(
-                resource("/me")
-                    .wrap(AuthenticationService::new(thread_db.clone()))
-                    .route(delete().to(endpoints::delete_account))
-                    .route(get().to(endpoints::get_account)),
-            )
-            .service(
-                resource("/logout")
-                    .wrap(AuthenticationService::new(thread_db.clone()))
-                    .route(delete().to(endpoints::logout)),
-            )
-            .service(
-                resource("/verify")
-                    .wrap(AuthenticationService::new(thread_db.clone()))
-                    .route(get().to(endpoints::verify_user)),
-            )
-            .service(
-                resource("/authentication/{method}")
-                    .wrap(AuthenticationService::new(thread_db.clone()))
-                    .route(delete().to(endpoints::remove_authentication_method))
-                    .route(put().to(endpoints::update_authentication_method)),
-            )
-            // OpenAPI spec:
-            .with_json_spec_at("/spec/v2")
-            .with_json_spec_v3_at("/spec/v3")
-            .build()
+        create_app(
+            thread_db.clone(),
+            thread_config.clone(),
+            thread_hasher.clone(),
+            scorer.clone(),
+            signer.clone(),
+            mailer.clone(),
+            notifier.clone(),
+            limiter.clone(),
+        )
     })
-    .bind("127.0.0.1:8080")?
+    .bind(bind)?
     .run()
     .await
 }
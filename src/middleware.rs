@@ -1,3 +1,7 @@
 mod authenticated;
+mod rate_limited;
+mod require_role;
 
 pub use authenticated::AuthenticationService;
+pub use rate_limited::RateLimitService;
+pub use require_role::RequireRole;
@@ -3,34 +3,43 @@ use futures::future::LocalBoxFuture;
 use std::{
     future::{ready, Ready},
     rc::Rc,
-    str::FromStr,
 };
-use uuid::Uuid;
 
 use actix_web::{
     body::EitherBody,
     dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
-    Error, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+    http::header::AUTHORIZATION,
+    Error, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError,
 };
 
 use crate::{
-    constants::SESSION_KEY,
-    structs::{user::FullUser, Status},
-    traits::{PersistentStorageProvider, TemporaryStorageProvider},
-    types::FullDatabase,
-    util::{
-        hashing::xx_hash,
-        parse::{parse_browser_cookie, parse_user_agent},
-    },
+    constants::{API_SESSION_PREFIX, LEGACY_BROWSER_SESSION_PREFIX},
+    errors::HttpError,
+    structs::{risk::RiskOutcome, session::CurrentSession, user::FullUser, Status},
+    types::{FullConfig, FullDatabase, FullSigner, RiskScorer},
+    util::sessions::{browser_fingerprints, store},
 };
 
 pub struct AuthenticationService {
     database: FullDatabase,
+    config: FullConfig,
+    scorer: RiskScorer,
+    signer: FullSigner,
 }
 
 impl AuthenticationService {
-    pub fn new(database: FullDatabase) -> AuthenticationService {
-        Self { database }
+    pub fn new(
+        database: FullDatabase,
+        config: FullConfig,
+        scorer: RiskScorer,
+        signer: FullSigner,
+    ) -> AuthenticationService {
+        Self {
+            database,
+            config,
+            scorer,
+            signer,
+        }
     }
 }
 
@@ -50,6 +59,9 @@ where
         ready(Ok(AuthenticatedMiddleware {
             service: Rc::new(service),
             database: self.database.clone(),
+            config: self.config.clone(),
+            scorer: self.scorer.clone(),
+            signer: self.signer.clone(),
         }))
     }
 }
@@ -57,6 +69,9 @@ where
 pub struct AuthenticatedMiddleware<S> {
     service: Rc<S>,
     database: FullDatabase,
+    config: FullConfig,
+    scorer: RiskScorer,
+    signer: FullSigner,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthenticatedMiddleware<S>
@@ -72,30 +87,59 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let cookie = match req.cookie(SESSION_KEY) {
-            Some(cookie) => cookie,
+        let session_key = &self.config.session.key;
+        let cookie = match session_token(&req, session_key) {
+            Some(token) => token,
             None => {
                 let (req, _pl) = req.into_parts();
                 let res = HttpResponse::BadRequest()
                     .json(Status {
-                        message: format!("'{}' cookie not found", SESSION_KEY),
+                        message: format!("'{}' cookie or bearer token not found", session_key),
                     })
                     .map_into_right_body();
 
                 return Box::pin(async move { Ok(ServiceResponse::new(req, res)) });
             }
         };
-        let cookie = cookie.value().to_string();
+
+        // Only browser sessions are bound to the client that created them, their
+        // signature is checked before the session is looked up.
+        let fingerprints = if cookie.starts_with(API_SESSION_PREFIX) {
+            None
+        } else {
+            match browser_fingerprints(&cookie, req.request(), &self.signer) {
+                Ok(fingerprints) => Some(fingerprints),
+                Err(e) => {
+                    let (req, _pl) = req.into_parts();
+                    let res = e.error_response().map_into_right_body();
+
+                    return Box::pin(async move { Ok(ServiceResponse::new(req, res)) });
+                }
+            }
+        };
+
+        // Only accepted while `tokens.accept_s1` is on, checked above.
+        let legacy = fingerprints.is_some() && cookie.starts_with(LEGACY_BROWSER_SESSION_PREFIX);
 
         let db = self.database.clone();
+        let config = self.config.clone();
+        let scorer = self.scorer.clone();
+        let signer = self.signer.clone();
         let svc = self.service.clone();
 
         Box::pin(async move {
-            let temporary = db.temporary.lock().unwrap();
-            let client_id = temporary.get(cookie.clone()).await;
-            drop(temporary);
+            if legacy {
+                store::migrate_legacy(&db, &config.session, &signer, &cookie, req.request())
+                    .await
+                    .map_err(HttpError::from)?;
+            }
+
+            let session_id = store::session_id(&cookie);
+            let session = store::resume(&db, &config.session, &session_id)
+                .await
+                .map_err(HttpError::from)?;
 
-            if client_id.is_none() {
+            if session.is_none() {
                 let (req, _pl) = req.into_parts();
                 let res = HttpResponse::Unauthorized()
                     .json(Status {
@@ -105,108 +149,61 @@ where
 
                 return Ok(ServiceResponse::new(req, res));
             }
-            let client_id = client_id.unwrap();
+            let client_id = session.unwrap().user_id;
+
+            if let Some((expected, actual)) = fingerprints {
+                let assessment = scorer.assess(&expected, &actual);
+
+                if assessment.outcome != RiskOutcome::Allow {
+                    log::warn!(
+                        "Rejected session: outcome={} score={:.3} user={} session={} mismatches=[{}]",
+                        assessment.outcome,
+                        assessment.score,
+                        client_id,
+                        session_id,
+                        assessment
+                            .mismatches
+                            .iter()
+                            .map(|mismatch| mismatch.to_string())
+                            .collect::<Vec<String>>()
+                            .join(",")
+                    );
+                }
 
-            if cookie.starts_with("s1") {
-                let user_agent = match req.headers().get("User-Agent") {
-                    Some(agent) => agent,
-                    None => {
+                match assessment.outcome {
+                    RiskOutcome::Allow => {}
+                    RiskOutcome::StepUp => {
                         let (req, _pl) = req.into_parts();
-                        let res = HttpResponse::BadRequest()
+                        let res = HttpResponse::Unauthorized()
                             .json(Status {
-                                message: "No user agent present".to_string(),
+                                message: "Please sign in again to confirm it is you.".to_string(),
                             })
                             .map_into_right_body();
 
                         return Ok(ServiceResponse::new(req, res));
                     }
-                };
-
-                let ip = req.peer_addr().unwrap().ip().to_string();
-                let parsed_user_agent = parse_user_agent(user_agent.to_str().unwrap().to_string());
-                let expected_cookie = match parse_browser_cookie(&cookie) {
-                    Ok(parsed) => parsed,
-                    Err(e) => {
+                    RiskOutcome::Revoke => {
                         let (req, _pl) = req.into_parts();
-                        let res = HttpResponse::BadRequest()
+                        let res = HttpResponse::Gone()
                             .json(Status {
-                                message: e.to_string(),
+                                message: "Anti-Cookie theft has removed this session.".to_string(),
                             })
                             .map_into_right_body();
 
-                        return Ok(ServiceResponse::new(req, res));
-                    }
-                };
-
-                // s1.
-                // 2426094911.
-                // 1768803836-3566326825-3912814204||3967086928-2746952293-707505781.
-                // 3788223220_59786466.
-                // Jmtl9LJ3bAYkoymfnCdHCjYjE00hJhdJ
-                let penalty_threshold = 0.6;
-                let mut cookie_owner_probability: f64 = 1.0;
-                let cookie_platform_pentalty_values = (expected_cookie.platforms.len() * 3) as f64;
-                // Improve accuracy the more variables
-                let penalty: f64 = 1.0
-                    / (1.0
-                        + cookie_platform_pentalty_values
-                        + expected_cookie.extensions.len() as f64);
-
-                if expected_cookie.ip != xx_hash(&ip) {
-                    cookie_owner_probability -= penalty;
-                }
+                        store::revoke(&db, client_id, &session_id)
+                            .await
+                            .map_err(HttpError::from)?;
 
-                for (expected, received) in expected_cookie
-                    .platforms
-                    .into_iter()
-                    .zip(parsed_user_agent.platforms.into_iter())
-                {
-                    if expected.name != xx_hash(&received.name) {
-                        cookie_owner_probability -= penalty;
-                    }
-
-                    if expected.version != xx_hash(&received.version) {
-                        cookie_owner_probability -= penalty;
-                    }
-
-                    if expected.details != xx_hash(&received.details) {
-                        cookie_owner_probability -= penalty;
-                    }
-                }
-
-                for (expected, received) in expected_cookie
-                    .extensions
-                    .into_iter()
-                    .zip(parsed_user_agent.extensions.into_iter())
-                {
-                    if expected != xx_hash(&received) {
-                        cookie_owner_probability -= penalty;
+                        return Ok(ServiceResponse::new(req, res));
                     }
                 }
-
-                let same_client = cookie_owner_probability > penalty_threshold;
-
-                if !same_client {
-                    let (req, _pl) = req.into_parts();
-                    let res = HttpResponse::Gone()
-                        .json(Status {
-                            message: "Anti-Cookie theft has removed this session.".to_string(),
-                        })
-                        .map_into_right_body();
-
-                    let temporary = db.temporary.lock().unwrap();
-                    temporary.delete(cookie).await;
-                    drop(temporary);
-
-                    return Ok(ServiceResponse::new(req, res));
-                }
             }
 
-            let persistent = db.persistent.lock().unwrap();
-            let full_user = persistent
-                .get_user_by_id(Uuid::from_str(&client_id).unwrap())
-                .await;
-            drop(persistent);
+            let full_user = db
+                .persistent
+                .get_user_by_id(client_id)
+                .await
+                .map_err(HttpError::from)?;
 
             if full_user.is_none() {
                 let (req, _pl) = req.into_parts();
@@ -220,7 +217,20 @@ where
             }
             let full_user = full_user.unwrap();
 
+            if full_user.disabled {
+                let (req, _pl) = req.into_parts();
+                let res = HttpError::Forbidden(Status {
+                    message: "This account has been disabled.".to_string(),
+                })
+                .error_response()
+                .map_into_right_body();
+
+                return Ok(ServiceResponse::new(req, res));
+            }
+
             req.extensions_mut().insert(full_user);
+            req.extensions_mut()
+                .insert(CurrentSession { id: session_id });
 
             let res = svc.call(req).await?;
 
@@ -229,6 +239,25 @@ where
     }
 }
 
+/// Read the API session token from the `Authorization: Bearer` header, or else the
+/// browser session token from the session cookie. Other authorization headers, like
+/// the ones a proxy adds, do not hide the cookie.
+fn session_token(req: &ServiceRequest, session_key: &str) -> Option<String> {
+    let bearer = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| token.starts_with(&format!("{}.", API_SESSION_PREFIX)));
+    if let Some(token) = bearer {
+        return Some(token.to_string());
+    }
+
+    req.cookie(session_key)
+        .map(|cookie| cookie.value().to_string())
+}
+
 impl FromRequest for FullUser {
     type Error = Error;
     type Future = Ready<Result<FullUser, Error>>;
@@ -238,3 +267,17 @@ impl FromRequest for FullUser {
         ready(Ok(req.extensions().get::<FullUser>().unwrap().clone()))
     }
 }
+
+impl FromRequest for CurrentSession {
+    type Error = Error;
+    type Future = Ready<Result<CurrentSession, Error>>;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(req
+            .extensions()
+            .get::<CurrentSession>()
+            .unwrap()
+            .clone()))
+    }
+}
@@ -0,0 +1,150 @@
+use futures::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderMap, HeaderName, HeaderValue},
+    Error, HttpMessage, ResponseError,
+};
+
+use crate::{
+    config::RateLimit,
+    errors::HttpError,
+    structs::{session::CurrentSession, user::FullUser, RetryStatus},
+    types::RateLimiter,
+    util::rate_limit::{RateLimitDecision, RateLimitKey},
+};
+
+/// Limits the requests to a resource with a token bucket per client. Limits that
+/// are counted per user or session must be wrapped inside `AuthenticationService`.
+/// Resources that share a name share their buckets.
+pub struct RateLimitService {
+    name: &'static str,
+    limit: RateLimit,
+    store: RateLimiter,
+}
+
+impl RateLimitService {
+    pub fn new(name: &'static str, limit: RateLimit, store: RateLimiter) -> RateLimitService {
+        Self { name, limit, store }
+    }
+}
+
+impl<S: 'static, B> Transform<S, ServiceRequest> for RateLimitService
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimitedMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitedMiddleware {
+            service: Rc::new(service),
+            name: self.name,
+            limit: Rc::new(self.limit.clone()),
+            store: self.store.clone(),
+        }))
+    }
+}
+
+pub struct RateLimitedMiddleware<S> {
+    service: Rc<S>,
+    name: &'static str,
+    limit: Rc<RateLimit>,
+    store: RateLimiter,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitedMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let svc = self.service.clone();
+
+        if self.limit.requests == 0 {
+            return Box::pin(async move { Ok(svc.call(req).await?.map_into_left_body()) });
+        }
+
+        let key = format!("{}:{}", self.name, client(&req, self.limit.key));
+        let limit = self.limit.clone();
+        let store = self.store.clone();
+
+        Box::pin(async move {
+            let decision = store.take(key, &limit).await.map_err(HttpError::from)?;
+
+            if !decision.allowed {
+                let (req, _pl) = req.into_parts();
+                let mut res = HttpError::TooManyRequests(RetryStatus {
+                    message: "Too many requests, try again later.".to_string(),
+                    retry_after: decision.retry_after,
+                })
+                .error_response();
+                insert_headers(res.headers_mut(), &limit, &decision);
+
+                return Ok(ServiceResponse::new(req, res.map_into_right_body()));
+            }
+
+            let mut res = svc.call(req).await?;
+            insert_headers(res.headers_mut(), &limit, &decision);
+
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+/// Who the request is counted for, the IP address when the user or session is not
+/// known.
+fn client(req: &ServiceRequest, key: RateLimitKey) -> String {
+    let extensions = req.extensions();
+    let known = match key {
+        RateLimitKey::Ip => None,
+        RateLimitKey::User => extensions
+            .get::<FullUser>()
+            .map(|user| format!("user:{}", user.id)),
+        RateLimitKey::Session => extensions
+            .get::<CurrentSession>()
+            .map(|session| format!("session:{}", session.id)),
+    };
+
+    known.unwrap_or_else(|| {
+        let ip = req
+            .peer_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        format!("ip:{}", ip)
+    })
+}
+
+/// The `RateLimit-*` headers of the IETF draft on rate limit headers.
+fn insert_headers(headers: &mut HeaderMap, limit: &RateLimit, decision: &RateLimitDecision) {
+    for (name, value) in [
+        ("ratelimit-limit", decision.limit.to_string()),
+        ("ratelimit-remaining", decision.remaining.to_string()),
+        ("ratelimit-reset", decision.reset.to_string()),
+        (
+            "ratelimit-policy",
+            format!("{};w={}", limit.requests, limit.per),
+        ),
+    ] {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use futures::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpMessage, ResponseError,
+};
+
+use crate::{
+    errors::HttpError,
+    structs::{
+        role::{Permission, Role},
+        user::FullUser,
+        Status,
+    },
+};
+
+/// What the user of a request needs to have.
+#[derive(Clone, Copy)]
+enum Requirement {
+    Role(Role),
+    Permission(Permission),
+}
+
+/// Refuses requests of users that do not have a role or permission with 403
+/// Forbidden. It reads the user that `AuthenticationService` found, so it has to
+/// be wrapped inside it: `.wrap(RequireRole::new(..)).wrap(authenticated())`.
+pub struct RequireRole {
+    requirement: Requirement,
+}
+
+impl RequireRole {
+    /// Require the user to have this role.
+    pub fn new(role: Role) -> RequireRole {
+        Self {
+            requirement: Requirement::Role(role),
+        }
+    }
+
+    /// Require any of the roles of the user to grant this permission.
+    pub fn permission(permission: Permission) -> RequireRole {
+        Self {
+            requirement: Requirement::Permission(permission),
+        }
+    }
+}
+
+impl<S: 'static, B> Transform<S, ServiceRequest> for RequireRole
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequireRoleMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireRoleMiddleware {
+            service: Rc::new(service),
+            requirement: self.requirement,
+        }))
+    }
+}
+
+pub struct RequireRoleMiddleware<S> {
+    service: Rc<S>,
+    requirement: Requirement,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireRoleMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let allowed = req
+            .extensions()
+            .get::<FullUser>()
+            .map(|user| match self.requirement {
+                Requirement::Role(role) => user.role_set().contains(role),
+                Requirement::Permission(permission) => user.can(permission),
+            });
+
+        let error = match allowed {
+            Some(true) => {
+                let svc = self.service.clone();
+                return Box::pin(async move { Ok(svc.call(req).await?.map_into_left_body()) });
+            }
+            Some(false) => HttpError::Forbidden(Status {
+                message: "You do not have permission to do this.".to_string(),
+            }),
+            // Not wrapped inside `AuthenticationService`, never let it through.
+            None => HttpError::Unauthorized(Status {
+                message: "Not authenticated".to_string(),
+            }),
+        };
+
+        let (req, _pl) = req.into_parts();
+        let res = error.error_response().map_into_right_body();
+        Box::pin(async move { Ok(ServiceResponse::new(req, res)) })
+    }
+}
@@ -1,7 +1,13 @@
+pub mod admin;
 pub mod cookie;
+pub mod mail;
+pub mod mfa;
+pub mod risk;
+pub mod role;
 pub mod session;
 pub mod status;
 pub mod user;
 pub mod user_agent;
+pub mod webauthn;
 
-pub use self::status::Status;
+pub use self::status::{RetryStatus, Status};
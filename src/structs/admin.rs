@@ -0,0 +1,29 @@
+// What operators see of other accounts.
+
+use paperclip::actix::Apiv2Schema;
+use serde::Serialize;
+
+use super::role::Role;
+
+/// A user as operators see them, without the secrets of their authentication
+/// methods or their verification token.
+#[derive(Serialize, Apiv2Schema)]
+pub struct AdminUser {
+    pub id: String,
+    pub username: String,
+    pub email: String,
+    pub created_at: usize,
+    pub roles: Vec<Role>,
+    /// An int that contains the linked platforms, can be parsed by using bitwize operations.
+    pub authentication: i16,
+    pub verified: bool,
+    pub disabled: bool,
+}
+
+#[derive(Serialize, Apiv2Schema)]
+pub struct UserList {
+    pub users: Vec<AdminUser>,
+    /// Pass as `after` to get the next page, `null` on the last page. A search can
+    /// return pages with fewer users than the limit before the last page.
+    pub next: Option<String>,
+}
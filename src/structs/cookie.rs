@@ -1,10 +1,20 @@
 use super::user_agent::{UserAgentExtension, UserAgentPlatform};
 
-// s1.2426094911.1768803836-3566326825-3912814204||3967086928-2746952293-707505781.3788223220_59786466.Jmtl9LJ3bAYkoymfnCdHCjYjE00hJhdJ
-pub struct ParsedCookie {
-    pub prefix: String,
+/// The hashed IP address and user agent of a client.
+pub struct Fingerprint {
     pub ip: String,
     pub platforms: Vec<UserAgentPlatform>,
     pub extensions: Vec<UserAgentExtension>,
+}
+
+// s1.2426094911.1768803836-3566326825-3912814204||3967086928-2746952293-707505781.3788223220_59786466.Jmtl9LJ3bAYkoymfnCdHCjYjE00hJhdJ
+// s2.<key id>.<ip>.<platforms>.<extensions>.<random>.<signature>
+pub struct ParsedCookie {
+    pub prefix: String,
+    /// The key that signed the token, `s1` tokens are not signed.
+    pub key_id: Option<String>,
+    /// The fingerprint of the client that created the session.
+    pub fingerprint: Fingerprint,
     pub random: String,
+    pub signature: Option<String>,
 }
@@ -0,0 +1,12 @@
+// Represents an email that is sent to a user
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Mail {
+    /// The address of the recipient.
+    pub to: String,
+    pub subject: String,
+    /// The plain text body.
+    pub body: String,
+}
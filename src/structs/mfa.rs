@@ -0,0 +1,53 @@
+// Represents the second step of signing in, and the enrollment of second factors
+
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+
+/// Returned instead of a session when the user has to provide a second factor.
+#[derive(Serialize, Apiv2Schema)]
+pub struct MfaChallenge {
+    /// Send this along with the code to `/login/mfa`.
+    pub mfa_token: String,
+    /// The amount of seconds the second factor can be provided.
+    pub ttl: usize,
+    /// The second factors the user can provide.
+    pub methods: Vec<String>,
+}
+
+#[derive(Deserialize, Apiv2Schema)]
+pub struct MfaLogin {
+    pub mfa_token: String,
+    /// A code from the authenticator app, or one of the recovery codes.
+    pub code: String,
+}
+
+#[derive(Serialize, Apiv2Schema)]
+pub struct TotpEnrollment {
+    /// The base32 encoded secret, for apps that can not scan the URI.
+    pub secret: String,
+    /// The `otpauth://` URI, usually shown as a QR code.
+    pub uri: String,
+}
+
+#[derive(Deserialize, Apiv2Schema)]
+pub struct TotpConfirmation {
+    /// A code from the authenticator app.
+    pub code: String,
+}
+
+/// Proof that the user can still sign in, either is enough. It is asked again before
+/// changes that would keep an attacker signed in, like removing the authenticator app.
+#[derive(Deserialize, Apiv2Schema)]
+pub struct Reauthentication {
+    /// A code from the authenticator app, or one of the recovery codes.
+    pub code: Option<String>,
+    /// The password of the user.
+    pub password: Option<String>,
+}
+
+/// Codes that can be used once instead of the authenticator app, they are only
+/// shown once.
+#[derive(Serialize, Apiv2Schema)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
@@ -0,0 +1,63 @@
+use std::fmt::Display;
+
+/// What happens with a session after its risk has been assessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RiskOutcome {
+    /// The session is used by the client that created it.
+    Allow,
+    /// The session might be stolen, the user has to sign in again.
+    StepUp,
+    /// The session is most likely stolen and is removed.
+    Revoke,
+}
+
+/// A part of the fingerprint that does not match the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mismatch {
+    Ip,
+    PlatformName(usize),
+    PlatformVersion(usize),
+    PlatformDetails(usize),
+    /// The client has less platforms than the session was created with.
+    MissingPlatform(usize),
+    /// The client has more platforms than the session was created with.
+    ExtraPlatform(usize),
+    Extension(usize),
+    MissingExtension(usize),
+    ExtraExtension(usize),
+}
+
+/// The result of comparing a session with the client that uses it.
+#[derive(Debug, Clone)]
+pub struct RiskAssessment {
+    /// The probability that the session is used by its owner, between 0 and 1.
+    pub score: f64,
+    pub outcome: RiskOutcome,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl Display for RiskOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RiskOutcome::Allow => write!(f, "allow"),
+            RiskOutcome::StepUp => write!(f, "step_up"),
+            RiskOutcome::Revoke => write!(f, "revoke"),
+        }
+    }
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::Ip => write!(f, "ip"),
+            Mismatch::PlatformName(i) => write!(f, "platforms[{}].name", i),
+            Mismatch::PlatformVersion(i) => write!(f, "platforms[{}].version", i),
+            Mismatch::PlatformDetails(i) => write!(f, "platforms[{}].details", i),
+            Mismatch::MissingPlatform(i) => write!(f, "platforms[{}].missing", i),
+            Mismatch::ExtraPlatform(i) => write!(f, "platforms[{}].extra", i),
+            Mismatch::Extension(i) => write!(f, "extensions[{}]", i),
+            Mismatch::MissingExtension(i) => write!(f, "extensions[{}].missing", i),
+            Mismatch::ExtraExtension(i) => write!(f, "extensions[{}].extra", i),
+        }
+    }
+}
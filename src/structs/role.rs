@@ -0,0 +1,108 @@
+// The roles of a user, stored as a bitmask in `FullUser.roles`.
+//
+// Endpoints check permissions rather than roles, a role is a named set of
+// permissions. Bits that do not belong to a role are kept but grant nothing.
+
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+
+/// A role that can be given to a user, its value is its bit in `FullUser.roles`.
+/// Roles are ordered by how much they allow.
+#[derive(
+    Serialize, Deserialize, Apiv2Schema, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Helps users with their accounts.
+    Support = 1,
+    /// Deals with accounts that misbehave.
+    Moderator = 2,
+    /// Manages every account, including the roles of other users.
+    Admin = 4,
+}
+
+/// Something a role allows a user to do to other accounts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    ViewUsers,
+    VerifyUsers,
+    RevokeSessions,
+    DisableUsers,
+    DeleteUsers,
+    ManageRoles,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Support, Role::Moderator, Role::Admin];
+
+    pub fn bit(self) -> usize {
+        self as usize
+    }
+
+    pub fn permissions(self) -> &'static [Permission] {
+        match self {
+            Role::Support => &[Permission::ViewUsers, Permission::VerifyUsers],
+            Role::Moderator => &[
+                Permission::ViewUsers,
+                Permission::VerifyUsers,
+                Permission::RevokeSessions,
+                Permission::DisableUsers,
+            ],
+            Role::Admin => &[
+                Permission::ViewUsers,
+                Permission::VerifyUsers,
+                Permission::RevokeSessions,
+                Permission::DisableUsers,
+                Permission::DeleteUsers,
+                Permission::ManageRoles,
+            ],
+        }
+    }
+}
+
+/// The decoded roles bitmask of a user.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Roles(usize);
+
+impl Roles {
+    pub fn from_bits(bits: usize) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(self) -> usize {
+        self.0
+    }
+
+    pub fn contains(self, role: Role) -> bool {
+        self.0 & role.bit() != 0
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Role> {
+        Role::ALL
+            .into_iter()
+            .filter(move |role| self.contains(*role))
+    }
+
+    /// The role that allows the most, `None` for users without roles.
+    pub fn highest(self) -> Option<Role> {
+        self.iter().max()
+    }
+
+    /// If any of the roles grants the permission.
+    pub fn allows(self, permission: Permission) -> bool {
+        self.iter()
+            .any(|role| role.permissions().contains(&permission))
+    }
+}
+
+impl From<usize> for Roles {
+    fn from(bits: usize) -> Self {
+        Self::from_bits(bits)
+    }
+}
+
+impl FromIterator<Role> for Roles {
+    fn from_iter<I: IntoIterator<Item = Role>>(roles: I) -> Self {
+        Self(roles.into_iter().fold(0, |bits, role| bits | role.bit()))
+    }
+}
@@ -1,5 +1,8 @@
 use paperclip::actix::Apiv2Schema;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::user_agent::UserAgentPlatform;
 
 /// Represents a session
 #[derive(Serialize, Apiv2Schema)]
@@ -7,3 +10,56 @@ pub struct Session {
     pub token: String,
     pub ttl: usize,
 }
+
+/// The kind of client a session is created for.
+#[derive(Serialize, Deserialize, Apiv2Schema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionKind {
+    /// A session for a browser, stored in a cookie and bound to the browser fingerprint.
+    #[default]
+    Browser,
+    /// A session for API and mobile clients, sent as a bearer token.
+    Api,
+}
+
+/// What is stored about a session, next to the token itself.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SessionDetails {
+    pub user_id: Uuid,
+    pub kind: SessionKind,
+    pub created_at: usize,
+    pub last_used_at: usize,
+    /// When the session expires if it is not used.
+    pub expires_at: usize,
+    /// When the session expires, even if it is used.
+    pub ends_at: usize,
+    pub platforms: Vec<UserAgentPlatform>,
+    /// A keyed hash of the IP address the session was created from.
+    pub ip: String,
+}
+
+/// A session of the user, as listed to the user.
+#[derive(Serialize, Apiv2Schema)]
+pub struct ActiveSession {
+    pub id: String,
+    pub kind: SessionKind,
+    pub created_at: usize,
+    pub last_used_at: usize,
+    /// When the session expires if it is not used again.
+    pub expires_at: usize,
+    /// The platforms of the user agent that created the session.
+    pub platforms: Vec<UserAgentPlatform>,
+    /// A hash of the IP address the session was created from.
+    pub ip: String,
+    /// If this is the session that made the request.
+    pub current: bool,
+}
+
+/// The session that authenticated the request.
+#[derive(Clone)]
+pub struct CurrentSession {
+    pub id: String,
+}
+
+impl paperclip::v2::schema::Apiv2Schema for CurrentSession {}
+impl paperclip::actix::OperationModifier for CurrentSession {}
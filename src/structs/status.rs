@@ -14,3 +14,18 @@ impl Display for Status {
         write!(f, "{}", self.message)
     }
 }
+
+/// Represents a status response for a request that can be retried later.
+#[derive(Serialize, Debug)]
+pub struct RetryStatus {
+    pub message: String,
+    /// The amount of seconds until the request can be retried.
+    #[serde(skip)]
+    pub retry_after: u64,
+}
+
+impl Display for RetryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
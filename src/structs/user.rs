@@ -1,12 +1,21 @@
 // Represents a user in memory
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use chrono::Duration;
-use paperclip::actix::{Apiv2Schema, Apiv2Security};
+use paperclip::{
+    actix::{Apiv2Schema, Apiv2Security, OperationModifier},
+    v2::models::{DefaultOperationRaw, SecurityScheme},
+};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use super::{
+    admin::AdminUser,
+    role::{Permission, Roles},
+    session::SessionKind,
+};
+
 pub type UserAuthenticationMap = HashMap<i16, String>;
 
 #[derive(Serialize, Apiv2Schema)]
@@ -15,7 +24,7 @@ pub struct User {
     pub username: String,
     pub email: String,
     pub created_at: usize,
-    /// A int that contains the user roles, can be parsed by using bitwize operations.
+    /// A int that contains the user roles, the bits are the values of `Role`.
     pub roles: usize,
     /// An int that contains the linked platforms, can be parsed by using bitwize operations.
     pub authentication: i16,
@@ -29,45 +38,118 @@ pub struct UserRegistration {
     pub email: String,
     /// A hashed version of the password.
     pub password: String,
+    /// The kind of session that is created for the new user.
+    #[serde(default)]
+    pub session: SessionKind,
 }
 
 #[derive(Deserialize, Apiv2Schema)]
 pub struct UserLogin {
     pub username: String,
     pub password: String,
+    /// The kind of session that is created.
+    #[serde(default)]
+    pub session: SessionKind,
 }
 
-#[derive(Clone, Apiv2Security)]
+/// Browser sessions are sent in the session cookie.
+#[derive(Apiv2Security)]
 #[openapi(
     apiKey,
+    alias = "session_cookie",
     in = "cookie",
     name = "xiler-session",
     description = "The session cookie."
 )]
+pub struct SessionCookie;
+
+/// API sessions are sent as `Authorization: Bearer <token>`.
+#[derive(Apiv2Security)]
+#[openapi(
+    apiKey,
+    alias = "bearer_token",
+    in = "header",
+    name = "Authorization",
+    description = "An API session token, prefixed with `Bearer `."
+)]
+pub struct BearerToken;
+
+#[derive(Clone)]
 pub struct FullUser {
     pub id: Uuid,
     pub username: String,
     pub email: String,
     pub created_at: Duration,
-    /// A int that contains the user roles, can be parsed by using bitwize operations.
+    /// A int that contains the user roles, can be decoded with `FullUser::role_set`.
     pub roles: usize,
     /// A map of authentication tokens, the key is the authentication type.
     /// For example: 0 = password, 1 = google, 2 = GitHub, etc.
     /// The value is the hash of the password or the ID of the account on the provider
     pub authentication: UserAuthenticationMap,
     pub verification_token: Option<String>,
+    /// Disabled users can not sign in and their sessions are rejected.
+    pub disabled: bool,
+}
+
+/// A page of users, see `PersistentStorageProvider::list_users`.
+pub struct UserPage {
+    pub users: Vec<FullUser>,
+    /// Where the next page starts, `None` when there are no more users.
+    pub next: Option<Uuid>,
 }
 
 impl FullUser {
-    pub fn to_user(self) -> User {
+    pub fn role_set(&self) -> Roles {
+        Roles::from_bits(self.roles)
+    }
+
+    /// If any of the roles of the user grants the permission.
+    pub fn can(&self, permission: Permission) -> bool {
+        self.role_set().allows(permission)
+    }
+
+    /// If the username or email contains the search, which has to be lowercase.
+    pub fn matches(&self, search: &str) -> bool {
+        self.username.to_lowercase().contains(search) || self.email.to_lowercase().contains(search)
+    }
+
+    pub fn to_user(&self) -> User {
         User {
             id: self.id.to_string(),
-            username: self.username,
-            email: self.email,
+            username: self.username.clone(),
+            email: self.email.clone(),
             created_at: self.created_at.num_seconds() as usize,
             roles: self.roles,
             authentication: self.authentication.keys().fold(0, |acc, x| acc | x),
             verified: self.verification_token.is_none(),
         }
     }
+
+    pub fn to_admin_user(&self) -> AdminUser {
+        AdminUser {
+            id: self.id.to_string(),
+            username: self.username.clone(),
+            email: self.email.clone(),
+            created_at: self.created_at.num_seconds() as usize,
+            roles: self.role_set().iter().collect(),
+            authentication: self.authentication.keys().fold(0, |acc, x| acc | x),
+            verified: self.verification_token.is_none(),
+            disabled: self.disabled,
+        }
+    }
+}
+
+impl paperclip::v2::schema::Apiv2Schema for FullUser {}
+
+/// Either of the session transports authenticates the user.
+impl OperationModifier for FullUser {
+    fn update_security(op: &mut DefaultOperationRaw) {
+        SessionCookie::update_security(op);
+        BearerToken::update_security(op);
+    }
+
+    fn update_security_definitions(map: &mut BTreeMap<String, SecurityScheme>) {
+        SessionCookie::update_security_definitions(map);
+        BearerToken::update_security_definitions(map);
+    }
 }
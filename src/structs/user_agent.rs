@@ -1,5 +1,9 @@
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+
 pub type UserAgentExtension = String;
 
+#[derive(Serialize, Deserialize, Apiv2Schema, Clone, Debug)]
 pub struct UserAgentPlatform {
     pub name: String,
     pub version: String,
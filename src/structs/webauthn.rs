@@ -0,0 +1,56 @@
+// Represents the ceremonies of passkeys (WebAuthn), binary values are base64url encoded
+
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+
+use super::session::SessionKind;
+
+/// What the browser needs to create a passkey, see `navigator.credentials.create`.
+#[derive(Serialize, Apiv2Schema)]
+pub struct PasskeyCreationOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub rp_name: String,
+    /// The user handle, authenticators return this when the passkey is used.
+    pub user_id: String,
+    pub user_name: String,
+    /// The COSE algorithms of the keys that are accepted.
+    pub algorithms: Vec<i64>,
+    /// The ids of the passkeys the user already has.
+    pub exclude_credentials: Vec<String>,
+    /// The amount of seconds the challenge can be signed.
+    pub ttl: usize,
+}
+
+/// The passkey the browser created, the response of `navigator.credentials.create`.
+#[derive(Deserialize, Apiv2Schema)]
+pub struct PasskeyCredential {
+    pub id: String,
+    pub client_data_json: String,
+    pub attestation_object: String,
+    /// A name to recognize the passkey by.
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// What the browser needs to sign in with a passkey, see `navigator.credentials.get`.
+#[derive(Serialize, Apiv2Schema)]
+pub struct PasskeyRequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    /// The amount of seconds the challenge can be signed.
+    pub ttl: usize,
+}
+
+/// A signed challenge, the response of `navigator.credentials.get`.
+#[derive(Deserialize, Apiv2Schema)]
+pub struct PasskeyAssertion {
+    pub id: String,
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    pub user_handle: String,
+    /// The kind of session that is created.
+    #[serde(default)]
+    pub session: SessionKind,
+}
@@ -1,5 +1,13 @@
+mod lockout_notifier;
+mod mailer;
 mod persistent_storage_provider;
+mod rate_limit_store;
+mod session_risk_scorer;
 mod temporary_storage_provider;
 
+pub use lockout_notifier::LockoutNotifier;
+pub use mailer::Mailer;
 pub use persistent_storage_provider::PersistentStorageProvider;
+pub use rate_limit_store::RateLimitStore;
+pub use session_risk_scorer::SessionRiskScorer;
 pub use temporary_storage_provider::TemporaryStorageProvider;
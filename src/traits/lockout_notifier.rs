@@ -0,0 +1,12 @@
+// Is told when an account gets locked after too many failed sign ins, e.g. to warn
+// the owner of the account.
+use async_trait::async_trait;
+
+use crate::structs::user::FullUser;
+
+#[async_trait]
+pub trait LockoutNotifier: Send + Sync {
+    /// Called once per lockout, `ip` is the address of the last failed attempt and
+    /// `duration` the amount of seconds the account is locked.
+    async fn account_locked(&self, user: &FullUser, ip: &str, duration: usize);
+}
@@ -0,0 +1,12 @@
+// Represents a way to deliver email to users, such as an SMTP server.
+use async_trait::async_trait;
+
+use crate::{errors::MailResult, structs::mail::Mail};
+
+// Mailers are shared between all workers without a lock, so they must handle
+// concurrent calls themselves.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    /// Deliver a mail, it is not retried when this fails.
+    async fn send(&self, mail: Mail) -> MailResult<()>;
+}
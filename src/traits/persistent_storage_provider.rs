@@ -5,28 +5,54 @@
 use async_trait::async_trait;
 use uuid::Uuid;
 
-use crate::structs::user::FullUser;
+use crate::{
+    errors::StorageResult,
+    structs::user::{FullUser, UserPage},
+};
 
+// Providers are shared between all workers without a lock, so they must handle
+// concurrent calls themselves.
 #[async_trait]
-pub trait PersistentStorageProvider {
-    async fn get_user_by_username(&self, username: String) -> Option<FullUser>;
-    async fn get_user_by_email(&self, email: String) -> Option<FullUser>;
-    async fn get_user_by_id(&self, id: Uuid) -> Option<FullUser>;
+pub trait PersistentStorageProvider: Send + Sync {
+    async fn get_user_by_username(&self, username: String) -> StorageResult<Option<FullUser>>;
+    async fn get_user_by_email(&self, email: String) -> StorageResult<Option<FullUser>>;
+    async fn get_user_by_id(&self, id: Uuid) -> StorageResult<Option<FullUser>>;
+    /// The user that has an authentication method with this value, such as the
+    /// id of their account at a provider.
+    async fn get_user_by_authentication(
+        &self,
+        method: i16,
+        value: String,
+    ) -> StorageResult<Option<FullUser>>;
+
+    /// Up to `limit` users whose username or email contains `search`, ignoring
+    /// case. The page starts after the user `after`, in an order that is up to
+    /// the provider. Providers may stop early, so a page with a `next` cursor can
+    /// have fewer users or none at all.
+    async fn list_users(
+        &self,
+        search: Option<String>,
+        after: Option<Uuid>,
+        limit: usize,
+    ) -> StorageResult<UserPage>;
 
-    async fn does_username_exist(&self, username: String) -> bool;
-    async fn does_email_exist(&self, email: String) -> bool;
+    async fn does_username_exist(&self, username: String) -> StorageResult<bool>;
+    async fn does_email_exist(&self, email: String) -> StorageResult<bool>;
 
-    async fn register_user(&self, user: FullUser) -> Result<(), String>;
-    async fn delete_user(&self, id: Uuid) -> Result<(), String>;
+    async fn register_user(&self, user: FullUser) -> StorageResult<()>;
+    async fn delete_user(&self, id: Uuid) -> StorageResult<()>;
 
-    async fn verify_user(&self, id: Uuid) -> Result<(), String>;
+    async fn verify_user(&self, id: Uuid) -> StorageResult<()>;
+    /// Replace the roles bitmask of a user.
+    async fn set_roles(&self, id: Uuid, roles: usize) -> StorageResult<()>;
+    async fn set_disabled(&self, id: Uuid, disabled: bool) -> StorageResult<()>;
 
-    async fn remove_authentication_method(&self, id: Uuid, method: i16) -> Result<(), String>;
+    async fn remove_authentication_method(&self, id: Uuid, method: i16) -> StorageResult<()>;
     async fn update_authentication_method_value(
         &self,
         id: Uuid,
         method: i16,
         new_value: &str,
-    ) -> Result<(), String>;
-    async fn get_authentication_methods(&self, id: Uuid) -> Result<Vec<i16>, String>;
+    ) -> StorageResult<()>;
+    async fn get_authentication_methods(&self, id: Uuid) -> StorageResult<Vec<i16>>;
 }
@@ -0,0 +1,14 @@
+// Keeps the token buckets of the rate limits, such as in memory or in temporary
+// storage.
+use async_trait::async_trait;
+
+use crate::{config::RateLimit, errors::StorageResult, util::rate_limit::RateLimitDecision};
+
+// Stores are shared between all workers without a lock, so they must handle
+// concurrent calls themselves.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Take a token from the bucket of `key` (`<limit name>:<client>`), after filling
+    /// it for the time that passed since it was last used.
+    async fn take(&self, key: String, limit: &RateLimit) -> StorageResult<RateLimitDecision>;
+}
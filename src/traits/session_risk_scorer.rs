@@ -0,0 +1,8 @@
+// Decides if a browser session is used by the client that created it.
+use crate::structs::{cookie::Fingerprint, risk::RiskAssessment};
+
+pub trait SessionRiskScorer: Send + Sync {
+    /// Compare the fingerprint the session was created with (`expected`) with
+    /// the fingerprint of the client that uses it (`actual`).
+    fn assess(&self, expected: &Fingerprint, actual: &Fingerprint) -> RiskAssessment;
+}
@@ -1,10 +1,22 @@
 // Represents a storage that can be used to store temporary data. (eg sessions)
 use async_trait::async_trait;
 
+use crate::errors::StorageResult;
+
+// Providers are shared between all workers without a lock, so they must handle
+// concurrent calls themselves.
 #[async_trait]
-pub trait TemporaryStorageProvider {
-    async fn get(&self, key: String) -> Option<String>;
-    async fn set(&self, key: String, value: String) -> bool;
-    async fn delete(&self, key: String) -> bool;
-    async fn drop_all(&self, value: String) -> bool;
+pub trait TemporaryStorageProvider: Send + Sync {
+    /// Get the value of a key, `None` if the key does not exist (anymore).
+    async fn get(&self, key: String) -> StorageResult<Option<String>>;
+    /// Set the value of a key, it expires after the default ttl of the provider.
+    async fn set(&self, key: String, value: String) -> StorageResult<()>;
+    /// Set the value of a key that expires after `ttl` seconds, 0 means never.
+    async fn set_with_ttl(&self, key: String, value: String, ttl: usize) -> StorageResult<()>;
+    /// Set the value of a key that expires after `ttl` seconds, unless the key
+    /// exists. Returns if it was set, only one of many concurrent calls sets it.
+    async fn set_if_absent(&self, key: String, value: String, ttl: usize) -> StorageResult<bool>;
+    async fn delete(&self, key: String) -> StorageResult<()>;
+    /// Remove all keys that have the given value.
+    async fn drop_all(&self, value: String) -> StorageResult<()>;
 }
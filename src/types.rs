@@ -1,5 +1,16 @@
-use crate::util::Database;
+use crate::{
+    config::Config,
+    traits::{LockoutNotifier, RateLimitStore, SessionRiskScorer},
+    util::{hashing::PasswordHasher, mail::MailTransport, signing::TokenSigner, Database},
+};
 use actix_web::web::Data;
 use std::sync::Arc;
 
 pub type FullDatabase = Data<Arc<Database>>;
+pub type FullConfig = Data<Arc<Config>>;
+pub type FullHasher = Data<Arc<PasswordHasher>>;
+pub type FullSigner = Data<Arc<TokenSigner>>;
+pub type FullMailer = Data<MailTransport>;
+pub type RiskScorer = Arc<dyn SessionRiskScorer>;
+pub type RateLimiter = Arc<dyn RateLimitStore>;
+pub type FullNotifier = Data<Arc<dyn LockoutNotifier>>;
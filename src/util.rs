@@ -1,9 +1,21 @@
 pub mod actix;
+pub mod codes;
 pub mod data;
 pub mod hashing;
+pub mod lockout;
+pub mod mail;
 pub mod math;
+pub mod mfa;
+pub mod oauth;
 pub mod parse;
 pub mod random;
+pub mod rate_limit;
+pub mod risk;
 pub mod sessions;
+pub mod signing;
+pub mod totp;
+pub mod validation;
+pub mod webauthn;
+pub mod workers;
 
 pub use data::Database;
@@ -1,3 +1,9 @@
+pub mod either;
 pub mod path;
+pub mod redirect;
+pub mod with_cookie;
 
+pub use either::Either;
 pub use path::Path;
+pub use redirect::Redirect;
+pub use with_cookie::WithCookie;
@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+
+use actix_web::{body::BoxBody, HttpRequest, HttpResponse, Responder};
+use paperclip::{
+    actix::OperationModifier,
+    v2::{
+        models::{DefaultOperationRaw, DefaultSchemaRaw, SecurityScheme},
+        schema::Apiv2Schema,
+    },
+};
+
+/// One of two responses, documented as both.
+pub enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+impl<L, R> Responder for Either<L, R>
+where
+    L: Responder,
+    R: Responder,
+{
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        match self {
+            Either::Left(left) => left.respond_to(req).map_into_boxed_body(),
+            Either::Right(right) => right.respond_to(req).map_into_boxed_body(),
+        }
+    }
+}
+
+impl<L, R> Apiv2Schema for Either<L, R> {}
+
+impl<L, R> OperationModifier for Either<L, R>
+where
+    L: OperationModifier,
+    R: OperationModifier,
+{
+    fn update_parameter(op: &mut DefaultOperationRaw) {
+        L::update_parameter(op);
+        R::update_parameter(op);
+    }
+
+    fn update_response(op: &mut DefaultOperationRaw) {
+        L::update_response(op);
+        R::update_response(op);
+    }
+
+    fn update_definitions(map: &mut BTreeMap<String, DefaultSchemaRaw>) {
+        L::update_definitions(map);
+        R::update_definitions(map);
+    }
+
+    fn update_security(op: &mut DefaultOperationRaw) {
+        L::update_security(op);
+        R::update_security(op);
+    }
+
+    fn update_security_definitions(map: &mut BTreeMap<String, SecurityScheme>) {
+        L::update_security_definitions(map);
+        R::update_security_definitions(map);
+    }
+}
@@ -0,0 +1,42 @@
+use actix_web::{body::BoxBody, http::header, HttpRequest, HttpResponse, Responder};
+use paperclip::{
+    actix::OperationModifier,
+    v2::{
+        models::{DataType, DefaultOperationRaw, Either, Header, Response},
+        schema::Apiv2Schema,
+    },
+};
+
+/// Send the client to another URL with `302 Found`.
+pub struct Redirect(pub String);
+
+impl Responder for Redirect {
+    type Body = BoxBody;
+
+    fn respond_to(self, _: &HttpRequest) -> HttpResponse<Self::Body> {
+        HttpResponse::Found()
+            .insert_header((header::LOCATION, self.0))
+            .finish()
+    }
+}
+
+impl Apiv2Schema for Redirect {}
+
+impl OperationModifier for Redirect {
+    fn update_response(op: &mut DefaultOperationRaw) {
+        let location = Header {
+            description: Some("The URL the client is sent to.".to_string()),
+            data_type: Some(DataType::String),
+            ..Default::default()
+        };
+
+        op.responses.insert(
+            "302".to_string(),
+            Either::Right(Response {
+                description: Some("Found".to_string()),
+                schema: None,
+                headers: [("Location".to_string(), location)].into_iter().collect(),
+            }),
+        );
+    }
+}
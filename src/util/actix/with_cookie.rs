@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+
+use actix_web::{body::BoxBody, cookie::Cookie, HttpRequest, HttpResponse, Responder};
+use paperclip::{
+    actix::OperationModifier,
+    v2::{
+        models::{DefaultOperationRaw, DefaultSchemaRaw, SecurityScheme},
+        schema::Apiv2Schema,
+    },
+};
+
+/// A response that may also set a cookie, documented as the response it wraps.
+pub struct WithCookie<T>(pub T, pub Option<Cookie<'static>>);
+
+impl<T> Responder for WithCookie<T>
+where
+    T: Responder,
+{
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        let mut res = self.0.respond_to(req).map_into_boxed_body();
+        if let Some(cookie) = self.1 {
+            if let Err(e) = res.add_cookie(&cookie) {
+                log::error!("Could not set the {} cookie: {}", cookie.name(), e);
+            }
+        }
+        res
+    }
+}
+
+impl<T> Apiv2Schema for WithCookie<T>
+where
+    T: Apiv2Schema,
+{
+    fn name() -> Option<String> {
+        T::name()
+    }
+
+    fn description() -> &'static str {
+        T::description()
+    }
+
+    fn required() -> bool {
+        T::required()
+    }
+
+    fn raw_schema() -> DefaultSchemaRaw {
+        T::raw_schema()
+    }
+}
+
+impl<T> OperationModifier for WithCookie<T>
+where
+    T: OperationModifier,
+{
+    fn update_parameter(op: &mut DefaultOperationRaw) {
+        T::update_parameter(op);
+    }
+
+    fn update_response(op: &mut DefaultOperationRaw) {
+        T::update_response(op);
+    }
+
+    fn update_definitions(map: &mut BTreeMap<String, DefaultSchemaRaw>) {
+        T::update_definitions(map);
+    }
+
+    fn update_security(op: &mut DefaultOperationRaw) {
+        T::update_security(op);
+    }
+
+    fn update_security_definitions(map: &mut BTreeMap<String, SecurityScheme>) {
+        T::update_security_definitions(map);
+    }
+}
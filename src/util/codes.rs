@@ -0,0 +1,190 @@
+// Single-use codes that are mailed to users as links, such as verification links.
+// A `CodePurpose` describes one kind of link, the endpoints that use it only add
+// what the link is for.
+//
+// Codes are kept in temporary storage, so they expire on their own. Every purpose
+// uses its own keys:
+// - `<prefix>:<hash of code>` contains the user as JSON, so the code is never
+//   stored and `drop_all(user_id)` does not remove it when the user signs out.
+// - `<prefix>-code:<user_id>` contains the hash of the latest code, so the
+//   previous code stops working when a new one is issued.
+// - `<prefix>-resend:<user_id>` contains the time another code may be mailed.
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    errors::{HttpError, StorageError, StorageResult},
+    structs::{mail::Mail, user::FullUser},
+    traits::Mailer,
+    util::{hashing::sha256_hex, random::random_string, Database},
+};
+
+/// A kind of link that is mailed to users.
+pub struct CodePurpose {
+    /// The prefix of the keys of these codes.
+    pub prefix: &'static str,
+    /// The page of the site the link opens, the code is added as `?code=`.
+    pub path: &'static str,
+    /// The amount of seconds a code can be used.
+    pub ttl: fn(&Config) -> usize,
+    /// The amount of seconds before another code is mailed to the same user.
+    pub resend_interval: fn(&Config) -> usize,
+    /// The mail for a user with the link, and the amount of seconds it can be used.
+    pub mail: fn(&FullUser, &str, usize) -> Mail,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingCode {
+    user_id: Uuid,
+}
+
+fn code_key(prefix: &str, hash: &str) -> String {
+    format!("{}:{}", prefix, hash)
+}
+
+fn latest_key(prefix: &str, user_id: Uuid) -> String {
+    format!("{}-code:{}", prefix, user_id)
+}
+
+fn resend_key(prefix: &str, user_id: Uuid) -> String {
+    format!("{}-resend:{}", prefix, user_id)
+}
+
+fn now() -> u64 {
+    Utc::now().timestamp() as u64
+}
+
+/// Store a new code for a user that expires after `ttl` seconds, and remove the
+/// code that was issued before.
+async fn issue(db: &Database, prefix: &str, ttl: usize, user_id: Uuid) -> StorageResult<String> {
+    if let Some(previous) = db.temporary.get(latest_key(prefix, user_id)).await? {
+        db.temporary.delete(code_key(prefix, &previous)).await?;
+    }
+
+    let code = random_string(64);
+    let hash = sha256_hex(&code);
+    let pending = serde_json::to_string(&PendingCode { user_id })
+        .map_err(|e| StorageError::Conflict(e.to_string()))?;
+
+    db.temporary
+        .set_with_ttl(code_key(prefix, &hash), pending, ttl)
+        .await?;
+    db.temporary
+        .set_with_ttl(latest_key(prefix, user_id), hash, ttl)
+        .await?;
+
+    Ok(code)
+}
+
+async fn pending(db: &Database, prefix: &str, hash: &str) -> StorageResult<Option<PendingCode>> {
+    match db.temporary.get(code_key(prefix, hash)).await? {
+        Some(pending) => serde_json::from_str(&pending)
+            .map(Some)
+            .map_err(|e| StorageError::Conflict(format!("Malformed {} data: {}", prefix, e))),
+        None => Ok(None),
+    }
+}
+
+/// The user a code belongs to, without using it. `None` if the code expired, was
+/// replaced or has been used.
+pub async fn peek(db: &Database, purpose: &CodePurpose, code: &str) -> StorageResult<Option<Uuid>> {
+    Ok(pending(db, purpose.prefix, &sha256_hex(code))
+        .await?
+        .map(|pending| pending.user_id))
+}
+
+/// Use a code, returns the user it belongs to. `None` if the code expired, was
+/// replaced or has been used.
+pub async fn redeem(
+    db: &Database,
+    purpose: &CodePurpose,
+    code: &str,
+) -> StorageResult<Option<Uuid>> {
+    let hash = sha256_hex(code);
+    let pending = match pending(db, purpose.prefix, &hash).await? {
+        Some(pending) => pending,
+        None => return Ok(None),
+    };
+
+    db.temporary.delete(code_key(purpose.prefix, &hash)).await?;
+    db.temporary
+        .delete(latest_key(purpose.prefix, pending.user_id))
+        .await?;
+
+    Ok(Some(pending.user_id))
+}
+
+/// Do not mail another code to a user for `interval` seconds.
+async fn throttle(
+    db: &Database,
+    prefix: &str,
+    user_id: Uuid,
+    interval: usize,
+) -> StorageResult<()> {
+    if interval == 0 {
+        return Ok(());
+    }
+
+    db.temporary
+        .set_with_ttl(
+            resend_key(prefix, user_id),
+            (now() + interval as u64).to_string(),
+            interval,
+        )
+        .await
+}
+
+/// The amount of seconds until another code can be mailed to a user, `None` if
+/// it can be mailed now.
+pub async fn retry_after(
+    db: &Database,
+    purpose: &CodePurpose,
+    user_id: Uuid,
+) -> StorageResult<Option<u64>> {
+    let allowed_at = match db
+        .temporary
+        .get(resend_key(purpose.prefix, user_id))
+        .await?
+    {
+        Some(allowed_at) => allowed_at.parse::<u64>().unwrap_or(0),
+        None => return Ok(None),
+    };
+
+    let now = now();
+    Ok((allowed_at > now).then(|| allowed_at - now))
+}
+
+/// Mail a new link to a user, links that were sent before stop working. Nothing is
+/// sent when a link was sent within the resend interval.
+pub async fn send_link(
+    db: &Database,
+    mailer: &dyn Mailer,
+    config: &Config,
+    purpose: &CodePurpose,
+    user: &FullUser,
+) -> Result<(), HttpError> {
+    if retry_after(db, purpose, user.id).await?.is_some() {
+        return Ok(());
+    }
+
+    let ttl = (purpose.ttl)(config);
+    let code = issue(db, purpose.prefix, ttl, user.id).await?;
+    let link = format!(
+        "{}{}?code={}",
+        config.site.base_url.trim_end_matches('/'),
+        purpose.path,
+        code
+    );
+    mailer.send((purpose.mail)(user, &link, ttl)).await?;
+    throttle(
+        db,
+        purpose.prefix,
+        user.id,
+        (purpose.resend_interval)(config),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -1,19 +1,17 @@
 mod providers;
 
-use std::sync::Mutex;
-
 pub use providers::*;
 
 pub struct Database {
-    pub persistent: Mutex<PersistentStorage>,
-    pub temporary: Mutex<TemporaryStorage>,
+    pub persistent: PersistentStorage,
+    pub temporary: TemporaryStorage,
 }
 
 impl Database {
     pub fn new(persistent: PersistentStorage, temporary: TemporaryStorage) -> Self {
         Self {
-            persistent: Mutex::new(persistent),
-            temporary: Mutex::new(temporary),
+            persistent,
+            temporary,
         }
     }
 }
@@ -1,9 +1,57 @@
 mod firefly;
-// mod in_memory;
+mod in_memory;
 mod scylla;
 
-// This allows us to easily swap the data provider.
-// pub use in_memory::InMemoryDataProvider as PersistentStorage;
-pub use self::scylla::ScyllaDataProvider as PersistentStorage;
-// pub use in_memory::InMemoryDataProvider as TemporaryStorage;
-pub use self::firefly::FireflyDataProvider as TemporaryStorage;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    traits::{PersistentStorageProvider, TemporaryStorageProvider},
+};
+
+pub use self::firefly::FireflyDataProvider;
+pub use self::in_memory::InMemoryDataProvider;
+pub use self::scylla::ScyllaDataProvider;
+
+pub type PersistentStorage = Box<dyn PersistentStorageProvider>;
+pub type TemporaryStorage = Box<dyn TemporaryStorageProvider>;
+
+/// The backends that can be used to store persistent data.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PersistentStorageKind {
+    Scylla,
+    #[serde(alias = "memory")]
+    InMemory,
+}
+
+/// The backends that can be used to store temporary data.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TemporaryStorageKind {
+    Firefly,
+    #[serde(alias = "memory")]
+    InMemory,
+}
+
+impl PersistentStorageKind {
+    /// Connect to the selected backend.
+    pub async fn connect(self, config: &Config) -> PersistentStorage {
+        match self {
+            Self::Scylla => Box::new(ScyllaDataProvider::new(&config.scylla).await),
+            Self::InMemory => Box::new(InMemoryDataProvider::new()),
+        }
+    }
+}
+
+impl TemporaryStorageKind {
+    /// Connect to the selected backend.
+    pub async fn connect(self, config: &Config) -> TemporaryStorage {
+        match self {
+            Self::Firefly => {
+                Box::new(FireflyDataProvider::new(&config.firefly, config.session.ttl).await)
+            }
+            Self::InMemory => Box::new(InMemoryDataProvider::with_ttl(config.session.ttl)),
+        }
+    }
+}
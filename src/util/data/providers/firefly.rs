@@ -1,44 +1,120 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
 use async_trait::async_trait;
-use ffly_rs::FireflyStream;
+use ffly_rs::{FireflyError, FireflyStream, GenericError};
+use tokio::sync::Mutex;
 
-use crate::{constants::TTL, traits::TemporaryStorageProvider};
+use crate::{
+    config::FireflyConfig,
+    errors::{StorageError, StorageResult},
+    traits::TemporaryStorageProvider,
+};
 
+/// A FireflyStream handles one query at a time, so a pool of connections is
+/// used to let requests query Firefly concurrently.
 pub struct FireflyDataProvider {
-    stream: FireflyStream,
+    streams: Vec<FireflyStream>,
+    next: AtomicUsize,
+    /// Firefly can not set a key only when it is missing, so `set_if_absent` is
+    /// a get and a set under this lock. It is only atomic within this instance.
+    claims: Mutex<()>,
 }
 
 impl FireflyDataProvider {
-    pub async fn new() -> Self {
-        // TODO: Fetch from env
-        let address = "127.0.0.1:46600";
-        let mut stream = FireflyStream::connect(address)
-            .await
-            .expect("Could not connect to Firefly database");
+    pub async fn new(config: &FireflyConfig, ttl: usize) -> Self {
+        let mut streams = Vec::with_capacity(config.connections);
+
+        for _ in 0..config.connections {
+            let mut stream =
+                FireflyStream::connect_with_max_buffer(&config.address, config.max_buffer)
+                    .await
+                    .expect("Could not connect to Firefly database");
 
-        stream.default_ttl = TTL;
+            stream.default_ttl = ttl;
+            streams.push(stream);
+        }
 
-        FireflyDataProvider { stream }
+        FireflyDataProvider {
+            streams,
+            next: AtomicUsize::new(0),
+            claims: Mutex::new(()),
+        }
     }
+
+    /// Get the next connection of the pool.
+    fn stream(&self) -> &FireflyStream {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.streams.len();
+        &self.streams[index]
+    }
+}
+
+/// Firefly answers with an error message when a key does not exist, every
+/// other error means that we could not talk to the server.
+fn is_missing(error: &GenericError) -> bool {
+    error.downcast_ref::<FireflyError>().is_some()
+}
+
+fn unavailable(error: GenericError) -> StorageError {
+    StorageError::Unavailable(format!("Firefly: {}", error))
 }
 
 #[async_trait]
 impl TemporaryStorageProvider for FireflyDataProvider {
-    async fn get(&self, key: String) -> Option<String> {
-        match self.stream.get_value(&key).await {
-            Ok(value) => Some(value),
-            Err(_) => None,
+    async fn get(&self, key: String) -> StorageResult<Option<String>> {
+        match self.stream().get_value(&key).await {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if is_missing(&e) => Ok(None),
+            Err(e) => Err(unavailable(e)),
         }
     }
 
-    async fn set(&self, key: String, value: String) -> bool {
-        self.stream.new(&key, &value).await.is_ok()
+    async fn set(&self, key: String, value: String) -> StorageResult<()> {
+        self.stream().new(&key, &value).await.map_err(unavailable)
     }
 
-    async fn delete(&self, key: String) -> bool {
-        self.stream.drop(&key).await.is_ok()
+    async fn set_with_ttl(&self, key: String, value: String, ttl: usize) -> StorageResult<()> {
+        // Firefly expects the moment the key expires, as seconds since the epoch.
+        let expires_at = match ttl {
+            0 => 0,
+            ttl => {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as usize
+                    + ttl
+            }
+        };
+
+        self.stream()
+            .new_with_ttl(&key, &value, expires_at)
+            .await
+            .map_err(unavailable)
+    }
+
+    async fn set_if_absent(&self, key: String, value: String, ttl: usize) -> StorageResult<bool> {
+        let _claim = self.claims.lock().await;
+        if self.get(key.clone()).await?.is_some() {
+            return Ok(false);
+        }
+
+        self.set_with_ttl(key, value, ttl).await?;
+        Ok(true)
     }
 
-    async fn drop_all(&self, value: String) -> bool {
-        self.stream.drop_values(&value).await.is_ok()
+    async fn delete(&self, key: String) -> StorageResult<()> {
+        match self.stream().drop(&key).await {
+            Err(e) if !is_missing(&e) => Err(unavailable(e)),
+            _ => Ok(()),
+        }
+    }
+
+    async fn drop_all(&self, value: String) -> StorageResult<()> {
+        match self.stream().drop_values(&value).await {
+            Err(e) if !is_missing(&e) => Err(unavailable(e)),
+            _ => Ok(()),
+        }
     }
 }
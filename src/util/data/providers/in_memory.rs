@@ -4,87 +4,251 @@
 // This should be used for testing purposes only. (not suitable for production)
 
 use async_trait::async_trait;
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
 use uuid::Uuid;
 
 use crate::{
-    structs::user::FullUser,
+    constants::TTL,
+    errors::{StorageError, StorageResult},
+    structs::user::{FullUser, UserPage},
     traits::{PersistentStorageProvider, TemporaryStorageProvider},
 };
 
+struct TemporaryEntry {
+    value: String,
+    expires_at: Option<Instant>,
+}
+
+impl TemporaryEntry {
+    fn is_expired(&self) -> bool {
+        matches!(self.expires_at, Some(expires_at) if expires_at <= Instant::now())
+    }
+}
+
 pub struct InMemoryDataProvider {
-    users: HashMap<Uuid, FullUser>,
-    sessions: HashMap<String, String>,
+    users: RwLock<HashMap<Uuid, FullUser>>,
+    sessions: RwLock<HashMap<String, TemporaryEntry>>,
+    /// The amount of seconds a temporary key lives, 0 means forever.
+    default_ttl: usize,
 }
 
 impl InMemoryDataProvider {
     pub fn new() -> Self {
+        Self::with_ttl(TTL)
+    }
+
+    pub fn with_ttl(default_ttl: usize) -> Self {
         Self {
-            users: HashMap::new(),
-            sessions: HashMap::new(),
+            users: RwLock::new(HashMap::new()),
+            sessions: RwLock::new(HashMap::new()),
+            default_ttl,
+        }
+    }
+
+    fn find_user(&self, predicate: impl Fn(&FullUser) -> bool) -> Option<FullUser> {
+        self.users
+            .read()
+            .unwrap()
+            .values()
+            .find(|user| predicate(user))
+            .cloned()
+    }
+
+    fn update_user(&self, id: Uuid, update: impl FnOnce(&mut FullUser)) -> StorageResult<()> {
+        match self.users.write().unwrap().get_mut(&id) {
+            Some(user) => {
+                update(user);
+                Ok(())
+            }
+            None => Err(StorageError::NotFound),
         }
     }
 }
 
+impl Default for InMemoryDataProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[async_trait]
 impl PersistentStorageProvider for InMemoryDataProvider {
-    async fn does_username_exist(&self, username: String) -> bool {
-        self.users
-            .values()
-            .any(|user| user.username == username.to_string())
+    async fn get_user_by_username(&self, username: String) -> StorageResult<Option<FullUser>> {
+        Ok(self.find_user(|user| user.username == username))
     }
 
-    async fn does_email_exist(&self, email: String) -> bool {
-        self.users
+    async fn get_user_by_email(&self, email: String) -> StorageResult<Option<FullUser>> {
+        Ok(self.find_user(|user| user.email == email))
+    }
+
+    async fn get_user_by_id(&self, id: Uuid) -> StorageResult<Option<FullUser>> {
+        Ok(self.users.read().unwrap().get(&id).cloned())
+    }
+
+    async fn get_user_by_authentication(
+        &self,
+        method: i16,
+        value: String,
+    ) -> StorageResult<Option<FullUser>> {
+        Ok(self.find_user(|user| user.authentication.get(&method) == Some(&value)))
+    }
+
+    async fn list_users(
+        &self,
+        search: Option<String>,
+        after: Option<Uuid>,
+        limit: usize,
+    ) -> StorageResult<UserPage> {
+        let search = search.map(|search| search.to_lowercase());
+        let users = self.users.read().unwrap();
+        let mut matches: Vec<&FullUser> = users
             .values()
-            .any(|user| user.email == email.to_string())
+            .filter(|user| after.is_none_or(|after| user.id > after))
+            .filter(|user| search.as_deref().is_none_or(|search| user.matches(search)))
+            .collect();
+        matches.sort_by_key(|user| user.id);
+
+        let next = (limit > 0 && matches.len() > limit).then(|| matches[limit - 1].id);
+        Ok(UserPage {
+            users: matches.into_iter().take(limit).cloned().collect(),
+            next,
+        })
     }
 
-    async fn register_user(&mut self, user: FullUser) -> Result<(), String> {
-        if self.does_username_exist(user.username.clone()).await {
-            return Err("User already exists".to_string());
-        } else if self.does_email_exist(user.email.clone()).await {
-            return Err("Email already exists".to_string());
+    async fn does_username_exist(&self, username: String) -> StorageResult<bool> {
+        Ok(self.find_user(|user| user.username == username).is_some())
+    }
+
+    async fn does_email_exist(&self, email: String) -> StorageResult<bool> {
+        Ok(self.find_user(|user| user.email == email).is_some())
+    }
+
+    async fn register_user(&self, user: FullUser) -> StorageResult<()> {
+        let mut users = self.users.write().unwrap();
+
+        if users.values().any(|u| u.username == user.username) {
+            return Err(StorageError::UsernameTaken);
+        } else if users.values().any(|u| u.email == user.email) {
+            return Err(StorageError::EmailTaken);
         }
 
-        self.users.insert(user.id, user);
+        users.insert(user.id, user);
         Ok(())
     }
 
-    async fn get_user_by_id(&self, id: Uuid) -> Option<FullUser> {
-        self.users.get(&id).cloned()
+    async fn delete_user(&self, id: Uuid) -> StorageResult<()> {
+        match self.users.write().unwrap().remove(&id) {
+            Some(_) => Ok(()),
+            None => Err(StorageError::NotFound),
+        }
     }
 
-    async fn delete_user(&mut self, id: Uuid) -> Result<(), String> {
-        let user = self.get_user_by_id(id).await;
-        match user {
-            Some(user) => {
-                self.users.remove(&user.id);
-                Ok(())
-            }
-            None => Err("User does not exist".to_string()),
+    async fn verify_user(&self, id: Uuid) -> StorageResult<()> {
+        self.update_user(id, |user| user.verification_token = None)
+    }
+
+    async fn set_roles(&self, id: Uuid, roles: usize) -> StorageResult<()> {
+        self.update_user(id, |user| user.roles = roles)
+    }
+
+    async fn set_disabled(&self, id: Uuid, disabled: bool) -> StorageResult<()> {
+        self.update_user(id, |user| user.disabled = disabled)
+    }
+
+    async fn remove_authentication_method(&self, id: Uuid, method: i16) -> StorageResult<()> {
+        self.update_user(id, |user| {
+            user.authentication.remove(&method);
+        })
+    }
+
+    async fn update_authentication_method_value(
+        &self,
+        id: Uuid,
+        method: i16,
+        new_value: &str,
+    ) -> StorageResult<()> {
+        self.update_user(id, |user| {
+            user.authentication.insert(method, new_value.to_string());
+        })
+    }
+
+    async fn get_authentication_methods(&self, id: Uuid) -> StorageResult<Vec<i16>> {
+        match self.users.read().unwrap().get(&id) {
+            Some(user) => Ok(user.authentication.keys().cloned().collect()),
+            None => Err(StorageError::NotFound),
         }
     }
 }
 
 #[async_trait]
 impl TemporaryStorageProvider for InMemoryDataProvider {
-    async fn get(&mut self, key: String) -> Option<String> {
-        self.sessions.get(&key).cloned()
+    async fn get(&self, key: String) -> StorageResult<Option<String>> {
+        let sessions = self.sessions.read().unwrap();
+        match sessions.get(&key) {
+            Some(entry) if !entry.is_expired() => Ok(Some(entry.value.clone())),
+            Some(_) => {
+                drop(sessions);
+                // Another task can set the key before the write lock is taken.
+                let mut sessions = self.sessions.write().unwrap();
+                match sessions.get(&key) {
+                    Some(entry) if !entry.is_expired() => Ok(Some(entry.value.clone())),
+                    Some(_) => {
+                        sessions.remove(&key);
+                        Ok(None)
+                    }
+                    None => Ok(None),
+                }
+            }
+            None => Ok(None),
+        }
     }
 
-    async fn set(&mut self, key: String, value: String) -> bool {
-        self.sessions.insert(key, value);
-        true
+    async fn set(&self, key: String, value: String) -> StorageResult<()> {
+        self.set_with_ttl(key, value, self.default_ttl).await
     }
 
-    async fn delete(&mut self, key: String) -> bool {
-        self.sessions.remove(&key);
-        true
+    async fn set_with_ttl(&self, key: String, value: String, ttl: usize) -> StorageResult<()> {
+        let expires_at = match ttl {
+            0 => None,
+            ttl => Some(Instant::now() + Duration::from_secs(ttl as u64)),
+        };
+
+        self.sessions
+            .write()
+            .unwrap()
+            .insert(key, TemporaryEntry { value, expires_at });
+        Ok(())
     }
 
-    async fn drop_all(&mut self, value: String) -> bool {
-        self.sessions.retain(|_, v| v != &value);
-        true
+    async fn set_if_absent(&self, key: String, value: String, ttl: usize) -> StorageResult<bool> {
+        let expires_at = match ttl {
+            0 => None,
+            ttl => Some(Instant::now() + Duration::from_secs(ttl as u64)),
+        };
+
+        let mut sessions = self.sessions.write().unwrap();
+        if matches!(sessions.get(&key), Some(entry) if !entry.is_expired()) {
+            return Ok(false);
+        }
+
+        sessions.insert(key, TemporaryEntry { value, expires_at });
+        Ok(true)
+    }
+
+    async fn delete(&self, key: String) -> StorageResult<()> {
+        self.sessions.write().unwrap().remove(&key);
+        Ok(())
+    }
+
+    async fn drop_all(&self, value: String) -> StorageResult<()> {
+        self.sessions
+            .write()
+            .unwrap()
+            .retain(|_, entry| entry.value != value && !entry.is_expired());
+        Ok(())
     }
 }
@@ -4,11 +4,16 @@ use async_trait::async_trait;
 use chrono::Duration;
 use scylla::{
     frame::value::ValueList, prepared_statement::PreparedStatement, FromRow, IntoTypedRows,
-    Session, SessionBuilder,
+    QueryResult, Session, SessionBuilder,
 };
 use uuid::Uuid;
 
-use crate::{structs::user::FullUser, traits::PersistentStorageProvider};
+use crate::{
+    config::ScyllaConfig,
+    errors::{StorageError, StorageResult},
+    structs::user::{FullUser, UserPage},
+    traits::PersistentStorageProvider,
+};
 
 struct PreparedQueries {
     pub get_user: PreparedStatement,
@@ -20,8 +25,13 @@ struct PreparedQueries {
 
     pub get_user_from_username: PreparedStatement,
     pub get_user_from_email: PreparedStatement,
+    pub get_user_from_authentication: PreparedStatement,
+    pub list_users: PreparedStatement,
+    pub list_users_after: PreparedStatement,
 
     pub verify_user: PreparedStatement,
+    pub set_roles: PreparedStatement,
+    pub set_disabled: PreparedStatement,
 
     pub get_authentication_methods: PreparedStatement,
     pub update_authentication_method_value: PreparedStatement,
@@ -41,18 +51,36 @@ type UserRow = (
     Option<String>,
     Option<i16>,
     Option<HashMap<i16, String>>,
+    Option<bool>,
 );
 
-impl ScyllaDataProvider {
-    pub async fn new() -> Self {
-        // TODO: Get from .env
-        let uri = "0.0.0.0:9042";
-        let username = "cassandra";
-        let password = "cassandra";
+/// The amount of users that is read at once when listing users.
+const SCAN_BATCH: i32 = 500;
+/// The amount of users that is read at most for a page. A search that matches few
+/// users returns what it found so far, with a cursor to continue from.
+const MAX_SCANNED: usize = 10 * SCAN_BATCH as usize;
+
+fn to_full_user(row: UserRow) -> FullUser {
+    let (id, username, email, created_at, verification_token, roles, authentication, disabled) =
+        row;
+
+    FullUser {
+        id,
+        username,
+        email,
+        created_at: Duration::seconds(created_at),
+        verification_token,
+        roles: roles.unwrap_or_default() as usize,
+        authentication: authentication.unwrap_or_default(),
+        disabled: disabled.unwrap_or_default(),
+    }
+}
 
+impl ScyllaDataProvider {
+    pub async fn new(config: &ScyllaConfig) -> Self {
         let session = SessionBuilder::new()
-            .known_node(uri)
-            .user(username, password)
+            .known_node(&config.uri)
+            .user(&config.username, &config.password)
             .build()
             .await
             .expect("Failed to build scylla session");
@@ -64,7 +92,7 @@ impl ScyllaDataProvider {
         let prepared = PreparedQueries {
             get_user: prepare_query(
                 &session,
-                "SELECT id, username, email, created_at, verification_token, roles, authentication FROM accounts.users WHERE id = ?;",
+                "SELECT id, username, email, created_at, verification_token, roles, authentication, disabled FROM accounts.users WHERE id = ?;",
             )
             .await,
             get_id_from_username: prepare_query(&session, "SELECT id FROM accounts.users WHERE username = ? LIMIT 1;").await,
@@ -73,13 +101,31 @@ impl ScyllaDataProvider {
             delete_user: prepare_query(&session, "DELETE FROM accounts.users WHERE id = ?;").await,
             get_user_from_username: prepare_query(
                 &session,
-                "SELECT id, username, email, created_at, verification_token, roles, authentication FROM accounts.users WHERE username = ? LIMIT 1;",
+                "SELECT id, username, email, created_at, verification_token, roles, authentication, disabled FROM accounts.users WHERE username = ? LIMIT 1;",
             ).await,
             get_user_from_email: prepare_query(
                 &session,
-                "SELECT id, username, email, created_at, verification_token, roles, authentication FROM accounts.users WHERE email = ? LIMIT 1;",
+                "SELECT id, username, email, created_at, verification_token, roles, authentication, disabled FROM accounts.users WHERE email = ? LIMIT 1;",
+            ).await,
+            // Requires an index on the entries of the map:
+            // CREATE INDEX ON accounts.users (ENTRIES(authentication));
+            // Every user query reads the disabled column, existing tables need it added:
+            // ALTER TABLE accounts.users ADD disabled boolean;
+            get_user_from_authentication: prepare_query(
+                &session,
+                "SELECT id, username, email, created_at, verification_token, roles, authentication, disabled FROM accounts.users WHERE authentication[?] = ? LIMIT 1;",
+            ).await,
+            list_users: prepare_query(
+                &session,
+                "SELECT id, username, email, created_at, verification_token, roles, authentication, disabled FROM accounts.users LIMIT ?;",
+            ).await,
+            list_users_after: prepare_query(
+                &session,
+                "SELECT id, username, email, created_at, verification_token, roles, authentication, disabled FROM accounts.users WHERE token(id) > token(?) LIMIT ?;",
             ).await,
             verify_user: prepare_query(&session, "UPDATE accounts.users SET verification_token = null WHERE id = ?;").await,
+            set_roles: prepare_query(&session, "UPDATE accounts.users SET roles = ? WHERE id = ?;").await,
+            set_disabled: prepare_query(&session, "UPDATE accounts.users SET disabled = ? WHERE id = ?;").await,
 
             get_authentication_methods: prepare_query(&session, "SELECT authentication FROM accounts.users WHERE id = ? LIMIT 1;").await,
             update_authentication_method_value: prepare_query(&session, "UPDATE accounts.users SET authentication[?] = ? WHERE id = ?;").await,
@@ -89,153 +135,202 @@ impl ScyllaDataProvider {
         ScyllaDataProvider { session, prepared }
     }
 
-    async fn exists(&self, prepared: &PreparedStatement, args: impl ValueList) -> bool {
+    async fn execute(
+        &self,
+        prepared: &PreparedStatement,
+        args: impl ValueList,
+    ) -> StorageResult<QueryResult> {
         self.session
             .execute(prepared, args)
             .await
-            .unwrap()
-            .rows
-            .unwrap()
-            .len()
-            == 1
+            .map_err(|e| StorageError::Unavailable(format!("Scylla: {}", e)))
     }
 
-    async fn get_first<T: FromRow>(
+    async fn exists(
         &self,
         prepared: &PreparedStatement,
         args: impl ValueList,
-    ) -> Option<T> {
-        let res = self.session.execute(prepared, args).await;
+    ) -> StorageResult<bool> {
+        let query = self.execute(prepared, args).await?;
 
-        if let Ok(query) = res {
-            if let Some(rows) = query.rows {
-                if let Some(row) = rows.into_typed::<T>().next() {
-                    return Some(row.unwrap());
-                }
-            }
-        }
+        Ok(query.rows.map(|rows| rows.len() == 1).unwrap_or(false))
+    }
 
-        None
+    async fn get_first<T: FromRow>(
+        &self,
+        prepared: &PreparedStatement,
+        args: impl ValueList,
+    ) -> StorageResult<Option<T>> {
+        let query = self.execute(prepared, args).await?;
+
+        match query.rows.and_then(|rows| rows.into_typed::<T>().next()) {
+            Some(row) => row
+                .map(Some)
+                .map_err(|e| StorageError::Conflict(format!("Scylla: {}", e))),
+            None => Ok(None),
+        }
     }
 
     async fn user_query(
         &self,
         prepared: &PreparedStatement,
         args: impl ValueList,
-    ) -> Option<FullUser> {
-        let res: Option<UserRow> = self.get_first(prepared, args).await;
-
-        if let Some(row) = res {
-            let (id, username, email, created_at, verification_token, roles, authentication) = row;
-
-            return Some(FullUser {
-                id,
-                username,
-                email,
-                created_at: Duration::seconds(created_at),
-                verification_token,
-                roles: roles.unwrap_or_default() as usize,
-                authentication: authentication.unwrap_or_default(),
-            });
-        }
+    ) -> StorageResult<Option<FullUser>> {
+        let res: Option<UserRow> = self.get_first(prepared, args).await?;
 
-        None
+        Ok(res.map(to_full_user))
     }
 }
 
 #[async_trait]
 impl PersistentStorageProvider for ScyllaDataProvider {
-    async fn get_user_by_id(&self, id: Uuid) -> Option<FullUser> {
+    async fn get_user_by_id(&self, id: Uuid) -> StorageResult<Option<FullUser>> {
         self.user_query(&self.prepared.get_user, (id,)).await
     }
 
-    async fn does_username_exist(&self, username: String) -> bool {
+    /// Scylla can not search inside values, so the users are read in batches in
+    /// token order and filtered here.
+    async fn list_users(
+        &self,
+        search: Option<String>,
+        after: Option<Uuid>,
+        limit: usize,
+    ) -> StorageResult<UserPage> {
+        let search = search.map(|search| search.to_lowercase());
+        let mut users = Vec::new();
+        let mut cursor = after;
+        let mut total = 0;
+
+        while users.len() < limit {
+            let query = match cursor {
+                Some(id) => {
+                    self.execute(&self.prepared.list_users_after, (id, SCAN_BATCH))
+                        .await?
+                }
+                None => {
+                    self.execute(&self.prepared.list_users, (SCAN_BATCH,))
+                        .await?
+                }
+            };
+            let rows = query.rows.unwrap_or_default();
+            let scanned = rows.len();
+
+            for row in rows.into_typed::<UserRow>() {
+                let user = to_full_user(
+                    row.map_err(|e| StorageError::Conflict(format!("Scylla: {}", e)))?,
+                );
+                cursor = Some(user.id);
+
+                if search.as_deref().is_none_or(|search| user.matches(search)) {
+                    users.push(user);
+                    if users.len() == limit {
+                        return Ok(UserPage {
+                            users,
+                            next: cursor,
+                        });
+                    }
+                }
+            }
+
+            if scanned < SCAN_BATCH as usize {
+                break;
+            }
+
+            total += scanned;
+            if total >= MAX_SCANNED {
+                return Ok(UserPage {
+                    users,
+                    next: cursor,
+                });
+            }
+        }
+
+        Ok(UserPage { users, next: None })
+    }
+
+    async fn does_username_exist(&self, username: String) -> StorageResult<bool> {
         self.exists(&self.prepared.get_id_from_username, (username,))
             .await
     }
 
-    async fn does_email_exist(&self, email: String) -> bool {
+    async fn does_email_exist(&self, email: String) -> StorageResult<bool> {
         self.exists(&self.prepared.get_id_from_email, (email,))
             .await
     }
 
-    async fn register_user(&self, user: FullUser) -> Result<(), String> {
-        if self.does_username_exist(user.username.clone()).await {
-            return Err("User already exists".to_string());
-        } else if self.does_email_exist(user.email.clone()).await {
-            return Err("Email already exists".to_string());
+    async fn register_user(&self, user: FullUser) -> StorageResult<()> {
+        if self.does_username_exist(user.username.clone()).await? {
+            return Err(StorageError::UsernameTaken);
+        } else if self.does_email_exist(user.email.clone()).await? {
+            return Err(StorageError::EmailTaken);
         }
 
-        match self
-            .session
-            .execute(
-                &self.prepared.create_user,
-                (
-                    user.id,
-                    user.username,
-                    user.email,
-                    user.created_at.num_seconds(),
-                    user.authentication,
-                    user.verification_token,
-                ),
-            )
-            .await
-        {
-            Ok(_) => Ok(()),
-            Err(_) => Err("Failed to create user.".to_string()),
-        }
+        self.execute(
+            &self.prepared.create_user,
+            (
+                user.id,
+                user.username,
+                user.email,
+                user.created_at.num_seconds(),
+                user.authentication,
+                user.verification_token,
+            ),
+        )
+        .await?;
+
+        Ok(())
     }
 
-    async fn delete_user(&self, id: Uuid) -> Result<(), String> {
-        match self
-            .session
-            .execute(&self.prepared.delete_user, (id,))
-            .await
-        {
-            Ok(_) => Ok(()),
-            Err(_) => Err("Failed to delete user".to_string()),
-        }
+    async fn delete_user(&self, id: Uuid) -> StorageResult<()> {
+        self.execute(&self.prepared.delete_user, (id,)).await?;
+        Ok(())
     }
 
-    async fn get_user_by_username(&self, username: String) -> Option<FullUser> {
+    async fn get_user_by_username(&self, username: String) -> StorageResult<Option<FullUser>> {
         self.user_query(&self.prepared.get_user_from_username, (username,))
             .await
     }
 
-    async fn get_user_by_email(&self, email: String) -> Option<FullUser> {
+    async fn get_user_by_email(&self, email: String) -> StorageResult<Option<FullUser>> {
         self.user_query(&self.prepared.get_user_from_email, (email,))
             .await
     }
 
-    async fn verify_user(&self, id: Uuid) -> Result<(), String> {
-        match self
-            .session
-            .execute(&self.prepared.verify_user, (id,))
+    async fn get_user_by_authentication(
+        &self,
+        method: i16,
+        value: String,
+    ) -> StorageResult<Option<FullUser>> {
+        self.user_query(&self.prepared.get_user_from_authentication, (method, value))
             .await
-        {
-            Ok(_) => Ok(()),
-            Err(_) => Err("Could not verify user!".to_string()),
-        }
     }
 
-    async fn get_authentication_methods(&self, id: Uuid) -> Result<Vec<i16>, String> {
-        match self
-            .session
-            .execute(&self.prepared.get_authentication_methods, (id,))
-            .await
-        {
-            Ok(query) => {
-                if let Some(rows) = query.rows {
-                    if let Some(row) = rows.into_typed::<(HashMap<i16, String>,)>().next() {
-                        let (methods,): (HashMap<i16, String>,) = row.unwrap();
+    async fn verify_user(&self, id: Uuid) -> StorageResult<()> {
+        self.execute(&self.prepared.verify_user, (id,)).await?;
+        Ok(())
+    }
 
-                        return Ok(methods.keys().cloned().collect());
-                    }
-                }
+    async fn set_roles(&self, id: Uuid, roles: usize) -> StorageResult<()> {
+        let roles = i16::try_from(roles)
+            .map_err(|_| StorageError::Conflict(format!("Roles {} do not fit", roles)))?;
+        self.execute(&self.prepared.set_roles, (roles, id)).await?;
+        Ok(())
+    }
 
-                Err("Could not get authentication methods!".to_string())
-            }
-            Err(_) => Err("Could not get authentication methods!".to_string()),
+    async fn set_disabled(&self, id: Uuid, disabled: bool) -> StorageResult<()> {
+        self.execute(&self.prepared.set_disabled, (disabled, id))
+            .await?;
+        Ok(())
+    }
+
+    async fn get_authentication_methods(&self, id: Uuid) -> StorageResult<Vec<i16>> {
+        let row: Option<(HashMap<i16, String>,)> = self
+            .get_first(&self.prepared.get_authentication_methods, (id,))
+            .await?;
+
+        match row {
+            Some((methods,)) => Ok(methods.keys().cloned().collect()),
+            None => Err(StorageError::NotFound),
         }
     }
 
@@ -244,31 +339,18 @@ impl PersistentStorageProvider for ScyllaDataProvider {
         id: Uuid,
         method: i16,
         new_value: &str,
-    ) -> Result<(), String> {
-        match self
-            .session
-            .execute(
-                &self.prepared.update_authentication_method_value,
-                (method, new_value, id),
-            )
-            .await
-        {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                println!("{:?}", e);
-                return Err("Could not update authentication method value!".to_string());
-            }
-        }
+    ) -> StorageResult<()> {
+        self.execute(
+            &self.prepared.update_authentication_method_value,
+            (method, new_value, id),
+        )
+        .await?;
+        Ok(())
     }
 
-    async fn remove_authentication_method(&self, id: Uuid, method: i16) -> Result<(), String> {
-        match self
-            .session
-            .execute(&self.prepared.remove_authentication_method, (method, id))
-            .await
-        {
-            Ok(_) => Ok(()),
-            Err(_) => Err("Could not remove authentication method!".to_string()),
-        }
+    async fn remove_authentication_method(&self, id: Uuid, method: i16) -> StorageResult<()> {
+        self.execute(&self.prepared.remove_authentication_method, (method, id))
+            .await?;
+        Ok(())
     }
 }
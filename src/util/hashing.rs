@@ -1,27 +1,155 @@
-use argon2::{hash_encoded, Config, ThreadMode, Variant, Version};
-use std::hash::Hasher;
+use argon2::{hash_encoded, verify_encoded_ext, Config, ThreadMode, Variant, Version};
+use rand::{thread_rng, RngCore};
+use sha2::{Digest, Sha256};
+use std::{fmt::Write, hash::Hasher, sync::Arc};
 use twox_hash::XxHash32;
 
+use crate::{config::HashingConfig, errors::HttpError};
+
+use super::workers::WorkerPool;
+
 pub fn xx_hash(data: &str) -> String {
     let mut hasher = XxHash32::with_seed(0);
     hasher.write(data.as_bytes());
     hasher.finish().to_string()
 }
 
-static ARGON2_CONFIG: Config = Config {
-    ad: &[],
-    hash_length: 128,
-    lanes: 1,
-    mem_cost: 32,
-    secret: &[],
-    thread_mode: ThreadMode::Parallel,
-    time_cost: 3,
-    variant: Variant::Argon2i,
-    version: Version::Version13,
-};
-
-pub fn argon2_hash(data: &str) -> String {
-    // TODO: Get salt from env
-    let salt = b"ajsldAJKHDLAKJDjsna/AZ";
-    hash_encoded(data.as_bytes(), salt, &ARGON2_CONFIG).unwrap()
+/// A hex encoded SHA-256 hash, for values that must not be reversible.
+pub fn sha256_hex(data: &str) -> String {
+    Sha256::digest(data.as_bytes())
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+const SALT_LENGTH: usize = 16;
+const HASH_LENGTH: u32 = 32;
+const VARIANT: Variant = Variant::Argon2id;
+
+/// The result of checking a password against a stored hash.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PasswordVerification {
+    Invalid,
+    Valid,
+    /// The password is valid, but the hash was produced with older parameters
+    /// and should be replaced by a new hash.
+    NeedsRehash,
+}
+
+fn argon2_config(config: &HashingConfig) -> Config<'_> {
+    Config {
+        ad: &[],
+        hash_length: HASH_LENGTH,
+        lanes: config.lanes,
+        mem_cost: config.memory_cost,
+        secret: config.pepper.as_deref().unwrap_or_default().as_bytes(),
+        thread_mode: ThreadMode::Parallel,
+        time_cost: config.time_cost,
+        variant: VARIANT,
+        version: Version::Version13,
+    }
+}
+
+/// Hash a password with a random salt and the configured parameters.
+pub fn argon2_hash(data: &str, config: &HashingConfig) -> String {
+    let mut salt = [0u8; SALT_LENGTH];
+    thread_rng().fill_bytes(&mut salt);
+
+    hash_encoded(data.as_bytes(), &salt, &argon2_config(config)).unwrap()
+}
+
+/// Verify a password against an encoded Argon2 hash.
+pub fn argon2_verify(encoded: &str, data: &str, config: &HashingConfig) -> PasswordVerification {
+    let verify = |secret: &[u8]| match verify_encoded_ext(encoded, data.as_bytes(), secret, &[]) {
+        Ok(valid) => valid,
+        Err(e) => {
+            log::error!("Could not verify stored password hash: {}", e);
+            false
+        }
+    };
+
+    let peppered = match &config.pepper {
+        Some(pepper) => {
+            if verify(pepper.as_bytes()) {
+                true
+            } else if verify(&[]) {
+                // Hashed before the pepper was configured.
+                return PasswordVerification::NeedsRehash;
+            } else {
+                return PasswordVerification::Invalid;
+            }
+        }
+        None => verify(&[]),
+    };
+
+    match peppered {
+        false => PasswordVerification::Invalid,
+        true if is_outdated(encoded, config) => PasswordVerification::NeedsRehash,
+        true => PasswordVerification::Valid,
+    }
+}
+
+/// Hashes and verifies passwords on a bounded pool of blocking workers.
+pub struct PasswordHasher {
+    config: Arc<HashingConfig>,
+    pool: WorkerPool,
+}
+
+impl PasswordHasher {
+    pub fn new(config: &HashingConfig) -> Self {
+        Self {
+            config: Arc::new(config.clone()),
+            pool: WorkerPool::new(config.workers, config.queue),
+        }
+    }
+
+    pub async fn hash(&self, password: &str) -> Result<String, HttpError> {
+        let config = self.config.clone();
+        let password = password.to_string();
+
+        Ok(self
+            .pool
+            .run(move || argon2_hash(&password, &config))
+            .await?)
+    }
+
+    pub async fn verify(
+        &self,
+        encoded: &str,
+        password: &str,
+    ) -> Result<PasswordVerification, HttpError> {
+        let config = self.config.clone();
+        let encoded = encoded.to_string();
+        let password = password.to_string();
+
+        Ok(self
+            .pool
+            .run(move || argon2_verify(&encoded, &password, &config))
+            .await?)
+    }
+}
+
+/// Check if an encoded hash (`$argon2id$v=19$m=..,t=..,p=..$salt$hash`) was
+/// produced with other parameters than the ones that are currently configured.
+fn is_outdated(encoded: &str, config: &HashingConfig) -> bool {
+    let parts: Vec<&str> = encoded.split('$').collect();
+    if parts.len() != 6 || parts[1] != VARIANT.as_lowercase_str() {
+        return true;
+    }
+
+    let expected = format!(
+        "m={},t={},p={}",
+        config.memory_cost, config.time_cost, config.lanes
+    );
+
+    parts[3] != expected
+        || parts[4].len() < base64_length(SALT_LENGTH)
+        || parts[5].len() != base64_length(HASH_LENGTH as usize)
+}
+
+/// The length of unpadded base64 for an amount of bytes.
+fn base64_length(bytes: usize) -> usize {
+    (bytes * 4).div_ceil(3)
 }
@@ -0,0 +1,263 @@
+// Protection against guessing passwords.
+//
+// Failed sign ins are counted per account and per IP address in temporary storage,
+// under `login-failures:<account|ip>:<id>`. The value is JSON, so `drop_all(user_id)`
+// does not remove it when the user signs out. After `backoff_after` failures every
+// attempt has to wait, the wait doubles with every failure. At the threshold the
+// account or IP address is locked for `duration` seconds, after which it starts over.
+//
+// Names that do not belong to an account are counted like accounts, so a lockout
+// does not reveal which accounts exist.
+//
+// Checking a password takes a while, so an attempt first claims
+// `login-pending:<account>`. Only one attempt of an account is checked at a time,
+// parallel attempts can not all get past the check before the failures of the
+// others are counted. Attempts on different accounts from one IP address are not
+// held up, the failures of the address can then be counted a little low.
+use actix_web::HttpRequest;
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    config::LockoutConfig,
+    errors::{HttpError, StorageError, StorageResult},
+    structs::{mail::Mail, user::FullUser, RetryStatus},
+    traits::LockoutNotifier,
+    util::{mail::MailTransport, Database},
+};
+
+#[derive(Serialize, Deserialize, Default)]
+struct Failures {
+    count: usize,
+    /// The time the next attempt is allowed.
+    retry_at: u64,
+}
+
+/// Who tries to sign in.
+pub struct Attempt {
+    account: String,
+    ip: String,
+}
+
+/// What a failed sign in led to.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FailureOutcome {
+    /// The next attempt can be made after the backoff, if any.
+    Counted,
+    /// The account was locked by this failure.
+    AccountLocked,
+}
+
+impl Attempt {
+    /// An attempt to sign in to `user`, or to the name that was tried when there is
+    /// no such account.
+    pub fn new(user: Option<&FullUser>, name: &str, req: &HttpRequest) -> Self {
+        Self {
+            account: match user {
+                Some(user) => user.id.to_string(),
+                None => name.to_lowercase(),
+            },
+            ip: req
+                .peer_addr()
+                .map(|addr| addr.ip().to_string())
+                .unwrap_or_else(|| "unknown".to_string()),
+        }
+    }
+
+    pub fn ip(&self) -> &str {
+        &self.ip
+    }
+}
+
+/// The amount of seconds an attempt that never finished holds its claim.
+const PENDING_TTL: usize = 30;
+
+/// The claim of an attempt that is being checked, see `reserve`.
+pub struct Reservation {
+    key: String,
+}
+
+fn now() -> u64 {
+    Utc::now().timestamp() as u64
+}
+
+fn account_key(account: &str) -> String {
+    format!("login-failures:account:{}", account)
+}
+
+fn ip_key(ip: &str) -> String {
+    format!("login-failures:ip:{}", ip)
+}
+
+async fn get(db: &Database, key: &str) -> StorageResult<Failures> {
+    match db.temporary.get(key.to_string()).await? {
+        Some(failures) => serde_json::from_str(&failures)
+            .map_err(|e| StorageError::Conflict(format!("Malformed login failures: {}", e))),
+        None => Ok(Failures::default()),
+    }
+}
+
+/// The amount of seconds to wait after `count` failures.
+fn backoff(config: &LockoutConfig, count: usize) -> u64 {
+    if count < config.backoff_after {
+        return 0;
+    }
+
+    let doublings = (count - config.backoff_after).min(32) as u32;
+    (config.backoff_base as u64)
+        .saturating_mul(1 << doublings)
+        .min(config.max_backoff as u64)
+}
+
+/// Count a failure of a key, returns if this locked it.
+async fn record(
+    db: &Database,
+    config: &LockoutConfig,
+    key: String,
+    threshold: usize,
+) -> StorageResult<bool> {
+    let mut failures = get(db, &key).await?;
+    failures.count += 1;
+
+    let locked = failures.count >= threshold;
+    let (wait, ttl) = if locked {
+        failures.count = 0;
+        (config.duration as u64, config.duration)
+    } else {
+        let wait = backoff(config, failures.count);
+        (wait, config.window.max(wait as usize))
+    };
+    failures.retry_at = now() + wait;
+
+    let value =
+        serde_json::to_string(&failures).map_err(|e| StorageError::Conflict(e.to_string()))?;
+    db.temporary.set_with_ttl(key, value, ttl).await?;
+
+    Ok(locked)
+}
+
+/// The amount of seconds until the account or IP address can try again, `None` if
+/// it can try now.
+pub async fn retry_after(db: &Database, attempt: &Attempt) -> StorageResult<Option<u64>> {
+    let account = get(db, &account_key(&attempt.account)).await?;
+    let ip = get(db, &ip_key(&attempt.ip)).await?;
+
+    let retry_at = account.retry_at.max(ip.retry_at);
+    let now = now();
+    Ok((retry_at > now).then(|| retry_at - now))
+}
+
+/// Claim the account of an attempt before it is checked. Refused with 429 when the
+/// account or IP address is locked, or when another attempt of the account is being
+/// checked. The reservation has to be released once the attempt was counted.
+pub async fn reserve(db: &Database, attempt: &Attempt) -> Result<Reservation, HttpError> {
+    let key = format!("login-pending:{}", attempt.account);
+    if !db
+        .temporary
+        .set_if_absent(key.clone(), now().to_string(), PENDING_TTL)
+        .await?
+    {
+        return Err(HttpError::TooManyRequests(RetryStatus {
+            message: "Another sign in attempt is in progress, try again in a moment.".to_string(),
+            retry_after: 1,
+        }));
+    }
+    let reservation = Reservation { key };
+
+    if let Some(retry_after) = retry_after(db, attempt).await? {
+        release(db, reservation).await?;
+        return Err(HttpError::TooManyRequests(RetryStatus {
+            message: "Too many failed sign in attempts, try again later.".to_string(),
+            retry_after,
+        }));
+    }
+
+    Ok(reservation)
+}
+
+/// Let the next attempt of the account be checked.
+pub async fn release(db: &Database, reservation: Reservation) -> StorageResult<()> {
+    db.temporary.delete(reservation.key).await
+}
+
+/// Count a failed sign in for both the account and the IP address, while the
+/// attempt is reserved.
+pub async fn fail(
+    db: &Database,
+    config: &LockoutConfig,
+    attempt: &Attempt,
+) -> StorageResult<FailureOutcome> {
+    record(db, config, ip_key(&attempt.ip), config.ip_threshold).await?;
+
+    let locked = record(
+        db,
+        config,
+        account_key(&attempt.account),
+        config.account_threshold,
+    )
+    .await?;
+
+    Ok(if locked {
+        FailureOutcome::AccountLocked
+    } else {
+        FailureOutcome::Counted
+    })
+}
+
+/// Count a failed attempt on an account that exists, and tell its owner when this
+/// locked it.
+pub async fn fail_user(
+    db: &Database,
+    config: &LockoutConfig,
+    attempt: &Attempt,
+    user: &FullUser,
+    notifier: &dyn LockoutNotifier,
+) -> StorageResult<()> {
+    if fail(db, config, attempt).await? == FailureOutcome::AccountLocked {
+        notifier
+            .account_locked(user, &attempt.ip, config.duration)
+            .await;
+    }
+    Ok(())
+}
+
+/// Forget the failures of an account, when its owner proved who they are. The
+/// failures of the IP address are kept.
+pub async fn clear(db: &Database, user_id: Uuid) -> StorageResult<()> {
+    db.temporary.delete(account_key(&user_id.to_string())).await
+}
+
+/// The default `LockoutNotifier`, it mails the owner of the account.
+pub struct MailLockoutNotifier {
+    mailer: MailTransport,
+}
+
+impl MailLockoutNotifier {
+    pub fn new(mailer: MailTransport) -> Self {
+        Self { mailer }
+    }
+}
+
+#[async_trait]
+impl LockoutNotifier for MailLockoutNotifier {
+    async fn account_locked(&self, user: &FullUser, ip: &str, duration: usize) {
+        let minutes = duration.div_ceil(60);
+        let mail = Mail {
+            to: user.email.clone(),
+            subject: "Your account has been locked".to_string(),
+            body: format!(
+                "Hi {},\n\nSomebody entered a wrong password for your Xiler account too many times, the last attempt came from {}. Signing in with a password is blocked for {} minute{}.\n\nIf this was not you, somebody may be trying to guess your password. Choose a strong password that you do not use anywhere else, resetting it also lifts the lock.",
+                user.username,
+                ip,
+                minutes,
+                if minutes == 1 { "" } else { "s" }
+            ),
+        };
+
+        if let Err(e) = self.mailer.send(mail).await {
+            log::warn!("Could not send the lockout email of {}: {}", user.id, e);
+        }
+    }
+}
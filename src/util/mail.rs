@@ -0,0 +1,33 @@
+mod outbox;
+mod smtp;
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, errors::ConfigError, traits::Mailer};
+
+pub use self::outbox::OutboxMailer;
+pub use self::smtp::SmtpMailer;
+
+pub type MailTransport = Arc<dyn Mailer>;
+
+/// The ways mail can be delivered.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MailerKind {
+    Smtp,
+    Outbox,
+}
+
+impl MailerKind {
+    /// Create the selected mailer.
+    pub fn connect(self, config: &Config) -> Result<MailTransport, ConfigError> {
+        Ok(match self {
+            Self::Smtp => Arc::new(SmtpMailer::new(&config.smtp, &config.mail.from)?),
+            Self::Outbox => Arc::new(OutboxMailer::new(
+                config.mail.outbox.as_ref().map(Into::into),
+            )),
+        })
+    }
+}
@@ -0,0 +1,72 @@
+// Keeps mail instead of delivering it, so it can be read during development and
+// in tests. Every mail can also be written to a directory.
+//
+// This should not be used in production, users never receive anything.
+
+use std::{path::PathBuf, sync::Mutex};
+
+use async_trait::async_trait;
+
+use crate::{
+    errors::{MailError, MailResult},
+    structs::mail::Mail,
+    traits::Mailer,
+    util::random::random_string,
+};
+
+pub struct OutboxMailer {
+    mails: Mutex<Vec<Mail>>,
+    directory: Option<PathBuf>,
+}
+
+impl OutboxMailer {
+    pub fn new(directory: Option<PathBuf>) -> Self {
+        Self {
+            mails: Mutex::new(Vec::new()),
+            directory,
+        }
+    }
+
+    /// All mail that was sent, oldest first.
+    pub fn mails(&self) -> Vec<Mail> {
+        self.mails.lock().unwrap().clone()
+    }
+
+    /// The mail that was sent last to an address.
+    pub fn last_to(&self, to: &str) -> Option<Mail> {
+        self.mails
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|mail| mail.to == to)
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl Mailer for OutboxMailer {
+    async fn send(&self, mail: Mail) -> MailResult<()> {
+        if let Some(directory) = &self.directory {
+            let name = format!(
+                "{}-{}.txt",
+                chrono::Utc::now().format("%Y%m%d%H%M%S"),
+                random_string(8)
+            );
+            let contents = format!(
+                "To: {}\nSubject: {}\n\n{}\n",
+                mail.to, mail.subject, mail.body
+            );
+
+            tokio::fs::create_dir_all(directory)
+                .await
+                .map_err(|e| MailError::Unavailable(e.to_string()))?;
+            tokio::fs::write(directory.join(name), contents)
+                .await
+                .map_err(|e| MailError::Unavailable(e.to_string()))?;
+        }
+
+        self.mails.lock().unwrap().push(mail);
+        Ok(())
+    }
+}
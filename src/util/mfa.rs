@@ -0,0 +1,189 @@
+// Sign ins that wait for a second factor.
+//
+// When a user with a second factor provides their password, they get a token
+// instead of a session. The session is created once the second factor is provided
+// with that token. `mfa-pending:<hash of token>` contains the `PendingLogin` as
+// JSON, so the token itself is never stored.
+use actix_web::HttpRequest;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    config::{Config, MfaConfig},
+    constants::{PASSWORD_AUTHENTICATION, TOTP_AUTHENTICATION},
+    errors::{HttpError, StorageError, StorageResult},
+    structs::{
+        mfa::{MfaChallenge, Reauthentication},
+        session::SessionKind,
+        user::FullUser,
+        Status,
+    },
+    traits::LockoutNotifier,
+    util::{
+        hashing::{sha256_hex, PasswordHasher, PasswordVerification},
+        lockout::{self, Attempt},
+        random::random_string,
+        totp::{self, TotpMethod},
+        Database,
+    },
+};
+
+#[derive(Serialize, Deserialize)]
+pub struct PendingLogin {
+    pub user_id: Uuid,
+    /// The kind of session that is created afterwards.
+    pub session: SessionKind,
+    pub attempts: usize,
+    pub expires_at: usize,
+}
+
+fn pending_key(token: &str) -> String {
+    format!("mfa-pending:{}", sha256_hex(token))
+}
+
+fn now() -> usize {
+    Utc::now().timestamp() as usize
+}
+
+/// The second factors of a user, empty if the password is enough.
+pub fn methods(user: &FullUser) -> Vec<String> {
+    let mut methods = Vec::new();
+    if user.authentication.contains_key(&TOTP_AUTHENTICATION) {
+        methods.push("totp".to_string());
+    }
+    methods
+}
+
+async fn set_pending(db: &Database, token: &str, pending: &PendingLogin) -> StorageResult<()> {
+    let value =
+        serde_json::to_string(pending).map_err(|e| StorageError::Conflict(e.to_string()))?;
+    let ttl = pending.expires_at.saturating_sub(now()).max(1);
+    db.temporary
+        .set_with_ttl(pending_key(token), value, ttl)
+        .await
+}
+
+/// Start a sign in that waits for a second factor.
+pub async fn start(
+    db: &Database,
+    config: &MfaConfig,
+    user: &FullUser,
+    session: SessionKind,
+) -> StorageResult<MfaChallenge> {
+    let token = random_string(64);
+    let pending = PendingLogin {
+        user_id: user.id,
+        session,
+        attempts: 0,
+        expires_at: now() + config.pending_ttl,
+    };
+    set_pending(db, &token, &pending).await?;
+
+    Ok(MfaChallenge {
+        mfa_token: token,
+        ttl: config.pending_ttl,
+        methods: methods(user),
+    })
+}
+
+/// The sign in of a token, `None` if it expired or was finished.
+pub async fn get(db: &Database, token: &str) -> StorageResult<Option<PendingLogin>> {
+    match db.temporary.get(pending_key(token)).await? {
+        Some(value) => serde_json::from_str(&value)
+            .map(Some)
+            .map_err(|e| StorageError::Conflict(format!("Malformed pending login: {}", e))),
+        None => Ok(None),
+    }
+}
+
+/// Count a wrong code, the sign in ends after too many of them. The attempt has to
+/// be reserved with `lockout::reserve`, so codes that are checked in parallel are
+/// all counted.
+pub async fn fail(
+    db: &Database,
+    config: &MfaConfig,
+    token: &str,
+    mut pending: PendingLogin,
+) -> StorageResult<()> {
+    pending.attempts += 1;
+    if pending.attempts >= config.max_attempts {
+        return finish(db, token).await;
+    }
+
+    set_pending(db, token, &pending).await
+}
+
+/// End a sign in, the token can not be used anymore.
+pub async fn finish(db: &Database, token: &str) -> StorageResult<()> {
+    db.temporary.delete(pending_key(token)).await
+}
+
+/// Check a second factor of a user, this is either a TOTP code or one of the
+/// recovery codes. A recovery code is removed once it is used.
+pub async fn check_code(db: &Database, user: &FullUser, code: &str) -> StorageResult<bool> {
+    let value = match user.authentication.get(&TOTP_AUTHENTICATION) {
+        Some(value) => value,
+        None => return Ok(false),
+    };
+    let mut method = TotpMethod::parse(value)?;
+
+    if totp::check(db, user.id, &method.secret, code.trim()).await? {
+        return Ok(true);
+    }
+
+    if method.use_recovery_code(code) {
+        db.persistent
+            .update_authentication_method_value(user.id, TOTP_AUTHENTICATION, &method.to_value()?)
+            .await?;
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// Check that the user of a session can still sign in, with a second factor or the
+/// password. Wrong ones count as failed sign ins of the account.
+pub async fn reauthenticate(
+    db: &Database,
+    config: &Config,
+    hasher: &PasswordHasher,
+    notifier: &dyn LockoutNotifier,
+    user: &FullUser,
+    proof: &Reauthentication,
+    data: &HttpRequest,
+) -> Result<(), HttpError> {
+    if proof.code.is_none() && proof.password.is_none() {
+        return Err(HttpError::BadRequest(Status {
+            message: "Provide a code from the authenticator app or your password.".to_string(),
+        }));
+    }
+
+    let attempt = Attempt::new(Some(user), &user.username, data);
+    let reservation = lockout::reserve(db, &attempt).await?;
+
+    // The reservation is released however the attempt ends.
+    let result = async {
+        let proven = match (&proof.code, &proof.password) {
+            (Some(code), _) => check_code(db, user, code).await?,
+            (None, Some(password)) => match user.authentication.get(&PASSWORD_AUTHENTICATION) {
+                Some(hash) => hasher.verify(hash, password).await? != PasswordVerification::Invalid,
+                None => false,
+            },
+            (None, None) => false,
+        };
+
+        if !proven {
+            lockout::fail_user(db, &config.lockout, &attempt, user, notifier).await?;
+            return Err(HttpError::Unauthorized(Status {
+                message: "Invalid code or password.".to_string(),
+            }));
+        }
+
+        Ok(())
+    }
+    .await;
+
+    lockout::release(db, reservation).await?;
+    result
+}
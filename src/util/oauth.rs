@@ -0,0 +1,254 @@
+// Signing in with another platform, with OAuth 2.0 and PKCE.
+//
+// `start` sends the user to the provider with a random state. The pending sign in
+// is stored under `oauth-state:<hash of state>` and contains the PKCE verifier and
+// the user that links the platform, if any. It is also tied to the browser with a
+// cookie, so nobody can make someone else finish a sign in they started. The
+// provider sends the user back with a code, which is exchanged for an access token
+// to ask the provider who the user is. The id of the user at the provider is stored
+// as the value of the authentication method of the provider.
+use std::time::Duration;
+
+use actix_web::{
+    cookie::{time, Cookie, SameSite},
+    http::header,
+    HttpRequest,
+};
+use awc::Client;
+use data_encoding::BASE64URL_NOPAD;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    config::{OauthConfig, OauthProviderConfig, SessionConfig},
+    errors::{OauthError, OauthResult, StorageError, StorageResult},
+    structs::session::SessionKind,
+    util::{hashing::sha256_hex, random::random_string, Database},
+};
+
+const STATE_LENGTH: usize = 48;
+const VERIFIER_LENGTH: usize = 64;
+/// The amount of time the provider has to answer.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize)]
+pub struct PendingAuthorization {
+    pub provider: String,
+    /// The PKCE code verifier, the provider only got its hash.
+    pub verifier: String,
+    /// The user that links the platform, `None` to sign in.
+    pub user_id: Option<Uuid>,
+    /// The kind of session that is created when the user signs in.
+    pub session: SessionKind,
+    /// The hash of the state cookie of the browser that started.
+    pub binding: String,
+}
+
+/// Where to send the user, and the cookie that ties the sign in to their browser.
+pub struct Authorization {
+    pub url: String,
+    pub cookie: Cookie<'static>,
+}
+
+/// Who the user is at the provider.
+pub struct Identity {
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub username: Option<String>,
+}
+
+fn state_key(state: &str) -> String {
+    format!("oauth-state:{}", sha256_hex(state))
+}
+
+fn cookie_name(session: &SessionConfig) -> String {
+    format!("{}-oauth", session.key)
+}
+
+fn state_cookie(session: &SessionConfig, config: &OauthConfig, value: String) -> Cookie<'static> {
+    // The provider sends the user back with a top-level navigation, which does not
+    // include strict cookies.
+    Cookie::build(cookie_name(session), value)
+        .path("/oauth")
+        .http_only(true)
+        .secure(session.secure)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(config.state_ttl as i64))
+        .finish()
+}
+
+/// If the request comes from the browser that started the sign in.
+pub fn is_same_browser(
+    session: &SessionConfig,
+    pending: &PendingAuthorization,
+    req: &HttpRequest,
+) -> bool {
+    req.cookie(&cookie_name(session))
+        .map(|cookie| sha256_hex(cookie.value()) == pending.binding)
+        .unwrap_or(false)
+}
+
+/// Where the provider sends the user back to.
+pub fn redirect_uri(config: &OauthConfig, provider: &str) -> String {
+    format!(
+        "{}/oauth/{}/callback",
+        config.redirect_base_url.trim_end_matches('/'),
+        provider
+    )
+}
+
+/// The S256 PKCE code challenge of a verifier.
+pub fn code_challenge(verifier: &str) -> String {
+    BASE64URL_NOPAD.encode(&Sha256::digest(verifier.as_bytes()))
+}
+
+/// Start signing in at a provider.
+pub async fn start(
+    db: &Database,
+    session_config: &SessionConfig,
+    config: &OauthConfig,
+    name: &str,
+    provider: &OauthProviderConfig,
+    user_id: Option<Uuid>,
+    session: SessionKind,
+) -> StorageResult<Authorization> {
+    let state = random_string(STATE_LENGTH);
+    let binding = random_string(STATE_LENGTH);
+    let pending = PendingAuthorization {
+        provider: name.to_string(),
+        verifier: random_string(VERIFIER_LENGTH),
+        user_id,
+        session,
+        binding: sha256_hex(&binding),
+    };
+    let value =
+        serde_json::to_string(&pending).map_err(|e| StorageError::Conflict(e.to_string()))?;
+    db.temporary
+        .set_with_ttl(state_key(&state), value, config.state_ttl)
+        .await?;
+
+    let query = serde_urlencoded::to_string([
+        ("response_type", "code"),
+        ("client_id", &provider.client_id),
+        ("redirect_uri", &redirect_uri(config, name)),
+        ("scope", &provider.scopes.join(" ")),
+        ("state", &state),
+        ("code_challenge", &code_challenge(&pending.verifier)),
+        ("code_challenge_method", "S256"),
+    ])
+    .expect("pairs of strings can always be encoded");
+    let separator = if provider.authorize_url.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+
+    Ok(Authorization {
+        url: format!("{}{}{}", provider.authorize_url, separator, query),
+        cookie: state_cookie(session_config, config, binding),
+    })
+}
+
+/// Use the state the provider sent back, `None` if it expired or was used.
+pub async fn take(db: &Database, state: &str) -> StorageResult<Option<PendingAuthorization>> {
+    let pending = match db.temporary.get(state_key(state)).await? {
+        Some(pending) => pending,
+        None => return Ok(None),
+    };
+    db.temporary.delete(state_key(state)).await?;
+
+    serde_json::from_str(&pending)
+        .map(Some)
+        .map_err(|e| StorageError::Conflict(format!("Malformed OAuth state: {}", e)))
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// Exchange the code of the provider for an access token and ask who the user is.
+pub async fn identify(
+    config: &OauthConfig,
+    name: &str,
+    provider: &OauthProviderConfig,
+    code: &str,
+    verifier: &str,
+) -> OauthResult<Identity> {
+    let unavailable =
+        |e: &dyn std::fmt::Display| OauthError::Unavailable(format!("{}: {}", name, e));
+    let client = Client::builder().timeout(TIMEOUT).finish();
+
+    let mut response = client
+        .post(&provider.token_url)
+        .insert_header((header::ACCEPT, "application/json"))
+        .send_form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &redirect_uri(config, name)),
+            ("client_id", &provider.client_id),
+            ("client_secret", &provider.client_secret),
+            ("code_verifier", verifier),
+        ])
+        .await
+        .map_err(|e| unavailable(&e))?;
+
+    if response.status().is_client_error() {
+        return Err(OauthError::Rejected(
+            "the provider did not accept the code".to_string(),
+        ));
+    } else if !response.status().is_success() {
+        return Err(unavailable(&response.status()));
+    }
+    let token: TokenResponse = response.json().await.map_err(|e| unavailable(&e))?;
+
+    let mut response = client
+        .get(&provider.userinfo_url)
+        .bearer_auth(&token.access_token)
+        .insert_header((header::ACCEPT, "application/json"))
+        // Some providers, like GitHub, refuse requests without a user agent.
+        .insert_header((header::USER_AGENT, "xiler-accounts"))
+        .send()
+        .await
+        .map_err(|e| unavailable(&e))?;
+
+    if !response.status().is_success() {
+        return Err(unavailable(&response.status()));
+    }
+    let claims: Value = response.json().await.map_err(|e| unavailable(&e))?;
+
+    identity(provider, &claims)
+}
+
+/// A claim as a string, ids of some providers are numbers.
+fn claim(claims: &Value, name: &str) -> Option<String> {
+    match claims.get(name)? {
+        Value::String(value) if !value.is_empty() => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Read the identity of the user from the claims of the provider.
+pub fn identity(provider: &OauthProviderConfig, claims: &Value) -> OauthResult<Identity> {
+    let subject = claim(claims, &provider.subject_claim).ok_or_else(|| {
+        OauthError::Unavailable("the provider did not share the id of the user".to_string())
+    })?;
+
+    let email_verified = provider.trust_email
+        || match claims.get(&provider.email_verified_claim) {
+            Some(Value::Bool(verified)) => *verified,
+            Some(Value::String(verified)) => verified == "true",
+            _ => false,
+        };
+
+    Ok(Identity {
+        subject,
+        email: claim(claims, &provider.email_claim),
+        email_verified,
+        username: claim(claims, &provider.username_claim),
+    })
+}
@@ -1,6 +1,9 @@
-use crate::structs::{
-    cookie::ParsedCookie,
-    user_agent::{ParsedUserAgent, UserAgentPlatform},
+use crate::{
+    constants::{BROWSER_SESSION_PREFIX, LEGACY_BROWSER_SESSION_PREFIX},
+    structs::{
+        cookie::{Fingerprint, ParsedCookie},
+        user_agent::{ParsedUserAgent, UserAgentPlatform},
+    },
 };
 
 fn parse_platform(platform: &str) -> UserAgentPlatform {
@@ -55,7 +58,7 @@ fn parse_extensions(platforms: &mut Vec<&str>) -> Vec<String> {
 pub fn parse_user_agent(user_agent: String) -> ParsedUserAgent {
     let mut platforms: Vec<&str> = user_agent.split_inclusive(')').collect();
 
-    if platforms.len() == 0 {
+    if platforms.is_empty() {
         return ParsedUserAgent {
             platforms: vec![],
             extensions: vec![],
@@ -72,16 +75,18 @@ pub fn parse_user_agent(user_agent: String) -> ParsedUserAgent {
 }
 
 // s1.2426094911.1768803836-3566326825-3912814204||3967086928-2746952293-707505781.3788223220_59786466.Jmtl9LJ3bAYkoymfnCdHCjYjE00hJhdJ
-// TODO: Parse expected (hashed) values from cookie
+// s2.<key id>.<ip>.<platforms>.<extensions>.<random>.<signature>
 pub fn parse_browser_cookie(cookie: &str) -> Result<ParsedCookie, String> {
     let splitted: Vec<&str> = cookie.split(".").collect();
-    if splitted.len() != 5 {
-        return Err("Invalid cookie".to_string());
-    }
+    let (key_id, fields, signature) = match (splitted[0], splitted.len()) {
+        (LEGACY_BROWSER_SESSION_PREFIX, 5) => (None, &splitted[1..5], None),
+        (BROWSER_SESSION_PREFIX, 7) => (Some(splitted[1]), &splitted[2..6], Some(splitted[6])),
+        _ => return Err("Invalid cookie".to_string()),
+    };
 
     let prefix = splitted[0].to_string();
-    let ip = splitted[1].to_string();
-    let platforms = splitted[2]
+    let ip = fields[0].to_string();
+    let platforms = fields[1]
         .split("||")
         .filter_map(|platform| {
             let platform_details = platform.split("-").collect::<Vec<&str>>();
@@ -98,13 +103,21 @@ pub fn parse_browser_cookie(cookie: &str) -> Result<ParsedCookie, String> {
         })
         .collect();
 
-    let extensions = splitted[3].split("_").map(|s| s.to_string()).collect();
+    let extensions = fields[2]
+        .split("_")
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect();
 
     Ok(ParsedCookie {
         prefix,
-        ip,
-        platforms,
-        extensions,
-        random: splitted[4].to_string(),
+        key_id: key_id.map(|id| id.to_string()),
+        fingerprint: Fingerprint {
+            ip,
+            platforms,
+            extensions,
+        },
+        random: fields[3].to_string(),
+        signature: signature.map(|signature| signature.to_string()),
     })
 }
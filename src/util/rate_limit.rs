@@ -0,0 +1,209 @@
+// Token buckets for the rate limits of `RateLimitService`.
+//
+// Every client has a bucket per limit that holds up to `requests` tokens and fills
+// up again in `per` seconds. Every request takes a token, requests that find the
+// bucket empty are refused. A bucket that is not stored is full.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::RateLimit,
+    errors::{StorageError, StorageResult},
+    traits::RateLimitStore,
+    types::{FullDatabase, RateLimiter},
+};
+
+/// Buckets that are kept in memory. Beyond this the full ones are removed, and when
+/// that is not enough the ones that were used least recently.
+pub const MAX_MEMORY_BUCKETS: usize = 10_000;
+
+/// Room that is made at once when the buckets do not fit, so they are not scanned
+/// again on every request.
+const EVICTED_BUCKETS: usize = MAX_MEMORY_BUCKETS / 10;
+
+/// Who the requests of a limit are counted for. Users and sessions are only known
+/// on resources that require a session, other requests count for their IP address.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    Ip,
+    User,
+    Session,
+}
+
+/// The state of a bucket after a request took a token from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    /// The amount of requests that can still be made right away.
+    pub remaining: u32,
+    /// The amount of seconds until the bucket is full again.
+    pub reset: u64,
+    /// The amount of seconds until the next request is allowed, 0 when allowed.
+    pub retry_after: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Bucket {
+    tokens: f64,
+    /// The time in milliseconds the tokens were counted.
+    updated_at: i64,
+}
+
+impl Bucket {
+    pub fn full(limit: &RateLimit, now: i64) -> Self {
+        Self {
+            tokens: limit.requests as f64,
+            updated_at: now,
+        }
+    }
+
+    /// Tokens per millisecond.
+    fn rate(limit: &RateLimit) -> f64 {
+        limit.requests as f64 / (limit.per.max(1) as f64 * 1000.0)
+    }
+
+    fn seconds_until(limit: &RateLimit, tokens: f64) -> u64 {
+        (tokens.max(0.0) / Self::rate(limit) / 1000.0).ceil() as u64
+    }
+
+    /// Fill the bucket for the time that passed and take a token from it.
+    pub fn take(&mut self, limit: &RateLimit, now: i64) -> RateLimitDecision {
+        let elapsed = (now - self.updated_at).max(0) as f64;
+        self.tokens = (self.tokens + elapsed * Self::rate(limit)).min(limit.requests as f64);
+        self.updated_at = now;
+
+        let allowed = self.tokens >= 1.0;
+        if allowed {
+            self.tokens -= 1.0;
+        }
+
+        RateLimitDecision {
+            allowed,
+            limit: limit.requests,
+            remaining: self.tokens.floor() as u32,
+            reset: Self::seconds_until(limit, limit.requests as f64 - self.tokens),
+            retry_after: if allowed {
+                0
+            } else {
+                Self::seconds_until(limit, 1.0 - self.tokens)
+            },
+        }
+    }
+
+    fn is_full(&self, limit: &RateLimit, now: i64) -> bool {
+        let elapsed = (now - self.updated_at).max(0) as f64;
+        self.tokens + elapsed * Self::rate(limit) >= limit.requests as f64
+    }
+}
+
+fn now() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+/// Keeps the buckets in the memory of the process, every instance of the service
+/// has its own limits.
+#[derive(Default)]
+pub struct MemoryRateLimitStore {
+    buckets: Mutex<HashMap<String, (Bucket, RateLimit)>>,
+}
+
+impl MemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn take(&self, key: String, limit: &RateLimit) -> StorageResult<RateLimitDecision> {
+        let now = now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_MEMORY_BUCKETS && !buckets.contains_key(&key) {
+            buckets.retain(|_, (bucket, limit)| !bucket.is_full(limit, now));
+
+            let keep = MAX_MEMORY_BUCKETS - EVICTED_BUCKETS;
+            if buckets.len() > keep {
+                let mut used: Vec<(i64, String)> = buckets
+                    .iter()
+                    .map(|(key, (bucket, _))| (bucket.updated_at, key.clone()))
+                    .collect();
+                let evicted = used.len() - keep;
+                used.select_nth_unstable(evicted);
+                for (_, key) in &used[..evicted] {
+                    buckets.remove(key);
+                }
+            }
+        }
+
+        let (bucket, _) = buckets
+            .entry(key)
+            .or_insert_with(|| (Bucket::full(limit, now), limit.clone()));
+        Ok(bucket.take(limit, now))
+    }
+}
+
+/// Keeps the buckets in temporary storage under `rate-limit:<name>:<client>`, so
+/// every instance of the service shares the limits. Requests that are made at the
+/// same time can both take the last token.
+pub struct TemporaryRateLimitStore {
+    database: FullDatabase,
+}
+
+impl TemporaryRateLimitStore {
+    pub fn new(database: FullDatabase) -> Self {
+        Self { database }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for TemporaryRateLimitStore {
+    async fn take(&self, key: String, limit: &RateLimit) -> StorageResult<RateLimitDecision> {
+        let now = now();
+        let key = format!("rate-limit:{}", key);
+
+        let mut bucket = match self.database.temporary.get(key.clone()).await? {
+            Some(bucket) => serde_json::from_str(&bucket)
+                .map_err(|e| StorageError::Conflict(format!("Malformed rate limit: {}", e)))?,
+            None => Bucket::full(limit, now),
+        };
+        let decision = bucket.take(limit, now);
+
+        let value =
+            serde_json::to_string(&bucket).map_err(|e| StorageError::Conflict(e.to_string()))?;
+        self.database
+            .temporary
+            .set_with_ttl(key, value, decision.reset.max(1) as usize)
+            .await?;
+
+        Ok(decision)
+    }
+}
+
+/// Where the buckets of the rate limits are kept.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitStoreKind {
+    /// In the process, the limits are per instance of the service.
+    Memory,
+    /// In the temporary storage provider, shared by every instance.
+    Temporary,
+}
+
+impl RateLimitStoreKind {
+    /// Create the selected store, it has to be shared by every worker.
+    pub fn connect(self, database: &FullDatabase) -> RateLimiter {
+        match self {
+            Self::Memory => Arc::new(MemoryRateLimitStore::new()),
+            Self::Temporary => Arc::new(TemporaryRateLimitStore::new(database.clone())),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+// The default `SessionRiskScorer`, every part of the fingerprint that does not
+// match the client costs its configured weight.
+use crate::{
+    config::RiskConfig,
+    structs::{
+        cookie::Fingerprint,
+        risk::{Mismatch, RiskAssessment, RiskOutcome},
+    },
+    traits::SessionRiskScorer,
+};
+
+pub struct WeightedRiskScorer {
+    config: RiskConfig,
+}
+
+impl WeightedRiskScorer {
+    pub fn new(config: &RiskConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    fn platform_weight(&self) -> f64 {
+        self.config.platform_name + self.config.platform_version + self.config.platform_details
+    }
+}
+
+impl SessionRiskScorer for WeightedRiskScorer {
+    fn assess(&self, expected: &Fingerprint, actual: &Fingerprint) -> RiskAssessment {
+        let config = &self.config;
+        let mut mismatches = vec![];
+        let mut total = config.ip;
+        let mut penalty = 0.0;
+
+        if expected.ip != actual.ip {
+            mismatches.push(Mismatch::Ip);
+            penalty += config.ip;
+        }
+
+        // Platforms and extensions that only one of both has count as a full mismatch.
+        let platforms = expected.platforms.len().max(actual.platforms.len());
+        for i in 0..platforms {
+            total += self.platform_weight();
+
+            match (expected.platforms.get(i), actual.platforms.get(i)) {
+                (Some(expected), Some(actual)) => {
+                    if expected.name != actual.name {
+                        mismatches.push(Mismatch::PlatformName(i));
+                        penalty += config.platform_name;
+                    }
+                    if expected.version != actual.version {
+                        mismatches.push(Mismatch::PlatformVersion(i));
+                        penalty += config.platform_version;
+                    }
+                    if expected.details != actual.details {
+                        mismatches.push(Mismatch::PlatformDetails(i));
+                        penalty += config.platform_details;
+                    }
+                }
+                (Some(_), None) => {
+                    mismatches.push(Mismatch::MissingPlatform(i));
+                    penalty += self.platform_weight();
+                }
+                (None, _) => {
+                    mismatches.push(Mismatch::ExtraPlatform(i));
+                    penalty += self.platform_weight();
+                }
+            }
+        }
+
+        let extensions = expected.extensions.len().max(actual.extensions.len());
+        for i in 0..extensions {
+            total += config.extension;
+
+            let mismatch = match (expected.extensions.get(i), actual.extensions.get(i)) {
+                (Some(expected), Some(actual)) if expected == actual => continue,
+                (Some(_), Some(_)) => Mismatch::Extension(i),
+                (Some(_), None) => Mismatch::MissingExtension(i),
+                (None, _) => Mismatch::ExtraExtension(i),
+            };
+            mismatches.push(mismatch);
+            penalty += config.extension;
+        }
+
+        let score = match total > 0.0 {
+            true => 1.0 - penalty / total,
+            false => 1.0,
+        };
+
+        let outcome = if score <= config.revoke_threshold {
+            RiskOutcome::Revoke
+        } else if score <= config.step_up_threshold {
+            RiskOutcome::StepUp
+        } else {
+            RiskOutcome::Allow
+        };
+
+        RiskAssessment {
+            score,
+            outcome,
+            mismatches,
+        }
+    }
+}
@@ -1,55 +1,222 @@
-use actix_web::HttpRequest;
+pub mod store;
+
+use actix_web::{
+    cookie::{time::Duration, Cookie, SameSite},
+    HttpRequest,
+};
 
 use crate::{
+    config::{SameSitePolicy, SessionConfig},
+    constants::{API_SESSION_PREFIX, BROWSER_SESSION_PREFIX},
     errors::HttpError,
-    structs::{user_agent::ParsedUserAgent, Status},
+    structs::{
+        cookie::Fingerprint,
+        session::SessionKind,
+        user_agent::{ParsedUserAgent, UserAgentPlatform},
+        Status,
+    },
+};
+
+use super::{
+    hashing::xx_hash,
+    parse::{parse_browser_cookie, parse_user_agent},
+    random::random_string,
+    signing::TokenSigner,
 };
 
-use super::{hashing::xx_hash, parse::parse_user_agent, random::random_string};
+/// Hash the details of a client, so they can be stored in a browser session.
+fn hash_fingerprint(
+    ip: &str,
+    user_agent: &ParsedUserAgent,
+    hash: impl Fn(&str) -> String,
+) -> Fingerprint {
+    Fingerprint {
+        ip: hash(ip),
+        platforms: user_agent
+            .platforms
+            .iter()
+            .map(|platform| UserAgentPlatform {
+                name: hash(&platform.name),
+                version: hash(&platform.version),
+                details: hash(&platform.details),
+            })
+            .collect(),
+        extensions: user_agent
+            .extensions
+            .iter()
+            .map(|extension| hash(extension))
+            .collect(),
+    }
+}
 
-pub fn generate_browser_session(ip: String, user_agent: ParsedUserAgent) -> String {
-    let random = random_string(32);
-    let hashed_ip = xx_hash(&ip);
-    let hashed_user_agent_platforms = user_agent
+/// The fingerprint of a client as it is stored in `s1` tokens.
+pub fn legacy_fingerprint(ip: &str, user_agent: &ParsedUserAgent) -> Fingerprint {
+    hash_fingerprint(ip, user_agent, xx_hash)
+}
+
+/// The fingerprint of a client as it is stored in `s2` tokens that are signed
+/// with `key_id`, `None` if the key is unknown.
+pub fn fingerprint(
+    signer: &TokenSigner,
+    key_id: &str,
+    ip: &str,
+    user_agent: &ParsedUserAgent,
+) -> Option<Fingerprint> {
+    if !signer.has_key(key_id) {
+        return None;
+    }
+
+    Some(hash_fingerprint(ip, user_agent, |value| {
+        signer.fingerprint(key_id, value).unwrap_or_default()
+    }))
+}
+
+pub fn generate_browser_session(
+    ip: String,
+    user_agent: ParsedUserAgent,
+    signer: &TokenSigner,
+) -> String {
+    let key_id = signer.signing_key();
+    let fingerprint =
+        fingerprint(signer, key_id, &ip, &user_agent).expect("the signing key is configured");
+    let platforms = fingerprint
         .platforms
         .iter()
         .map(|platform| {
             format!(
                 "{}-{}-{}",
-                xx_hash(&platform.name.clone()),
-                xx_hash(&platform.version.clone()),
-                xx_hash(&platform.details.clone())
+                platform.name, platform.version, platform.details
             )
         })
         .collect::<Vec<String>>();
 
-    let hashed_user_agent_extensions = user_agent
-        .extensions
-        .iter()
-        .map(|extension| xx_hash(&extension.to_string()))
-        .collect::<Vec<String>>();
-
-    vec![
-        "s1".to_string(),
-        hashed_ip,
-        hashed_user_agent_platforms.join("||"),
-        hashed_user_agent_extensions.join("_"),
-        random,
+    let token = [
+        BROWSER_SESSION_PREFIX.to_string(),
+        key_id.to_string(),
+        fingerprint.ip,
+        platforms.join("||"),
+        fingerprint.extensions.join("_"),
+        random_string(48),
     ]
-    .join(".")
+    .join(".");
+    let signature = signer.sign(&token);
+
+    format!("{}.{}", token, signature)
 }
 
-pub fn create_browser_session(data: HttpRequest) -> Result<String, HttpError> {
-    let user_agent = match data.headers().get("User-Agent") {
-        Some(agent) => agent,
-        None => {
-            return Err(HttpError::BadRequest(Status {
-                message: "No user agent present".to_string(),
-            }))
+fn user_agent_of(data: &HttpRequest) -> Result<ParsedUserAgent, HttpError> {
+    match data.headers().get("User-Agent") {
+        Some(agent) => Ok(parse_user_agent(agent.to_str().unwrap().to_string())),
+        None => Err(HttpError::BadRequest(Status {
+            message: "No user agent present".to_string(),
+        })),
+    }
+}
+
+pub fn create_browser_session(
+    data: &HttpRequest,
+    signer: &TokenSigner,
+) -> Result<String, HttpError> {
+    let user_agent = user_agent_of(data)?;
+    let ip = data.peer_addr().unwrap().ip().to_string();
+
+    Ok(generate_browser_session(ip, user_agent, signer))
+}
+
+/// Verify a browser session token, and return the fingerprint it was created with
+/// and the fingerprint of the client that uses it.
+pub fn browser_fingerprints(
+    token: &str,
+    data: &HttpRequest,
+    signer: &TokenSigner,
+) -> Result<(Fingerprint, Fingerprint), HttpError> {
+    let user_agent = user_agent_of(data)?;
+    let ip = data.peer_addr().unwrap().ip().to_string();
+    let parsed =
+        parse_browser_cookie(token).map_err(|message| HttpError::BadRequest(Status { message }))?;
+
+    let not_authenticated = |message: &str| {
+        HttpError::Unauthorized(Status {
+            message: message.to_string(),
+        })
+    };
+
+    let actual = match (&parsed.key_id, &parsed.signature) {
+        (Some(key_id), Some(signature)) => {
+            let (payload, _) = token.rsplit_once('.').unwrap();
+            if !signer.verify(key_id, payload, signature) {
+                return Err(not_authenticated("Invalid session signature"));
+            }
+
+            fingerprint(signer, key_id, &ip, &user_agent).unwrap()
+        }
+        _ if signer.accepts_s1() => legacy_fingerprint(&ip, &user_agent),
+        _ => {
+            return Err(not_authenticated(
+                "This session is no longer accepted, please sign in again",
+            ))
         }
     };
-    let ip = data.peer_addr().unwrap().ip().to_string();
-    let parsed_user_agent = parse_user_agent(user_agent.to_str().unwrap().to_string());
 
-    Ok(generate_browser_session(ip, parsed_user_agent))
+    Ok((parsed.fingerprint, actual))
+}
+
+pub fn generate_api_session() -> String {
+    format!("{}.{}", API_SESSION_PREFIX, random_string(64))
+}
+
+/// Create a session token of the requested kind.
+pub fn create_session(
+    kind: SessionKind,
+    data: &HttpRequest,
+    signer: &TokenSigner,
+) -> Result<String, HttpError> {
+    match kind {
+        SessionKind::Browser => create_browser_session(data, signer),
+        SessionKind::Api => Ok(generate_api_session()),
+    }
+}
+
+/// The cookie that stores a session token in the browser, API sessions are
+/// not stored in a cookie.
+pub fn session_cookie(
+    config: &SessionConfig,
+    kind: SessionKind,
+    token: String,
+) -> Option<Cookie<'static>> {
+    if kind != SessionKind::Browser {
+        return None;
+    }
+
+    let mut cookie = base_cookie(config, token);
+    cookie.set_max_age(Duration::seconds(config.ttl as i64));
+    Some(cookie)
+}
+
+/// A cookie that removes the session token from the browser.
+pub fn removal_cookie(config: &SessionConfig) -> Cookie<'static> {
+    let mut cookie = base_cookie(config, String::new());
+    cookie.make_removal();
+    cookie
+}
+
+fn base_cookie(config: &SessionConfig, value: String) -> Cookie<'static> {
+    let same_site = match config.same_site {
+        SameSitePolicy::Strict => SameSite::Strict,
+        SameSitePolicy::Lax => SameSite::Lax,
+        SameSitePolicy::None => SameSite::None,
+    };
+
+    let mut cookie = Cookie::build(config.key.clone(), value)
+        .path("/")
+        .http_only(true)
+        .secure(config.secure)
+        .same_site(same_site)
+        .finish();
+
+    if let Some(domain) = &config.cookie_domain {
+        cookie.set_domain(domain.clone());
+    }
+
+    cookie
 }
@@ -0,0 +1,289 @@
+// Sessions in temporary storage.
+//
+// A session is stored under the hash of its token, so the token itself is never
+// stored. The keys that are used:
+// - `session:<id>` contains the id of the user, so `drop_all(user_id)` removes
+//   every session of a user. It expires at the end of the absolute lifetime.
+// - `session-details:<id>` contains the `SessionDetails` as JSON. It expires
+//   when the session has not been used for the idle timeout, which is extended
+//   every time the session is used.
+// - `sessions:<user_id>` contains the ids of the sessions of a user as JSON, the
+//   oldest first. It holds at most `max_sessions` ids.
+//
+// Older versions stored the id of the user under the `s1` token itself, those are
+// moved by `migrate_legacy` when they are used while `tokens.accept_s1` is on.
+use actix_web::HttpRequest;
+use chrono::Utc;
+use serde::{de::DeserializeOwned, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    config::SessionConfig,
+    errors::{StorageError, StorageResult},
+    structs::session::{SessionDetails, SessionKind},
+    util::{hashing::sha256_hex, parse::parse_user_agent, signing::TokenSigner, Database},
+};
+
+/// The amount of seconds between updates of the last use of a session, so the
+/// idle timeout is extended at most once per interval.
+const TOUCH_INTERVAL: usize = 60;
+
+/// The id of the session of a token.
+pub fn session_id(token: &str) -> String {
+    sha256_hex(token)
+}
+
+fn session_key(id: &str) -> String {
+    format!("session:{}", id)
+}
+
+fn details_key(id: &str) -> String {
+    format!("session-details:{}", id)
+}
+
+fn index_key(user_id: Uuid) -> String {
+    format!("sessions:{}", user_id)
+}
+
+fn now() -> usize {
+    Utc::now().timestamp() as usize
+}
+
+async fn get_json<T: DeserializeOwned>(db: &Database, key: String) -> StorageResult<Option<T>> {
+    match db.temporary.get(key).await? {
+        Some(value) => serde_json::from_str(&value)
+            .map(Some)
+            .map_err(|e| StorageError::Conflict(format!("Malformed session data: {}", e))),
+        None => Ok(None),
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> StorageResult<String> {
+    serde_json::to_string(value)
+        .map_err(|e| StorageError::Conflict(format!("Unserializable session data: {}", e)))
+}
+
+async fn set_json<T: Serialize>(db: &Database, key: String, value: &T) -> StorageResult<()> {
+    db.temporary.set(key, to_json(value)?).await
+}
+
+/// Store the details until the session expires.
+async fn set_details(db: &Database, id: &str, details: &SessionDetails) -> StorageResult<()> {
+    let ttl = details.expires_at.saturating_sub(now()).max(1);
+    db.temporary
+        .set_with_ttl(details_key(id), to_json(details)?, ttl)
+        .await
+}
+
+async fn get_index(db: &Database, user_id: Uuid) -> StorageResult<Vec<String>> {
+    Ok(get_json(db, index_key(user_id)).await?.unwrap_or_default())
+}
+
+/// Store a new session for the user, with the details of the request that created it.
+/// The IP address is stored as a keyed hash, so it can not be recovered from storage.
+/// Returns the amount of seconds until the session expires when it is not used.
+pub async fn start(
+    db: &Database,
+    config: &SessionConfig,
+    signer: &TokenSigner,
+    token: &str,
+    kind: SessionKind,
+    user_id: Uuid,
+    request: &HttpRequest,
+) -> StorageResult<usize> {
+    let id = session_id(token);
+    let platforms = request
+        .headers()
+        .get("User-Agent")
+        .and_then(|agent| agent.to_str().ok())
+        .map(|agent| parse_user_agent(agent.to_string()).platforms)
+        .unwrap_or_default();
+    let ip = request
+        .peer_addr()
+        .and_then(|addr| signer.fingerprint(signer.signing_key(), &addr.ip().to_string()))
+        .unwrap_or_default();
+
+    let now = now();
+    let details = SessionDetails {
+        user_id,
+        kind,
+        created_at: now,
+        last_used_at: now,
+        expires_at: now + config.idle_timeout.min(config.ttl),
+        ends_at: now + config.ttl,
+        platforms,
+        ip,
+    };
+
+    db.temporary
+        .set_with_ttl(session_key(&id), user_id.to_string(), config.ttl)
+        .await?;
+    set_details(db, &id, &details).await?;
+
+    // Concurrent logins of the same user can lose an id here, that session is
+    // then not listed. Revoking sessions does not rely on the index, so it is
+    // still removed by `revoke_all` and `revoke_others`.
+    let mut index = Vec::new();
+    for session in get_index(db, user_id).await? {
+        if user_of(db, &session).await?.is_some() {
+            index.push(session);
+        }
+    }
+    index.push(id);
+
+    // The index has to stay small enough to be read, the oldest sessions make room.
+    let excess = index.len().saturating_sub(config.max_sessions);
+    for session in index.drain(..excess) {
+        db.temporary.delete(session_key(&session)).await?;
+        db.temporary.delete(details_key(&session)).await?;
+    }
+    set_json(db, index_key(user_id), &index).await?;
+
+    Ok(details.expires_at - now)
+}
+
+/// Move a session of an older version to the current keys, those were stored under
+/// the token itself. Does nothing when there is no such session.
+pub async fn migrate_legacy(
+    db: &Database,
+    config: &SessionConfig,
+    signer: &TokenSigner,
+    token: &str,
+    request: &HttpRequest,
+) -> StorageResult<()> {
+    let user_id = match db.temporary.get(token.to_string()).await? {
+        Some(user_id) => user_id,
+        None => return Ok(()),
+    };
+    db.temporary.delete(token.to_string()).await?;
+
+    let user_id = Uuid::parse_str(&user_id)
+        .map_err(|e| StorageError::Conflict(format!("Malformed legacy session: {}", e)))?;
+    start(
+        db,
+        config,
+        signer,
+        token,
+        SessionKind::Browser,
+        user_id,
+        request,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// The id of the user that owns a session.
+async fn user_of(db: &Database, id: &str) -> StorageResult<Option<String>> {
+    db.temporary.get(session_key(id)).await
+}
+
+/// The details of a session that has not expired.
+async fn active_details(db: &Database, id: &str) -> StorageResult<Option<SessionDetails>> {
+    if user_of(db, id).await?.is_none() {
+        return Ok(None);
+    }
+
+    match get_json::<SessionDetails>(db, details_key(id)).await? {
+        Some(details) if details.expires_at > now() => Ok(Some(details)),
+        _ => Ok(None),
+    }
+}
+
+/// Use a session, this extends its idle timeout up to the end of its lifetime.
+/// Returns `None` if the session does not exist or has expired.
+pub async fn resume(
+    db: &Database,
+    config: &SessionConfig,
+    id: &str,
+) -> StorageResult<Option<SessionDetails>> {
+    let mut details = match active_details(db, id).await? {
+        Some(details) => details,
+        None => return Ok(None),
+    };
+
+    // Short idle timeouts are extended more often, so they do not lapse in between.
+    let interval = TOUCH_INTERVAL.min(config.idle_timeout / 2);
+    let now = now();
+    if now >= details.last_used_at + interval {
+        details.last_used_at = now;
+        details.expires_at = (now + config.idle_timeout).min(details.ends_at);
+
+        if let Err(e) = set_details(db, id, &details).await {
+            log::warn!("Could not extend a session: {}", e);
+        }
+    }
+
+    Ok(Some(details))
+}
+
+/// The active sessions of a user, with their ids.
+pub async fn list(db: &Database, user_id: Uuid) -> StorageResult<Vec<(String, SessionDetails)>> {
+    let index = get_index(db, user_id).await?;
+    let mut sessions = Vec::with_capacity(index.len());
+
+    for id in &index {
+        if let Some(details) = active_details(db, id).await? {
+            if details.user_id == user_id {
+                sessions.push((id.clone(), details));
+            }
+        }
+    }
+
+    // Forget the sessions that have expired.
+    if sessions.len() != index.len() {
+        let ids: Vec<&String> = sessions.iter().map(|(id, _)| id).collect();
+        set_json(db, index_key(user_id), &ids).await?;
+    }
+
+    Ok(sessions)
+}
+
+/// Remove a single session of a user, returns false if the user has no such session.
+pub async fn revoke(db: &Database, user_id: Uuid, id: &str) -> StorageResult<bool> {
+    if user_of(db, id).await? != Some(user_id.to_string()) {
+        return Ok(false);
+    }
+
+    db.temporary.delete(session_key(id)).await?;
+    db.temporary.delete(details_key(id)).await?;
+
+    let mut index = get_index(db, user_id).await?;
+    index.retain(|session| session != id);
+    set_json(db, index_key(user_id), &index).await?;
+
+    Ok(true)
+}
+
+/// Remove every session of a user except `keep`, e.g. the session that is used.
+/// Every session is removed and `keep` is stored again, so sessions that are
+/// missing from the index are removed as well.
+pub async fn revoke_others(db: &Database, user_id: Uuid, keep: &str) -> StorageResult<()> {
+    let kept = active_details(db, keep)
+        .await?
+        .filter(|details| details.user_id == user_id);
+
+    revoke_all(db, user_id).await?;
+
+    if let Some(details) = kept {
+        let ttl = details.ends_at.saturating_sub(now()).max(1);
+        db.temporary
+            .set_with_ttl(session_key(keep), user_id.to_string(), ttl)
+            .await?;
+        set_details(db, keep, &details).await?;
+        set_json(db, index_key(user_id), &[keep]).await?;
+    }
+
+    Ok(())
+}
+
+/// Remove every session of a user.
+pub async fn revoke_all(db: &Database, user_id: Uuid) -> StorageResult<()> {
+    db.temporary.drop_all(user_id.to_string()).await?;
+
+    for id in get_index(db, user_id).await? {
+        db.temporary.delete(details_key(&id)).await?;
+    }
+
+    db.temporary.delete(index_key(user_id)).await
+}
@@ -0,0 +1,107 @@
+// Keyed hashes and signatures for session tokens, so their contents can not be
+// reproduced or changed without one of the configured secrets.
+use std::collections::HashMap;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::config::TokenConfig;
+
+use super::random::random_string;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The amount of bytes of a keyed hash that are kept for a fingerprint.
+const FINGERPRINT_LENGTH: usize = 8;
+/// The amount of bytes of a signature that are kept.
+const SIGNATURE_LENGTH: usize = 16;
+/// The id of the key that is generated when no keys are configured.
+const EPHEMERAL_KEY_ID: &str = "ephemeral";
+
+pub struct TokenSigner {
+    signing_key: String,
+    keys: HashMap<String, Vec<u8>>,
+    accept_s1: bool,
+}
+
+impl TokenSigner {
+    pub fn new(config: &TokenConfig) -> Self {
+        let (signing_key, keys) = match &config.signing_key {
+            Some(id) => (
+                id.clone(),
+                config
+                    .keys
+                    .iter()
+                    .map(|(id, secret)| (id.clone(), secret.as_bytes().to_vec()))
+                    .collect(),
+            ),
+            None => {
+                log::warn!("No token keys are configured, sessions will end when the server stops");
+                let secret = random_string(64).into_bytes();
+                (
+                    EPHEMERAL_KEY_ID.to_string(),
+                    HashMap::from([(EPHEMERAL_KEY_ID.to_string(), secret)]),
+                )
+            }
+        };
+
+        if config.accept_s1 {
+            log::warn!("Unsigned s1 session tokens are accepted, turn off tokens.accept_s1 once the upgrade is done");
+        }
+
+        Self {
+            signing_key,
+            keys,
+            accept_s1: config.accept_s1,
+        }
+    }
+
+    /// The id of the key that signs new tokens.
+    pub fn signing_key(&self) -> &str {
+        &self.signing_key
+    }
+
+    /// If a key with this id is configured.
+    pub fn has_key(&self, key_id: &str) -> bool {
+        self.keys.contains_key(key_id)
+    }
+
+    /// If unsigned `s1` tokens are still accepted.
+    pub fn accepts_s1(&self) -> bool {
+        self.accept_s1
+    }
+
+    fn mac(&self, key_id: &str) -> Option<HmacSha256> {
+        let secret = self.keys.get(key_id)?;
+        Some(HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length"))
+    }
+
+    /// A keyed hash of a part of a fingerprint, `None` if the key is unknown.
+    pub fn fingerprint(&self, key_id: &str, value: &str) -> Option<String> {
+        let mut mac = self.mac(key_id)?;
+        mac.update(value.as_bytes());
+        Some(hex::encode(
+            &mac.finalize().into_bytes()[..FINGERPRINT_LENGTH],
+        ))
+    }
+
+    /// Sign data with the current key.
+    pub fn sign(&self, data: &str) -> String {
+        let mut mac = self
+            .mac(&self.signing_key)
+            .expect("the signing key is configured");
+        mac.update(data.as_bytes());
+        hex::encode(&mac.finalize().into_bytes()[..SIGNATURE_LENGTH])
+    }
+
+    /// Check the signature of data, in constant time.
+    pub fn verify(&self, key_id: &str, data: &str, signature: &str) -> bool {
+        let (mut mac, signature) = match (self.mac(key_id), hex::decode(signature)) {
+            (Some(mac), Ok(signature)) if signature.len() == SIGNATURE_LENGTH => (mac, signature),
+            _ => return false,
+        };
+
+        mac.update(data.as_bytes());
+        mac.verify_truncated_left(&signature).is_ok()
+    }
+}
@@ -0,0 +1,191 @@
+// Time-based one-time passwords (RFC 6238), as generated by authenticator apps.
+//
+// The secret and the hashes of the recovery codes are stored as the value of the
+// TOTP authentication method. The last time step that was used is kept in
+// temporary storage under `totp-step:<user_id>`, so a code can not be used twice.
+use chrono::Utc;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::{thread_rng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use uuid::Uuid;
+
+use crate::{
+    errors::{StorageError, StorageResult},
+    util::{hashing::sha256_hex, random::random_string, Database},
+};
+
+type HmacSha1 = Hmac<Sha1>;
+
+/// The amount of seconds a code is valid.
+pub const PERIOD: u64 = 30;
+pub const DIGITS: u32 = 6;
+/// The amount of steps a code may be off, for clocks that are not in sync.
+const SKEW: u64 = 1;
+const SECRET_LENGTH: usize = 20;
+const RECOVERY_CODES: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 12;
+
+/// The TOTP method of a user, as it is stored.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TotpMethod {
+    /// The base32 encoded secret.
+    pub secret: String,
+    /// The hashes of the recovery codes that have not been used.
+    pub recovery_codes: Vec<String>,
+}
+
+impl TotpMethod {
+    pub fn parse(value: &str) -> StorageResult<Self> {
+        serde_json::from_str(value)
+            .map_err(|e| StorageError::Conflict(format!("Malformed TOTP method: {}", e)))
+    }
+
+    pub fn to_value(&self) -> StorageResult<String> {
+        serde_json::to_string(self).map_err(|e| StorageError::Conflict(e.to_string()))
+    }
+
+    /// Remove a recovery code, returns false if it is not one of the codes.
+    pub fn use_recovery_code(&mut self, code: &str) -> bool {
+        let hash = sha256_hex(&normalize_recovery_code(code));
+        let before = self.recovery_codes.len();
+        self.recovery_codes.retain(|stored| *stored != hash);
+        self.recovery_codes.len() != before
+    }
+}
+
+/// A new random base32 encoded secret.
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_LENGTH];
+    thread_rng().fill_bytes(&mut secret);
+    BASE32_NOPAD.encode(&secret)
+}
+
+/// New recovery codes, and the hashes that are stored.
+pub fn generate_recovery_codes() -> (Vec<String>, Vec<String>) {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let code = random_string(RECOVERY_CODE_LENGTH).to_lowercase();
+            let hash = sha256_hex(&code);
+            (code, hash)
+        })
+        .unzip()
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.trim().replace('-', "").to_lowercase()
+}
+
+/// The URI authenticator apps read from a QR code.
+pub fn provisioning_uri(issuer: &str, account: &str, secret: &str) -> String {
+    fn encode(value: &str) -> String {
+        value
+            .bytes()
+            .map(|byte| match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    (byte as char).to_string()
+                }
+                _ => format!("%{:02X}", byte),
+            })
+            .collect()
+    }
+
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        encode(issuer),
+        encode(account),
+        secret,
+        encode(issuer),
+        DIGITS,
+        PERIOD
+    )
+}
+
+/// The time step of a unix timestamp.
+pub fn step_at(timestamp: u64) -> u64 {
+    timestamp / PERIOD
+}
+
+/// The code of a raw secret at a time step.
+pub fn code_at(secret: &[u8], step: u64, digits: u32) -> u32 {
+    let mut mac = HmacSha1::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    binary % 10u32.pow(digits)
+}
+
+/// The time step a code belongs to, if it is valid around `timestamp`.
+pub fn matching_step(secret: &str, code: &str, timestamp: u64) -> Option<u64> {
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+
+    let step = step_at(timestamp);
+    (step.saturating_sub(SKEW)..=step + SKEW).find(|step| code_at(&secret, *step, DIGITS) == code)
+}
+
+/// The amount of seconds to confirm an enrollment with a first code.
+const ENROLLMENT_TTL: usize = 10 * 60;
+
+fn enrollment_key(user_id: Uuid) -> String {
+    format!("totp-enrollment:{}", user_id)
+}
+
+/// Start the enrollment of a user, it is stored until it is confirmed with a code.
+pub async fn start_enrollment(db: &Database, user_id: Uuid) -> StorageResult<String> {
+    let secret = generate_secret();
+    db.temporary
+        .set_with_ttl(enrollment_key(user_id), secret.clone(), ENROLLMENT_TTL)
+        .await?;
+    Ok(secret)
+}
+
+/// The secret of the enrollment of a user that is not confirmed yet.
+pub async fn pending_enrollment(db: &Database, user_id: Uuid) -> StorageResult<Option<String>> {
+    db.temporary.get(enrollment_key(user_id)).await
+}
+
+pub async fn finish_enrollment(db: &Database, user_id: Uuid) -> StorageResult<()> {
+    db.temporary.delete(enrollment_key(user_id)).await
+}
+
+fn step_key(user_id: Uuid) -> String {
+    format!("totp-step:{}", user_id)
+}
+
+/// Check a code of a user, a code that was used before is rejected.
+pub async fn check(db: &Database, user_id: Uuid, secret: &str, code: &str) -> StorageResult<bool> {
+    let step = match matching_step(secret, code, Utc::now().timestamp() as u64) {
+        Some(step) => step,
+        None => return Ok(false),
+    };
+
+    let last = db
+        .temporary
+        .get(step_key(user_id))
+        .await?
+        .and_then(|last| last.parse::<u64>().ok());
+    if matches!(last, Some(last) if step <= last) {
+        return Ok(false);
+    }
+
+    // Codes are accepted until the step after the current one has passed.
+    let ttl = (PERIOD * (2 * SKEW + 1)) as usize;
+    db.temporary
+        .set_with_ttl(step_key(user_id), step.to_string(), ttl)
+        .await?;
+
+    Ok(true)
+}
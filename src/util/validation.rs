@@ -0,0 +1,19 @@
+use crate::{
+    constants::{MAX_PASSWORD_LENGTH, MIN_PASSWORD_LENGTH},
+    errors::HttpError,
+    structs::Status,
+};
+
+/// Make sure a new password follows the same length rules as registration.
+pub fn password_length(password: &str) -> Result<(), HttpError> {
+    if password.len() < MIN_PASSWORD_LENGTH || password.len() > MAX_PASSWORD_LENGTH {
+        return Err(HttpError::BadRequest(Status {
+            message: format!(
+                "Your password must be between {} and {} characters long.",
+                MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+            ),
+        }));
+    }
+
+    Ok(())
+}
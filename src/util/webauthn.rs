@@ -0,0 +1,381 @@
+// Passkeys (WebAuthn), key pairs that are kept by the authenticator of the user.
+//
+// The passkeys of a user are stored as the value of the WebAuthn authentication
+// method. Every ceremony signs a challenge that can be used once, it is kept in
+// temporary storage under `webauthn-challenge:<challenge>` and contains the user
+// that adds a passkey, or no user when it is used to sign in.
+//
+// Attestation is not requested, so the attestation statement of a new passkey is
+// not verified. Only ES256 keys are accepted, all authenticators support them.
+use chrono::Utc;
+use ciborium::Value;
+use data_encoding::BASE64URL_NOPAD;
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use rand::{thread_rng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    config::WebauthnConfig,
+    constants::WEBAUTHN_AUTHENTICATION,
+    errors::{StorageError, StorageResult, WebauthnError, WebauthnResult},
+    structs::{
+        user::FullUser,
+        webauthn::{
+            PasskeyAssertion, PasskeyCreationOptions, PasskeyCredential, PasskeyRequestOptions,
+        },
+    },
+    util::Database,
+};
+
+/// The COSE algorithm of ECDSA with P-256 and SHA-256.
+pub const ES256: i64 = -7;
+const USER_PRESENT: u8 = 1 << 0;
+const USER_VERIFIED: u8 = 1 << 2;
+const ATTESTED_CREDENTIAL: u8 = 1 << 6;
+const CHALLENGE_LENGTH: usize = 32;
+const MAX_NAME_LENGTH: usize = 64;
+
+/// A passkey of a user, as it is stored.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Passkey {
+    /// The base64url encoded id of the credential.
+    pub id: String,
+    /// The base64url encoded SEC1 public key.
+    pub public_key: String,
+    /// The amount of signatures the authenticator made, 0 if it does not count them.
+    pub sign_count: u32,
+    pub name: String,
+    pub created_at: usize,
+}
+
+/// The WebAuthn method of a user, as it is stored.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct WebauthnMethod {
+    pub passkeys: Vec<Passkey>,
+}
+
+impl WebauthnMethod {
+    pub fn parse(value: &str) -> StorageResult<Self> {
+        serde_json::from_str(value)
+            .map_err(|e| StorageError::Conflict(format!("Malformed WebAuthn method: {}", e)))
+    }
+
+    pub fn to_value(&self) -> StorageResult<String> {
+        serde_json::to_string(self).map_err(|e| StorageError::Conflict(e.to_string()))
+    }
+
+    /// The method of a user, without passkeys if they did not add any.
+    pub fn of(user: &FullUser) -> StorageResult<Self> {
+        match user.authentication.get(&WEBAUTHN_AUTHENTICATION) {
+            Some(value) => Self::parse(value),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn find_mut(&mut self, id: &str) -> Option<&mut Passkey> {
+        let id = id.trim_end_matches('=');
+        self.passkeys.iter_mut().find(|passkey| passkey.id == id)
+    }
+}
+
+fn malformed(reason: &str) -> WebauthnError {
+    WebauthnError::Malformed(reason.to_string())
+}
+
+fn rejected(reason: &str) -> WebauthnError {
+    WebauthnError::Rejected(reason.to_string())
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    BASE64URL_NOPAD.encode(bytes)
+}
+
+/// Decode base64url, with or without padding.
+pub fn decode(value: &str) -> WebauthnResult<Vec<u8>> {
+    BASE64URL_NOPAD
+        .decode(value.trim_end_matches('=').as_bytes())
+        .map_err(|_| malformed("invalid base64url"))
+}
+
+/// The handle of a user, authenticators return it when a passkey is used.
+pub fn user_handle(user_id: Uuid) -> String {
+    encode(user_id.as_bytes())
+}
+
+pub fn user_of_handle(handle: &str) -> WebauthnResult<Uuid> {
+    Uuid::from_slice(&decode(handle)?).map_err(|_| malformed("invalid user handle"))
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingChallenge {
+    user_id: Option<Uuid>,
+}
+
+fn challenge_key(challenge: &str) -> String {
+    format!("webauthn-challenge:{}", challenge)
+}
+
+async fn new_challenge(
+    db: &Database,
+    config: &WebauthnConfig,
+    user_id: Option<Uuid>,
+) -> StorageResult<String> {
+    let mut challenge = [0u8; CHALLENGE_LENGTH];
+    thread_rng().fill_bytes(&mut challenge);
+    let challenge = encode(&challenge);
+
+    let pending = serde_json::to_string(&PendingChallenge { user_id })
+        .map_err(|e| StorageError::Conflict(e.to_string()))?;
+    db.temporary
+        .set_with_ttl(challenge_key(&challenge), pending, config.challenge_ttl)
+        .await?;
+
+    Ok(challenge)
+}
+
+/// Use a challenge, returns false if it expired, was used or was issued for
+/// another user. Sign ins use challenges without a user.
+pub async fn take_challenge(
+    db: &Database,
+    challenge: &str,
+    user_id: Option<Uuid>,
+) -> StorageResult<bool> {
+    let pending = match db.temporary.get(challenge_key(challenge)).await? {
+        Some(pending) => pending,
+        None => return Ok(false),
+    };
+    db.temporary.delete(challenge_key(challenge)).await?;
+
+    let pending: PendingChallenge = serde_json::from_str(&pending)
+        .map_err(|e| StorageError::Conflict(format!("Malformed WebAuthn challenge: {}", e)))?;
+    Ok(pending.user_id == user_id)
+}
+
+/// Start adding a passkey for a user.
+pub async fn start_registration(
+    db: &Database,
+    config: &WebauthnConfig,
+    user: &FullUser,
+) -> StorageResult<PasskeyCreationOptions> {
+    let method = WebauthnMethod::of(user)?;
+    let challenge = new_challenge(db, config, Some(user.id)).await?;
+
+    Ok(PasskeyCreationOptions {
+        challenge,
+        rp_id: config.rp_id.clone(),
+        rp_name: config.rp_name.clone(),
+        user_id: user_handle(user.id),
+        user_name: user.username.clone(),
+        algorithms: vec![ES256],
+        exclude_credentials: method.passkeys.iter().map(|p| p.id.clone()).collect(),
+        ttl: config.challenge_ttl,
+    })
+}
+
+/// Start signing in with a passkey, any passkey can sign the challenge.
+pub async fn start_login(
+    db: &Database,
+    config: &WebauthnConfig,
+) -> StorageResult<PasskeyRequestOptions> {
+    Ok(PasskeyRequestOptions {
+        challenge: new_challenge(db, config, None).await?,
+        rp_id: config.rp_id.clone(),
+        ttl: config.challenge_ttl,
+    })
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+/// Check that the client data was made for a ceremony of `kind` on our website,
+/// returns the challenge it contains.
+pub fn client_challenge(
+    config: &WebauthnConfig,
+    client_data_json: &[u8],
+    kind: &str,
+) -> WebauthnResult<String> {
+    let data: ClientData = serde_json::from_slice(client_data_json)
+        .map_err(|e| WebauthnError::Malformed(format!("invalid client data: {}", e)))?;
+
+    if data.kind != kind {
+        return Err(rejected("the client data belongs to another ceremony"));
+    }
+
+    if data.origin != config.origin {
+        return Err(rejected("the passkey was used on another website"));
+    }
+
+    Ok(data.challenge)
+}
+
+struct AuthenticatorData<'a> {
+    flags: u8,
+    sign_count: u32,
+    /// The attested credential data and extensions.
+    rest: &'a [u8],
+}
+
+/// Decode the authenticator data and check that it was made for our domain by a
+/// user that was verified.
+fn authenticator_data<'a>(
+    config: &WebauthnConfig,
+    bytes: &'a [u8],
+) -> WebauthnResult<AuthenticatorData<'a>> {
+    if bytes.len() < 37 {
+        return Err(malformed("the authenticator data is too short"));
+    }
+
+    if bytes[..32] != Sha256::digest(config.rp_id.as_bytes())[..] {
+        return Err(rejected("the passkey belongs to another domain"));
+    }
+
+    let data = AuthenticatorData {
+        flags: bytes[32],
+        sign_count: u32::from_be_bytes([bytes[33], bytes[34], bytes[35], bytes[36]]),
+        rest: &bytes[37..],
+    };
+
+    if data.flags & USER_PRESENT == 0 || data.flags & USER_VERIFIED == 0 {
+        return Err(rejected("the authenticator did not verify the user"));
+    }
+
+    Ok(data)
+}
+
+/// The id and public key of a new passkey, from the attested credential data.
+fn attested_credential(data: &[u8]) -> WebauthnResult<(Vec<u8>, VerifyingKey)> {
+    // The AAGUID of the authenticator (16 bytes), the length of the id (2 bytes),
+    // the id and the public key.
+    if data.len() < 18 {
+        return Err(malformed("the attested credential data is too short"));
+    }
+    let length = u16::from_be_bytes([data[16], data[17]]) as usize;
+    let id = data
+        .get(18..18 + length)
+        .ok_or_else(|| malformed("the attested credential data is too short"))?;
+
+    let key: Value = ciborium::de::from_reader(&data[18 + length..])
+        .map_err(|_| malformed("the public key is not valid CBOR"))?;
+
+    Ok((id.to_vec(), cose_key(&key)?))
+}
+
+/// Decode an ES256 public key in the COSE format.
+fn cose_key(key: &Value) -> WebauthnResult<VerifyingKey> {
+    let entries = key
+        .as_map()
+        .ok_or_else(|| malformed("the public key is not a COSE key"))?;
+    let get = |label: i64| {
+        entries
+            .iter()
+            .find(|(key, _)| key.as_integer().map(i128::from) == Some(label.into()))
+            .map(|(_, value)| value)
+    };
+    let integer = |label: i64| get(label).and_then(Value::as_integer).map(i128::from);
+
+    // The key type (EC2), the algorithm (ES256) and the curve (P-256).
+    if integer(1) != Some(2) || integer(3) != Some(ES256.into()) || integer(-1) != Some(1) {
+        return Err(rejected("only ES256 passkeys are supported"));
+    }
+
+    let coordinate = |label: i64| {
+        get(label)
+            .and_then(Value::as_bytes)
+            .filter(|coordinate| coordinate.len() == 32)
+    };
+    let (x, y) = match (coordinate(-2), coordinate(-3)) {
+        (Some(x), Some(y)) => (x, y),
+        _ => return Err(malformed("the public key has invalid coordinates")),
+    };
+
+    // An uncompressed SEC1 point.
+    let mut point = vec![0x04];
+    point.extend_from_slice(x);
+    point.extend_from_slice(y);
+    VerifyingKey::from_sec1_bytes(&point).map_err(|_| rejected("the public key is not valid"))
+}
+
+/// Verify a new passkey. The challenge in the client data has to be taken first.
+pub fn verify_registration(
+    config: &WebauthnConfig,
+    credential: &PasskeyCredential,
+) -> WebauthnResult<Passkey> {
+    let name = match credential.name.as_deref().map(str::trim) {
+        Some(name) if name.chars().count() > MAX_NAME_LENGTH => {
+            return Err(malformed("the name of the passkey is too long"))
+        }
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => "Passkey".to_string(),
+    };
+
+    let attestation: Value =
+        ciborium::de::from_reader(decode(&credential.attestation_object)?.as_slice())
+            .map_err(|_| malformed("the attestation object is not valid CBOR"))?;
+    let auth_data = attestation
+        .as_map()
+        .and_then(|entries| {
+            entries
+                .iter()
+                .find(|(key, _)| key.as_text() == Some("authData"))
+        })
+        .and_then(|(_, value)| value.as_bytes())
+        .ok_or_else(|| malformed("the attestation object has no authenticator data"))?;
+
+    let data = authenticator_data(config, auth_data)?;
+    if data.flags & ATTESTED_CREDENTIAL == 0 {
+        return Err(malformed("the authenticator data has no public key"));
+    }
+
+    let (id, key) = attested_credential(data.rest)?;
+    let id = encode(&id);
+    if id != credential.id.trim_end_matches('=') {
+        return Err(malformed("the id does not match the passkey"));
+    }
+
+    Ok(Passkey {
+        id,
+        public_key: encode(key.to_encoded_point(false).as_bytes()),
+        sign_count: data.sign_count,
+        name,
+        created_at: Utc::now().timestamp() as usize,
+    })
+}
+
+/// Verify a challenge that was signed with a passkey, returns the new sign count.
+/// The challenge in the client data has to be taken first.
+pub fn verify_assertion(
+    config: &WebauthnConfig,
+    passkey: &Passkey,
+    client_data_json: &[u8],
+    assertion: &PasskeyAssertion,
+) -> WebauthnResult<u32> {
+    let bytes = decode(&assertion.authenticator_data)?;
+    let data = authenticator_data(config, &bytes)?;
+
+    let signature = Signature::from_der(&decode(&assertion.signature)?)
+        .map_err(|_| malformed("the signature is not a DER encoded ECDSA signature"))?;
+    let key = VerifyingKey::from_sec1_bytes(&decode(&passkey.public_key)?)
+        .map_err(|_| rejected("the stored public key is not valid"))?;
+
+    let mut signed = bytes.clone();
+    signed.extend_from_slice(&Sha256::digest(client_data_json));
+    key.verify(&signed, &signature)
+        .map_err(|_| rejected("invalid signature"))?;
+
+    // Authenticators that count their signatures always increase the count, when
+    // it does not increase the passkey was copied to another authenticator.
+    if (data.sign_count != 0 || passkey.sign_count != 0) && data.sign_count <= passkey.sign_count {
+        return Err(rejected(
+            "the sign count did not increase, the passkey may be cloned",
+        ));
+    }
+
+    Ok(data.sign_count)
+}
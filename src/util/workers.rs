@@ -0,0 +1,91 @@
+// A bounded pool for CPU heavy work (eg password hashing), so it does not run on
+// the async executor and a burst of requests can not starve the other endpoints.
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use tokio::{sync::Semaphore, task};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum WorkerError {
+    /// Too many jobs are already waiting for a worker.
+    QueueFull,
+    /// The job panicked while running.
+    Failed,
+}
+
+impl Display for WorkerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorkerError::QueueFull => write!(f, "The worker queue is full"),
+            WorkerError::Failed => write!(f, "The job failed to run"),
+        }
+    }
+}
+
+pub struct WorkerPool {
+    permits: Arc<Semaphore>,
+    /// The amount of jobs that are running or waiting for a worker.
+    pending: Arc<AtomicUsize>,
+    capacity: usize,
+}
+
+/// Marks a job as finished when it is dropped, even if the request was cancelled
+/// before the job started.
+struct PendingGuard(Arc<AtomicUsize>);
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl WorkerPool {
+    /// Create a pool that runs at most `workers` jobs at the same time and lets
+    /// at most `queue` jobs wait for a worker.
+    pub fn new(workers: usize, queue: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(workers)),
+            pending: Arc::new(AtomicUsize::new(0)),
+            capacity: workers + queue,
+        }
+    }
+
+    /// Run a blocking job on the pool.
+    pub async fn run<F, T>(&self, job: F) -> Result<T, WorkerError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let reserved = self
+            .pending
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| {
+                (pending < self.capacity).then_some(pending + 1)
+            });
+        if reserved.is_err() {
+            return Err(WorkerError::QueueFull);
+        }
+        let guard = PendingGuard(self.pending.clone());
+
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| WorkerError::Failed)?;
+
+        // The job keeps its slot until it finished, also when the request that
+        // started it is cancelled while it runs.
+        task::spawn_blocking(move || {
+            let _guard = guard;
+            let _permit = permit;
+            job()
+        })
+        .await
+        .map_err(|_| WorkerError::Failed)
+    }
+}
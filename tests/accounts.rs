@@ -0,0 +1,157 @@
+mod common;
+
+use actix_web::{http::StatusCode, test, test::TestRequest};
+use serde_json::{json, Value};
+
+use common::{browser, registration, with_session, CHROME, HOME, PASSWORD};
+
+#[actix_web::test]
+async fn register_returns_user_and_session() {
+    let db = common::database();
+    let app = common::init(&db).await;
+
+    let request = browser(TestRequest::post().uri("/register"), CHROME, HOME)
+        .set_json(registration("arthur"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["user"]["username"], "arthur");
+    assert_eq!(body["user"]["email"], "arthur@xiler.net");
+    assert_eq!(body["user"]["verified"], false);
+    assert!(body["session"]["token"]
+        .as_str()
+        .unwrap()
+        .starts_with("s2."));
+}
+
+#[actix_web::test]
+async fn register_rejects_duplicates_and_invalid_input() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    common::register(&app, "arthur").await;
+
+    let mut taken_email = registration("ford");
+    taken_email["email"] = json!("arthur@xiler.net");
+    let short_password =
+        json!({ "username": "zaphod", "email": "zaphod@xiler.net", "password": "42" });
+
+    for body in [registration("arthur"), taken_email, short_password] {
+        let request = browser(TestRequest::post().uri("/register"), CHROME, HOME)
+            .set_json(body)
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
+#[actix_web::test]
+async fn login_with_username_or_email() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    common::register(&app, "arthur").await;
+
+    for username in ["arthur", "arthur@xiler.net"] {
+        let request = browser(TestRequest::post().uri("/login"), CHROME, HOME)
+            .set_json(json!({ "username": username, "password": PASSWORD }))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: Value = test::read_body_json(response).await;
+        let token = body["token"].as_str().unwrap();
+
+        let request =
+            with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), token).to_request();
+        let me: Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(me["username"], "arthur");
+    }
+}
+
+#[actix_web::test]
+async fn login_rejects_wrong_credentials() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    common::register(&app, "arthur").await;
+
+    for (username, password) in [("arthur", "not the password"), ("trillian", PASSWORD)] {
+        let request = browser(TestRequest::post().uri("/login"), CHROME, HOME)
+            .set_json(json!({ "username": username, "password": password }))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
+
+#[actix_web::test]
+async fn me_requires_a_session() {
+    let db = common::database();
+    let app = common::init(&db).await;
+
+    let request = browser(TestRequest::get().uri("/me"), CHROME, HOME).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let request = with_session(
+        browser(TestRequest::get().uri("/me"), CHROME, HOME),
+        "s1.0.0-0-0.0.unknown",
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn logout_ends_every_session() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let request = browser(TestRequest::post().uri("/login"), CHROME, HOME)
+        .set_json(json!({ "username": "arthur", "password": PASSWORD }))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, request).await;
+    let other = body["token"].as_str().unwrap().to_string();
+
+    let request = with_session(
+        browser(TestRequest::delete().uri("/logout"), CHROME, HOME),
+        &token,
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    for token in [token, other] {
+        let request =
+            with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
+
+#[actix_web::test]
+async fn delete_account_removes_user_and_sessions() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let request = with_session(
+        browser(TestRequest::delete().uri("/me"), CHROME, HOME),
+        &token,
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = browser(TestRequest::post().uri("/login"), CHROME, HOME)
+        .set_json(json!({ "username": "arthur", "password": PASSWORD }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,339 @@
+mod common;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceResponse},
+    http::StatusCode,
+    test::{self, TestRequest},
+};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use accounts_rest_api::{
+    structs::role::{Role, Roles},
+    types::FullDatabase,
+};
+use common::{browser, with_session, CHROME, HOME, PASSWORD};
+
+/// Register a user with roles, returns their id and session token.
+async fn staff<S, B>(app: &S, db: &FullDatabase, username: &str, roles: &[Role]) -> (String, String)
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let response = common::register(app, username).await;
+    let id = response["user"]["id"].as_str().unwrap().to_string();
+    let roles: Roles = roles.iter().copied().collect();
+    db.persistent
+        .set_roles(Uuid::parse_str(&id).unwrap(), roles.bits())
+        .await
+        .unwrap();
+
+    (
+        id,
+        response["session"]["token"].as_str().unwrap().to_string(),
+    )
+}
+
+fn admin(request: TestRequest, token: &str) -> actix_http::Request {
+    with_session(browser(request, CHROME, HOME), token).to_request()
+}
+
+fn login(username: &str) -> actix_http::Request {
+    browser(TestRequest::post().uri("/login"), CHROME, HOME)
+        .set_json(json!({ "username": username, "password": PASSWORD }))
+        .to_request()
+}
+
+#[actix_web::test]
+async fn users_are_listed_in_pages_and_searched() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let (_, token) = staff(&app, &db, "arthur", &[Role::Support]).await;
+    for username in ["ford", "zaphod", "trillian"] {
+        common::register(&app, username).await;
+    }
+
+    let mut usernames = Vec::new();
+    let mut uri = "/admin/users?limit=3".to_string();
+    loop {
+        let page: Value =
+            test::call_and_read_body_json(&app, admin(TestRequest::get().uri(&uri), &token)).await;
+        let users = page["users"].as_array().unwrap();
+        assert!(users.len() <= 3);
+        usernames.extend(users.iter().map(|user| user["username"].clone()));
+
+        match page["next"].as_str() {
+            Some(next) => uri = format!("/admin/users?limit=3&after={}", next),
+            None => break,
+        }
+    }
+    usernames.sort_by_key(|username| username.to_string());
+    assert_eq!(usernames, ["arthur", "ford", "trillian", "zaphod"]);
+
+    let page: Value = test::call_and_read_body_json(
+        &app,
+        admin(
+            TestRequest::get().uri("/admin/users?search=ZAPHOD@"),
+            &token,
+        ),
+    )
+    .await;
+    assert_eq!(page["users"].as_array().unwrap().len(), 1);
+    assert_eq!(page["users"][0]["email"], "zaphod@xiler.net");
+    assert!(page["next"].is_null());
+
+    let response = test::call_service(
+        &app,
+        admin(TestRequest::get().uri("/admin/users?after=nope"), &token),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn users_are_shown_without_their_secrets() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let (id, token) = staff(&app, &db, "arthur", &[Role::Support, Role::Admin]).await;
+
+    let user: Value = test::call_and_read_body_json(
+        &app,
+        admin(
+            TestRequest::get().uri(&format!("/admin/users/{}", id)),
+            &token,
+        ),
+    )
+    .await;
+    assert_eq!(
+        user,
+        json!({
+            "id": id,
+            "username": "arthur",
+            "email": "arthur@xiler.net",
+            "created_at": user["created_at"],
+            "roles": ["support", "admin"],
+            "authentication": 0,
+            "verified": false,
+            "disabled": false,
+        })
+    );
+
+    let response = test::call_service(
+        &app,
+        admin(
+            TestRequest::get().uri(&format!("/admin/users/{}", Uuid::new_v4())),
+            &token,
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn every_action_needs_its_permission() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let (arthur, _) = staff(&app, &db, "arthur", &[]).await;
+    let (_, ford) = staff(&app, &db, "ford", &[]).await;
+    let (_, zaphod) = staff(&app, &db, "zaphod", &[Role::Support]).await;
+    let user = format!("/admin/users/{}", arthur);
+
+    let response =
+        test::call_service(&app, admin(TestRequest::get().uri("/admin/users"), &ford)).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Support can look and verify, but not do anything else.
+    for (request, status) in [
+        (TestRequest::get().uri(&user), StatusCode::OK),
+        (
+            TestRequest::post().uri(&format!("{}/verify", user)),
+            StatusCode::OK,
+        ),
+        (
+            TestRequest::delete().uri(&format!("{}/sessions", user)),
+            StatusCode::FORBIDDEN,
+        ),
+        (
+            TestRequest::post().uri(&format!("{}/disabled", user)),
+            StatusCode::FORBIDDEN,
+        ),
+        (
+            TestRequest::put()
+                .uri(&format!("{}/roles", user))
+                .set_json(json!({ "roles": ["support"] })),
+            StatusCode::FORBIDDEN,
+        ),
+        (TestRequest::delete().uri(&user), StatusCode::FORBIDDEN),
+    ] {
+        let response = test::call_service(&app, admin(request, &zaphod)).await;
+        assert_eq!(response.status(), status);
+    }
+
+    let verified: Value =
+        test::call_and_read_body_json(&app, admin(TestRequest::get().uri(&user), &zaphod)).await;
+    assert_eq!(verified["verified"], true);
+}
+
+#[actix_web::test]
+async fn roles_can_be_changed_by_admins() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let (arthur, token) = staff(&app, &db, "arthur", &[Role::Admin]).await;
+    let (ford, ford_token) = staff(&app, &db, "ford", &[]).await;
+    let roles = |id: &str, token: &str, roles: Value| {
+        admin(
+            TestRequest::put()
+                .uri(&format!("/admin/users/{}/roles", id))
+                .set_json(json!({ "roles": roles })),
+            token,
+        )
+    };
+
+    let user: Value =
+        test::call_and_read_body_json(&app, roles(&ford, &token, json!(["moderator"]))).await;
+    assert_eq!(user["roles"], json!(["moderator"]));
+
+    // Moderators can manage users, but not the ones above them.
+    let response = test::call_service(
+        &app,
+        admin(TestRequest::get().uri("/admin/users"), &ford_token),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = test::call_service(
+        &app,
+        admin(
+            TestRequest::post().uri(&format!("/admin/users/{}/disabled", arthur)),
+            &ford_token,
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Nobody changes their own account here.
+    let response = test::call_service(&app, roles(&arthur, &token, json!([]))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = test::call_service(&app, roles(&ford, &token, json!(["owner"]))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn peers_can_not_manage_each_other() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let (_, token) = staff(&app, &db, "arthur", &[Role::Admin]).await;
+    let (ford, _) = staff(&app, &db, "ford", &[Role::Admin]).await;
+    let (trillian, _) = staff(&app, &db, "trillian", &[Role::Moderator]).await;
+
+    for request in [
+        TestRequest::post().uri(&format!("/admin/users/{}/disabled", ford)),
+        TestRequest::delete().uri(&format!("/admin/users/{}/sessions", ford)),
+        TestRequest::put()
+            .uri(&format!("/admin/users/{}/roles", ford))
+            .set_json(json!({ "roles": [] })),
+        TestRequest::delete().uri(&format!("/admin/users/{}", ford)),
+    ] {
+        let response = test::call_service(&app, admin(request, &token)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    let request = TestRequest::post().uri(&format!("/admin/users/{}/disabled", trillian));
+    let response = test::call_service(&app, admin(request, &token)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn sessions_can_be_revoked() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let (_, token) = staff(&app, &db, "arthur", &[Role::Moderator]).await;
+    let (ford, ford_token) = staff(&app, &db, "ford", &[]).await;
+    let me = || admin(TestRequest::get().uri("/me"), &ford_token);
+
+    let response = test::call_service(&app, me()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = TestRequest::delete().uri(&format!("/admin/users/{}/sessions", ford));
+    let response = test::call_service(&app, admin(request, &token)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = test::call_service(&app, me()).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn disabled_users_can_not_sign_in() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let (_, token) = staff(&app, &db, "arthur", &[Role::Moderator]).await;
+    let (ford, ford_token) = staff(&app, &db, "ford", &[]).await;
+    let disabled = format!("/admin/users/{}/disabled", ford);
+
+    let response =
+        test::call_service(&app, admin(TestRequest::post().uri(&disabled), &token)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response =
+        test::call_service(&app, admin(TestRequest::get().uri("/me"), &ford_token)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, login("ford")).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response =
+        test::call_service(&app, admin(TestRequest::delete().uri(&disabled), &token)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = test::call_service(&app, login("ford")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn sessions_of_disabled_users_are_rejected() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let (ford, token) = staff(&app, &db, "ford", &[]).await;
+
+    // Disabled without going through the API, so the session still exists.
+    db.persistent
+        .set_disabled(Uuid::parse_str(&ford).unwrap(), true)
+        .await
+        .unwrap();
+
+    let response = test::call_service(&app, admin(TestRequest::get().uri("/me"), &token)).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn users_can_be_deleted() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let (_, token) = staff(&app, &db, "arthur", &[Role::Admin]).await;
+    let (ford, ford_token) = staff(&app, &db, "ford", &[]).await;
+    let user = format!("/admin/users/{}", ford);
+
+    let response = test::call_service(&app, admin(TestRequest::delete().uri(&user), &token)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = test::call_service(&app, admin(TestRequest::get().uri(&user), &token)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response =
+        test::call_service(&app, admin(TestRequest::get().uri("/me"), &ford_token)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, login("ford")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn spec_documents_every_method_of_a_user() {
+    let db = common::database();
+    let app = common::init(&db).await;
+
+    let request = TestRequest::get().uri("/spec/v2").to_request();
+    let spec: Value = test::call_and_read_body_json(&app, request).await;
+
+    let user = &spec["paths"]["/admin/users/{id}"];
+    assert!(user["get"].is_object());
+    assert!(user["delete"].is_object());
+}
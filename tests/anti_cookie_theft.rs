@@ -0,0 +1,74 @@
+mod common;
+
+use std::str::FromStr;
+
+use actix_web::{http::StatusCode, test, test::TestRequest};
+use serde_json::Value;
+use uuid::Uuid;
+
+use common::{browser, with_session, CHROME, ELSEWHERE, FIREFOX, HOME};
+
+#[actix_web::test]
+async fn same_browser_on_another_network_is_accepted() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let request = with_session(
+        browser(TestRequest::get().uri("/me"), CHROME, ELSEWHERE),
+        &token,
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn stolen_cookie_removes_the_session() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let request = with_session(
+        browser(TestRequest::get().uri("/me"), FIREFOX, ELSEWHERE),
+        &token,
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::GONE);
+
+    // The rightful owner has lost the session as well.
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn browser_session_requires_a_user_agent() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let request = with_session(TestRequest::get().uri("/me"), &token)
+        .peer_addr(HOME.parse().unwrap())
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn session_of_a_removed_user_is_gone() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let registration: Value = common::register(&app, "arthur").await;
+    let token = registration["session"]["token"].as_str().unwrap();
+
+    let id = Uuid::from_str(registration["user"]["id"].as_str().unwrap()).unwrap();
+    db.persistent.delete_user(id).await.unwrap();
+
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), token).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::GONE);
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use actix_web::{http::StatusCode, test, test::TestRequest};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use accounts_rest_api::constants::{PASSWORD_AUTHENTICATION, WEBAUTHN_AUTHENTICATION};
+
+use common::{browser, with_session, CHROME, HOME};
+
+const GITHUB: i16 = 2;
+
+#[actix_web::test]
+async fn link_and_unlink_a_method() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let uri = format!("/authentication/{}", GITHUB);
+    let request = with_session(browser(TestRequest::put().uri(&uri), CHROME, HOME), &token)
+        .set_json(json!({ "value": "1234567" }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+    let me: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(me["authentication"], GITHUB);
+
+    let request = with_session(
+        browser(TestRequest::delete().uri(&uri), CHROME, HOME),
+        &token,
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = with_session(
+        browser(TestRequest::delete().uri(&uri), CHROME, HOME),
+        &token,
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn methods_must_be_a_power_of_two() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let request = with_session(
+        browser(TestRequest::put().uri("/authentication/3"), CHROME, HOME),
+        &token,
+    )
+    .set_json(json!({ "value": "1234567" }))
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let request = with_session(
+        browser(TestRequest::delete().uri("/authentication/3"), CHROME, HOME),
+        &token,
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn password_can_not_be_overwritten() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let request = with_session(
+        browser(TestRequest::put().uri("/authentication/0"), CHROME, HOME),
+        &token,
+    )
+    .set_json(json!({ "value": "not a hash" }))
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let request = browser(TestRequest::post().uri("/login"), CHROME, HOME)
+        .set_json(json!({ "username": "arthur", "password": common::PASSWORD }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn the_last_primary_method_can_not_be_removed() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let response = common::register(&app, "arthur").await;
+    let token = response["session"]["token"].as_str().unwrap();
+
+    // An account that signs in with a passkey only.
+    let id = Uuid::parse_str(response["user"]["id"].as_str().unwrap()).unwrap();
+    db.persistent
+        .update_authentication_method_value(id, WEBAUTHN_AUTHENTICATION, "[]")
+        .await
+        .unwrap();
+    db.persistent
+        .remove_authentication_method(id, PASSWORD_AUTHENTICATION)
+        .await
+        .unwrap();
+
+    // Not an OAuth provider in this config, so it can not sign in on its own.
+    let uri = format!("/authentication/{}", GITHUB);
+    let request = with_session(browser(TestRequest::put().uri(&uri), CHROME, HOME), token)
+        .set_json(json!({ "value": "1234567" }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = with_session(
+        browser(
+            TestRequest::delete().uri("/authentication/512"),
+            CHROME,
+            HOME,
+        ),
+        token,
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let request = with_session(
+        browser(TestRequest::delete().uri(&uri), CHROME, HOME),
+        token,
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
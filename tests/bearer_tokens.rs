@@ -0,0 +1,157 @@
+mod common;
+
+use actix_web::{
+    http::{header, StatusCode},
+    test,
+    test::TestRequest,
+};
+use serde_json::{json, Value};
+
+use common::{
+    browser, registration, with_bearer, with_session, CHROME, ELSEWHERE, FIREFOX, HOME, PASSWORD,
+};
+
+#[actix_web::test]
+async fn api_sessions_are_not_bound_to_a_browser() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    common::register(&app, "arthur").await;
+
+    let request = TestRequest::post()
+        .uri("/login")
+        .peer_addr(HOME.parse().unwrap())
+        .set_json(json!({ "username": "arthur", "password": PASSWORD, "session": "api" }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(header::SET_COOKIE).is_none());
+
+    let body: Value = test::read_body_json(response).await;
+    let token = body["token"].as_str().unwrap();
+    assert!(token.starts_with("a1."));
+
+    // Neither the user agent nor the address matter for an API session.
+    let requests = [
+        with_bearer(TestRequest::get().uri("/me"), token).peer_addr(ELSEWHERE.parse().unwrap()),
+        with_bearer(
+            browser(TestRequest::get().uri("/me"), FIREFOX, ELSEWHERE),
+            token,
+        ),
+    ];
+    for request in requests {
+        let me: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+        assert_eq!(me["username"], "arthur");
+    }
+}
+
+#[actix_web::test]
+async fn api_session_on_registration() {
+    let db = common::database();
+    let app = common::init(&db).await;
+
+    let mut body = registration("arthur");
+    body["session"] = json!("api");
+    let request = TestRequest::post()
+        .uri("/register")
+        .peer_addr(HOME.parse().unwrap())
+        .set_json(body)
+        .to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    let token = response["session"]["token"].as_str().unwrap();
+    assert!(token.starts_with("a1."));
+
+    let request = with_bearer(TestRequest::get().uri("/me"), token)
+        .peer_addr(HOME.parse().unwrap())
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn browser_sessions_are_only_read_from_the_cookie() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let request = with_bearer(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token);
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn other_authorization_falls_back_to_the_cookie() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    for authorization in [
+        "Basic YXJ0aHVyOjQy",
+        "Bearer ",
+        "Bearer",
+        "Bearer s2.forged",
+    ] {
+        let request = with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token)
+            .insert_header((header::AUTHORIZATION, authorization))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // Without a cookie there is no session to fall back to.
+    let request = browser(TestRequest::get().uri("/me"), CHROME, HOME)
+        .insert_header((header::AUTHORIZATION, "Basic YXJ0aHVyOjQy"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn a_bearer_token_takes_precedence_over_the_cookie() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let request = browser(TestRequest::post().uri("/login"), CHROME, HOME)
+        .set_json(json!({ "username": "arthur", "password": PASSWORD, "session": "api" }))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, request).await;
+    let api_token = body["token"].as_str().unwrap();
+
+    // The API session is used, so signing it out leaves the browser session.
+    let request = with_bearer(
+        with_session(
+            browser(TestRequest::delete().uri("/sessions/current"), CHROME, HOME),
+            &token,
+        ),
+        api_token,
+    );
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token);
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let request = with_bearer(TestRequest::get().uri("/me"), api_token);
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn spec_documents_both_transports() {
+    let db = common::database();
+    let app = common::init(&db).await;
+
+    let request = TestRequest::get().uri("/spec/v2").to_request();
+    let spec: Value = test::call_and_read_body_json(&app, request).await;
+
+    let definitions = &spec["securityDefinitions"];
+    assert_eq!(definitions["session_cookie"]["in"], "cookie");
+    assert_eq!(definitions["session_cookie"]["name"], "xiler-session");
+    assert_eq!(definitions["bearer_token"]["in"], "header");
+    assert_eq!(definitions["bearer_token"]["name"], "Authorization");
+
+    assert_eq!(
+        spec["paths"]["/me"]["get"]["security"],
+        json!([{ "session_cookie": [] }, { "bearer_token": [] }])
+    );
+}
@@ -0,0 +1,225 @@
+#![allow(dead_code)]
+
+use std::{net::SocketAddr, sync::Arc};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceResponse},
+    test::{self, TestRequest},
+    web::Data,
+};
+use serde_json::{json, Value};
+
+use accounts_rest_api::{
+    app::create_app,
+    config::Config,
+    constants::SESSION_KEY,
+    traits::LockoutNotifier,
+    types::{
+        FullConfig, FullDatabase, FullHasher, FullMailer, FullNotifier, FullSigner, RiskScorer,
+    },
+    util::{
+        data::{InMemoryDataProvider, PersistentStorageKind, TemporaryStorageKind},
+        hashing::PasswordHasher,
+        lockout::MailLockoutNotifier,
+        mail::{MailTransport, MailerKind, OutboxMailer},
+        risk::WeightedRiskScorer,
+        signing::TokenSigner,
+        Database,
+    },
+};
+
+pub const CHROME: &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/106.0.0.0 Safari/537.36";
+pub const FIREFOX: &str =
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:105.0) Gecko/20100101 Firefox/105.0";
+
+pub const HOME: &str = "192.168.1.10:51234";
+pub const ELSEWHERE: &str = "10.20.30.40:443";
+
+pub const PASSWORD: &str = "correct horse battery staple";
+
+/// A database that lives entirely in memory.
+pub fn database() -> FullDatabase {
+    Data::new(Arc::new(Database::new(
+        Box::new(InMemoryDataProvider::new()),
+        Box::new(InMemoryDataProvider::new()),
+    )))
+}
+
+/// The configuration of the service when it runs on in-memory storage.
+pub fn config() -> Config {
+    let mut config = Config::default();
+    config.storage.persistent = PersistentStorageKind::InMemory;
+    config.storage.temporary = TemporaryStorageKind::InMemory;
+    // Keep the tests fast, the parameters do not matter for the behaviour.
+    config.hashing.memory_cost = 1024;
+    config.hashing.time_cost = 1;
+    config.mail.kind = MailerKind::Outbox;
+    config
+}
+
+/// Initialize the same application as the server runs, with the test configuration.
+pub async fn init(
+    database: &FullDatabase,
+) -> impl Service<
+    actix_http::Request,
+    Response = ServiceResponse<impl MessageBody>,
+    Error = actix_web::Error,
+> {
+    init_with(database, config()).await
+}
+
+/// Initialize the same application as the server runs.
+pub async fn init_with(
+    database: &FullDatabase,
+    config: Config,
+) -> impl Service<
+    actix_http::Request,
+    Response = ServiceResponse<impl MessageBody>,
+    Error = actix_web::Error,
+> {
+    let scorer: RiskScorer = Arc::new(WeightedRiskScorer::new(&config.risk));
+    init_with_scorer(database, config, scorer).await
+}
+
+/// Initialize the application with another session risk scorer.
+pub async fn init_with_scorer(
+    database: &FullDatabase,
+    config: Config,
+    scorer: RiskScorer,
+) -> impl Service<
+    actix_http::Request,
+    Response = ServiceResponse<impl MessageBody>,
+    Error = actix_web::Error,
+> {
+    let mailer: MailTransport = Arc::new(OutboxMailer::new(None));
+    let notifier = Arc::new(MailLockoutNotifier::new(mailer.clone()));
+    init_with_parts(database, config, scorer, mailer, notifier).await
+}
+
+/// Initialize the application with a mailer that can be inspected.
+pub async fn init_with_mailer(
+    database: &FullDatabase,
+    config: Config,
+    mailer: MailTransport,
+) -> impl Service<
+    actix_http::Request,
+    Response = ServiceResponse<impl MessageBody>,
+    Error = actix_web::Error,
+> {
+    let scorer: RiskScorer = Arc::new(WeightedRiskScorer::new(&config.risk));
+    let notifier = Arc::new(MailLockoutNotifier::new(mailer.clone()));
+    init_with_parts(database, config, scorer, mailer, notifier).await
+}
+
+/// Initialize the application with another lockout notifier.
+pub async fn init_with_notifier(
+    database: &FullDatabase,
+    config: Config,
+    notifier: Arc<dyn LockoutNotifier>,
+) -> impl Service<
+    actix_http::Request,
+    Response = ServiceResponse<impl MessageBody>,
+    Error = actix_web::Error,
+> {
+    let scorer: RiskScorer = Arc::new(WeightedRiskScorer::new(&config.risk));
+    let mailer: MailTransport = Arc::new(OutboxMailer::new(None));
+    init_with_parts(database, config, scorer, mailer, notifier).await
+}
+
+async fn init_with_parts(
+    database: &FullDatabase,
+    config: Config,
+    scorer: RiskScorer,
+    mailer: MailTransport,
+    notifier: Arc<dyn LockoutNotifier>,
+) -> impl Service<
+    actix_http::Request,
+    Response = ServiceResponse<impl MessageBody>,
+    Error = actix_web::Error,
+> {
+    let hasher: FullHasher = Data::new(Arc::new(PasswordHasher::new(&config.hashing)));
+    let signer: FullSigner = Data::new(Arc::new(TokenSigner::new(&config.tokens)));
+    let mailer: FullMailer = Data::new(mailer);
+    let notifier: FullNotifier = Data::new(notifier);
+    let limiter = config.rate_limit.store.connect(database);
+    let config: FullConfig = Data::new(Arc::new(config));
+    test::init_service(create_app(
+        database.clone(),
+        config,
+        hasher,
+        scorer,
+        signer,
+        mailer,
+        notifier,
+        limiter,
+    ))
+    .await
+}
+
+/// Wait for the work that continues after a response, like sending mail.
+pub async fn settle() {
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(20)).await;
+}
+
+/// The code of the last verification link that was sent to an address.
+pub fn verification_code(outbox: &OutboxMailer, email: &str) -> String {
+    let mail = outbox
+        .last_to(email)
+        .expect("a verification email was sent");
+    let (_, code) = mail
+        .body
+        .split_once("/verify?code=")
+        .expect("the mail contains a verification link");
+    code.chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect()
+}
+
+/// A request coming from a known browser and address.
+pub fn browser(request: TestRequest, user_agent: &str, peer: &str) -> TestRequest {
+    request
+        .insert_header(("User-Agent", user_agent))
+        .peer_addr(peer.parse::<SocketAddr>().unwrap())
+}
+
+/// Attach a session to a request.
+pub fn with_session(request: TestRequest, token: &str) -> TestRequest {
+    request.insert_header(("Cookie", format!("{}={}", SESSION_KEY, token)))
+}
+
+/// Attach an API session to a request.
+pub fn with_bearer(request: TestRequest, token: &str) -> TestRequest {
+    request.insert_header(("Authorization", format!("Bearer {}", token)))
+}
+
+pub fn registration(username: &str) -> Value {
+    json!({
+        "username": username,
+        "email": format!("{}@xiler.net", username),
+        "password": PASSWORD,
+    })
+}
+
+/// Register a user from the home browser and return the registration response.
+pub async fn register<S, B>(app: &S, username: &str) -> Value
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let request = browser(TestRequest::post().uri("/register"), CHROME, HOME)
+        .set_json(registration(username))
+        .to_request();
+
+    test::call_and_read_body_json(app, request).await
+}
+
+/// Register a user and return their session token.
+pub async fn session<S, B>(app: &S, username: &str) -> String
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let response = register(app, username).await;
+    response["session"]["token"].as_str().unwrap().to_string()
+}
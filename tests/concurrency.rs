@@ -0,0 +1,85 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use actix_web::{
+    http::StatusCode,
+    rt::time::timeout,
+    test::{self, TestRequest},
+    web::Data,
+};
+use async_trait::async_trait;
+use tokio::sync::Barrier;
+
+use accounts_rest_api::{
+    errors::StorageResult,
+    traits::TemporaryStorageProvider,
+    util::{data::InMemoryDataProvider, Database},
+};
+use common::{browser, with_session, CHROME, HOME};
+
+/// Temporary storage that only answers session lookups once two of them are in flight.
+struct RendezvousStorage {
+    inner: InMemoryDataProvider,
+    barrier: Barrier,
+}
+
+#[async_trait]
+impl TemporaryStorageProvider for RendezvousStorage {
+    async fn get(&self, key: String) -> StorageResult<Option<String>> {
+        if key.starts_with("session:") {
+            self.barrier.wait().await;
+        }
+        self.inner.get(key).await
+    }
+
+    async fn set(&self, key: String, value: String) -> StorageResult<()> {
+        self.inner.set(key, value).await
+    }
+
+    async fn set_with_ttl(&self, key: String, value: String, ttl: usize) -> StorageResult<()> {
+        self.inner.set_with_ttl(key, value, ttl).await
+    }
+
+    async fn set_if_absent(&self, key: String, value: String, ttl: usize) -> StorageResult<bool> {
+        self.inner.set_if_absent(key, value, ttl).await
+    }
+
+    async fn delete(&self, key: String) -> StorageResult<()> {
+        self.inner.delete(key).await
+    }
+
+    async fn drop_all(&self, value: String) -> StorageResult<()> {
+        self.inner.drop_all(value).await
+    }
+}
+
+#[actix_web::test]
+async fn storage_calls_are_not_serialized() {
+    let db = Data::new(Arc::new(Database::new(
+        Box::new(InMemoryDataProvider::new()),
+        Box::new(RendezvousStorage {
+            inner: InMemoryDataProvider::new(),
+            barrier: Barrier::new(2),
+        }),
+    )));
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let me =
+        || with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+
+    // Both requests only finish when their session lookups run at the same time.
+    let (first, second) = timeout(
+        Duration::from_secs(5),
+        futures::future::join(
+            test::call_service(&app, me()),
+            test::call_service(&app, me()),
+        ),
+    )
+    .await
+    .expect("session lookups were serialized");
+
+    assert_eq!(first.status(), StatusCode::OK);
+    assert_eq!(second.status(), StatusCode::OK);
+}
@@ -0,0 +1,214 @@
+use accounts_rest_api::{
+    config::Config,
+    errors::ConfigError,
+    util::data::{PersistentStorageKind, TemporaryStorageKind},
+};
+
+fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars.iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn defaults_are_valid() {
+    let config = Config::from_sources("", vars(&[])).unwrap();
+
+    assert_eq!(config.server.bind, "127.0.0.1:8080");
+    assert_eq!(config.storage.persistent, PersistentStorageKind::Scylla);
+    assert_eq!(config.storage.temporary, TemporaryStorageKind::Firefly);
+    assert_eq!(config.session.key, "xiler-session");
+}
+
+#[test]
+fn file_values_are_used() {
+    let file = r#"
+        [storage]
+        persistent = "in_memory"
+        temporary = "memory"
+
+        [scylla]
+        uri = "scylla.internal:9042"
+
+        [session]
+        ttl = 3600
+    "#;
+    let config = Config::from_sources(file, vars(&[])).unwrap();
+
+    assert_eq!(config.storage.persistent, PersistentStorageKind::InMemory);
+    assert_eq!(config.storage.temporary, TemporaryStorageKind::InMemory);
+    assert_eq!(config.scylla.uri, "scylla.internal:9042");
+    assert_eq!(config.scylla.username, "cassandra");
+    assert_eq!(config.session.ttl, 3600);
+}
+
+#[test]
+fn environment_overrides_the_file() {
+    let file = r#"
+        [scylla]
+        password = "from-file"
+
+        [session]
+        ttl = 3600
+    "#;
+    let config = Config::from_sources(
+        file,
+        vars(&[
+            ("XILER_SCYLLA_PASSWORD", "from-env"),
+            ("XILER_SESSION_TTL", "60"),
+            ("XILER_SITE_BASE_URL", "http://localhost:3000"),
+            ("UNRELATED", "value"),
+        ]),
+    )
+    .unwrap();
+
+    assert_eq!(config.scylla.password, "from-env");
+    assert_eq!(config.session.ttl, 60);
+    assert_eq!(config.site.base_url, "http://localhost:3000");
+}
+
+#[test]
+fn environment_overrides_nested_tables() {
+    let file = r#"
+        [oauth.providers.github]
+        method = 1
+        client_id = "xiler"
+        client_secret = "from-file"
+        authorize_url = "https://github.com/login/oauth/authorize"
+        token_url = "https://github.com/login/oauth/access_token"
+        userinfo_url = "https://api.github.com/user"
+    "#;
+    let config = Config::from_sources(
+        file,
+        vars(&[
+            ("XILER_OAUTH_PROVIDERS_GITHUB_CLIENT_SECRET", "from-env"),
+            ("XILER_RATE_LIMIT_LOGIN_REQUESTS", "5"),
+            ("XILER_TOKENS_SIGNING_KEY", "k1"),
+            (
+                "XILER_TOKENS_KEYS_K1",
+                "a secret that is long enough to sign",
+            ),
+            ("XILER_PASSWORD_RESET_TTL", "60"),
+        ]),
+    )
+    .unwrap();
+
+    assert_eq!(config.oauth.providers["github"].client_secret, "from-env");
+    assert_eq!(config.rate_limit.login.requests, 5);
+    assert_eq!(config.tokens.signing_key.as_deref(), Some("k1"));
+    assert_eq!(
+        config.tokens.keys["k1"],
+        "a secret that is long enough to sign"
+    );
+    assert_eq!(config.password_reset.ttl, 60);
+
+    // Tables themselves can not be replaced.
+    let config = Config::from_sources("", vars(&[("XILER_RATE_LIMIT_LOGIN", "5")])).unwrap();
+    assert_eq!(
+        config.rate_limit.login.requests,
+        Config::default().rate_limit.login.requests
+    );
+}
+
+#[test]
+fn invalid_values_are_rejected() {
+    let cases = [
+        ("[session]\nttl = 0", vars(&[])),
+        ("[session]\nidle_timeout = 0", vars(&[])),
+        ("[site]\nbase_url = \"accounts.xiler.net\"", vars(&[])),
+        ("[server]\nbind = \"not an address\"", vars(&[])),
+        ("", vars(&[("XILER_SESSION_KEY", "xiler session")])),
+        ("[hashing]\nlanes = 0", vars(&[])),
+        ("", vars(&[("XILER_HASHING_PEPPER", "")])),
+        ("[session]\nsame_site = \"none\"\nsecure = false", vars(&[])),
+        ("[risk]\nstep_up_threshold = 0.5", vars(&[])),
+        ("", vars(&[("XILER_RISK_IP", "-1")])),
+        ("[tokens]\nsigning_key = \"k1\"", vars(&[])),
+        ("[tokens.keys]\nk1 = \"too short\"", vars(&[])),
+        ("[mail]\nfrom = \"not an address\"", vars(&[])),
+        ("", vars(&[("XILER_SMTP_USERNAME", "arthur")])),
+        ("[verification]\nttl = 0", vars(&[])),
+        ("", vars(&[("XILER_PASSWORD_RESET_TTL", "0")])),
+        ("[magic_link]\nttl = 0", vars(&[])),
+        ("[lockout]\naccount_threshold = 0", vars(&[])),
+        ("", vars(&[("XILER_LOCKOUT_DURATION", "0")])),
+        ("[lockout]\nbackoff_base = 120\nmax_backoff = 60", vars(&[])),
+        ("[rate_limit.login]\nper = 0", vars(&[])),
+        ("[session]\nmax_sessions = 0", vars(&[])),
+        ("[firefly]\nmax_buffer = 512", vars(&[])),
+        ("[mfa]\nissuer = \"Xiler: Accounts\"", vars(&[])),
+        ("[webauthn]\norigin = \"https://xiler.example\"", vars(&[])),
+        (
+            "",
+            vars(&[("XILER_WEBAUTHN_ORIGIN", "https://accounts.xiler.net/login")]),
+        ),
+        ("[oauth]\nstate_ttl = 0", vars(&[])),
+    ];
+
+    for (file, vars) in cases {
+        assert!(matches!(
+            Config::from_sources(file, vars),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+}
+
+fn provider(name: &str, method: &str, token_url: &str) -> String {
+    format!(
+        r#"
+        [oauth.providers.{}]
+        method = {}
+        client_id = "xiler-accounts"
+        authorize_url = "https://idp.example/authorize"
+        token_url = "{}"
+        userinfo_url = "https://idp.example/userinfo"
+        "#,
+        name, method, token_url
+    )
+}
+
+#[test]
+fn oauth_providers_are_checked() {
+    let token_url = "https://idp.example/token";
+    let config = Config::from_sources(&provider("google", "1", token_url), vars(&[])).unwrap();
+    let google = &config.oauth.providers["google"];
+    assert_eq!(google.scopes, ["openid", "email", "profile"]);
+    assert_eq!(google.subject_claim, "sub");
+
+    let cases = [
+        provider("google", "0", token_url),
+        provider("google", "3", token_url),
+        provider("google", "256", token_url),
+        provider("google", "1", "idp.example/token"),
+        provider("\"g oogle\"", "1", token_url),
+        provider("google", "1", token_url) + &provider("github", "1", token_url),
+        provider("google", "1", token_url).replace("client_id = \"xiler-accounts\"", ""),
+    ];
+
+    for file in cases {
+        assert!(matches!(
+            Config::from_sources(&file, vars(&[])),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+}
+
+#[test]
+fn malformed_values_are_rejected() {
+    assert!(matches!(
+        Config::from_sources("[session]\nttl = \"a day\"", vars(&[])),
+        Err(ConfigError::Parse(_))
+    ));
+    assert!(matches!(
+        Config::from_sources("[storage]\npersistent = \"postgres\"", vars(&[])),
+        Err(ConfigError::Parse(_))
+    ));
+    assert!(matches!(
+        Config::from_sources("[rate_limit.register]\nkey = \"email\"", vars(&[])),
+        Err(ConfigError::Parse(_))
+    ));
+    assert!(matches!(
+        Config::from_sources("", vars(&[("XILER_SESSION_TTL", "a day")])),
+        Err(ConfigError::Invalid(_))
+    ));
+}
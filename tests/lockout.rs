@@ -0,0 +1,248 @@
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use actix_web::{
+    body::MessageBody,
+    dev::ServiceResponse,
+    http::{header, StatusCode},
+    rt::time::sleep,
+    test::{self, TestRequest},
+};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use accounts_rest_api::{
+    config::Config, structs::user::FullUser, traits::LockoutNotifier, util::mail::OutboxMailer,
+};
+use common::{browser, CHROME, ELSEWHERE, HOME, PASSWORD};
+
+const EMAIL: &str = "arthur@xiler.net";
+const LOCKED: &str = "Your account has been locked";
+
+fn login(username: &str, password: &str, peer: &str) -> actix_http::Request {
+    browser(TestRequest::post().uri("/login"), CHROME, peer)
+        .set_json(json!({ "username": username, "password": password }))
+        .to_request()
+}
+
+/// Lock after `account` failures on an account or `ip` failures from an address,
+/// without waiting in between.
+fn lock_after(account: usize, ip: usize) -> Config {
+    let mut config = common::config();
+    config.lockout.backoff_after = 100;
+    config.lockout.account_threshold = account;
+    config.lockout.ip_threshold = ip;
+    config.lockout.duration = 60;
+    config
+}
+
+/// Remembers every account that got locked.
+#[derive(Default)]
+struct RecordingNotifier {
+    locked: Mutex<Vec<(String, String, usize)>>,
+}
+
+#[async_trait]
+impl LockoutNotifier for RecordingNotifier {
+    async fn account_locked(&self, user: &FullUser, ip: &str, duration: usize) {
+        self.locked
+            .lock()
+            .unwrap()
+            .push((user.username.clone(), ip.to_string(), duration));
+    }
+}
+
+/// The amount of seconds the client was told to wait.
+fn retry_after(response: &ServiceResponse<impl MessageBody>) -> u64 {
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    response
+        .headers()
+        .get(header::RETRY_AFTER)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn failures_slow_down_attempts() {
+    let mut config = common::config();
+    config.lockout.backoff_after = 1;
+    config.lockout.backoff_base = 2;
+    config.lockout.max_backoff = 4;
+
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+    common::register(&app, "arthur").await;
+
+    let response = test::call_service(&app, login("arthur", "wrong password", HOME)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Even the right password has to wait.
+    let response = test::call_service(&app, login("arthur", PASSWORD, HOME)).await;
+    assert!((1..=2).contains(&retry_after(&response)));
+
+    // The wait doubles with every failure.
+    sleep(Duration::from_millis(2100)).await;
+    let response = test::call_service(&app, login("arthur", "wrong password", HOME)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, login("arthur", PASSWORD, HOME)).await;
+    assert!((3..=4).contains(&retry_after(&response)));
+
+    sleep(Duration::from_millis(4100)).await;
+    let response = test::call_service(&app, login("arthur", PASSWORD, HOME)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Signing in starts the count of the account over.
+    let response = test::call_service(&app, login("arthur", "wrong password", ELSEWHERE)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, login("arthur", PASSWORD, ELSEWHERE)).await;
+    assert!((1..=2).contains(&retry_after(&response)));
+}
+
+#[actix_web::test]
+async fn accounts_get_locked_and_their_owner_is_told() {
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, lock_after(3, 100), outbox.clone()).await;
+    common::register(&app, "arthur").await;
+
+    for _ in 0..3 {
+        let response = test::call_service(&app, login(EMAIL, "wrong password", HOME)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let mail = outbox.last_to(EMAIL).unwrap();
+    assert_eq!(mail.subject, LOCKED);
+    assert!(mail.body.contains("192.168.1.10"));
+
+    // The lock is on the account, not on the address or the name that was used.
+    for peer in [HOME, ELSEWHERE] {
+        let response = test::call_service(&app, login("arthur", PASSWORD, peer)).await;
+        assert!((50..=60).contains(&retry_after(&response)));
+    }
+
+    // Resetting the password lifts the lock.
+    let request = TestRequest::post()
+        .uri("/password/forgot")
+        .set_json(json!({ "username": "arthur" }))
+        .to_request();
+    test::call_service(&app, request).await;
+    common::settle().await;
+    let mail = outbox.last_to(EMAIL).unwrap();
+    let (_, code) = mail.body.split_once("/password/reset?code=").unwrap();
+    let code: String = code
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect();
+
+    let request = TestRequest::post()
+        .uri("/password/reset")
+        .set_json(json!({ "code": code, "password": "a brand new password" }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response =
+        test::call_service(&app, login("arthur", "a brand new password", ELSEWHERE)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn addresses_get_locked() {
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, lock_after(100, 3), outbox.clone()).await;
+    common::register(&app, "arthur").await;
+    let sent = outbox.mails().len();
+
+    // Guessing a different account every time does not help.
+    for username in ["ford", "zaphod", "trillian"] {
+        let response = test::call_service(&app, login(username, PASSWORD, HOME)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let response = test::call_service(&app, login("arthur", PASSWORD, HOME)).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let response = test::call_service(&app, login("arthur", PASSWORD, ELSEWHERE)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(outbox.mails().len(), sent);
+}
+
+#[actix_web::test]
+async fn unknown_accounts_are_locked_like_real_ones() {
+    let db = common::database();
+    let notifier = Arc::new(RecordingNotifier::default());
+    let app = common::init_with_notifier(&db, lock_after(2, 100), notifier.clone()).await;
+    common::register(&app, "arthur").await;
+
+    for username in ["arthur", "zaphod"] {
+        for _ in 0..2 {
+            let response = test::call_service(&app, login(username, "wrong password", HOME)).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        let response = test::call_service(&app, login(username, PASSWORD, HOME)).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    assert_eq!(
+        *notifier.locked.lock().unwrap(),
+        [("arthur".to_string(), "192.168.1.10".to_string(), 60)]
+    );
+}
+
+#[actix_web::test]
+async fn parallel_attempts_are_checked_one_at_a_time() {
+    let db = common::database();
+    let app = common::init_with(&db, lock_after(3, 100)).await;
+    common::register(&app, "arthur").await;
+
+    // Every attempt would get past the lockout before the first one was counted.
+    let responses = futures::future::join_all(
+        (0..6).map(|_| test::call_service(&app, login("arthur", "wrong", HOME))),
+    )
+    .await;
+    let statuses: Vec<StatusCode> = responses.iter().map(|response| response.status()).collect();
+    assert_eq!(
+        statuses
+            .iter()
+            .filter(|status| **status == StatusCode::UNAUTHORIZED)
+            .count(),
+        1
+    );
+    assert!(statuses[1..]
+        .iter()
+        .all(|status| *status == StatusCode::TOO_MANY_REQUESTS));
+    let body: Value = test::read_body_json(responses.into_iter().last().unwrap()).await;
+    assert_eq!(
+        body["message"],
+        "Another sign in attempt is in progress, try again in a moment."
+    );
+
+    // Once the attempt was counted the next one can be made.
+    let response = test::call_service(&app, login("arthur", PASSWORD, HOME)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn accounts_behind_one_address_sign_in_at_the_same_time() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    common::register(&app, "arthur").await;
+    common::register(&app, "ford").await;
+
+    let (arthur, ford) = futures::future::join(
+        test::call_service(&app, login("arthur", PASSWORD, HOME)),
+        test::call_service(&app, login("ford", PASSWORD, HOME)),
+    )
+    .await;
+    assert_eq!(arthur.status(), StatusCode::OK);
+    assert_eq!(ford.status(), StatusCode::OK);
+}
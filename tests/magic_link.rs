@@ -0,0 +1,218 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use actix_web::{
+    http::{header, StatusCode},
+    rt::time::sleep,
+    test::{self, TestRequest},
+};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use accounts_rest_api::{config::Config, constants::TOTP_AUTHENTICATION, util::mail::OutboxMailer};
+use common::{browser, with_session, CHROME, ELSEWHERE, FIREFOX, HOME};
+
+const EMAIL: &str = "arthur@xiler.net";
+
+/// The code of the last sign in link that was sent to an address.
+fn sign_in_code(outbox: &OutboxMailer) -> String {
+    let mail = outbox.last_to(EMAIL).expect("a sign in email was sent");
+    assert_eq!(mail.subject, "Sign in to Xiler");
+    let (_, code) = mail.body.split_once("/login/magic?code=").unwrap();
+    code.chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect()
+}
+
+fn request_link(username: &str) -> actix_http::Request {
+    browser(TestRequest::post().uri("/login/magic"), FIREFOX, ELSEWHERE)
+        .set_json(json!({ "username": username }))
+        .to_request()
+}
+
+fn open_link(code: &str) -> actix_http::Request {
+    browser(
+        TestRequest::get().uri(&format!("/login/magic?code={}", code)),
+        CHROME,
+        HOME,
+    )
+    .to_request()
+}
+
+fn confirm(code: &str) -> actix_http::Request {
+    browser(
+        TestRequest::post().uri("/login/magic/confirm"),
+        CHROME,
+        HOME,
+    )
+    .set_json(json!({ "code": code }))
+    .to_request()
+}
+
+fn enabled() -> Config {
+    let mut config = common::config();
+    config.magic_link.enabled = true;
+    config.magic_link.resend_interval = 0;
+    config
+}
+
+#[actix_web::test]
+async fn disabled_by_default() {
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, common::config(), outbox.clone()).await;
+    common::register(&app, "arthur").await;
+    let sent = outbox.mails().len();
+
+    let response = test::call_service(&app, request_link("arthur")).await;
+    common::settle().await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(outbox.mails().len(), sent);
+
+    let response = test::call_service(&app, open_link("anything")).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = test::call_service(&app, confirm("anything")).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn request_does_not_reveal_accounts() {
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, enabled(), outbox.clone()).await;
+    common::register(&app, "arthur").await;
+    let sent = outbox.mails().len();
+
+    let unknown = test::call_service(&app, request_link("zaphod")).await;
+    common::settle().await;
+    assert_eq!(unknown.status(), StatusCode::ACCEPTED);
+    let unknown = test::read_body(unknown).await;
+    assert_eq!(outbox.mails().len(), sent);
+
+    for username in ["arthur", EMAIL] {
+        let known = test::call_service(&app, request_link(username)).await;
+        common::settle().await;
+        assert_eq!(known.status(), StatusCode::ACCEPTED);
+        assert_eq!(test::read_body(known).await, unknown);
+    }
+    assert_eq!(outbox.mails().len(), sent + 2);
+    assert!(outbox
+        .last_to(EMAIL)
+        .unwrap()
+        .body
+        .contains("https://accounts.xiler.net/login/magic?code="));
+}
+
+#[actix_web::test]
+async fn link_signs_in_the_browser_that_confirms_it() {
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, enabled(), outbox.clone()).await;
+    common::register(&app, "arthur").await;
+
+    // Asked for from another device, opened on this one.
+    test::call_service(&app, request_link("arthur")).await;
+    common::settle().await;
+    let code = sign_in_code(&outbox);
+
+    // Opening the link does not use it up, mail scanners open links too.
+    for _ in 0..2 {
+        let response = test::call_service(&app, open_link(&code)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(header::SET_COOKIE).is_none());
+    }
+
+    let response = test::call_service(&app, confirm(&code)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(header::SET_COOKIE).is_some());
+    let session: Value = test::read_body_json(response).await;
+    let token = session["token"].as_str().unwrap();
+    assert!(token.starts_with("s2."));
+
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), token).to_request();
+    let user: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(user["username"], "arthur");
+
+    // The session belongs to the browser that opened the link.
+    let request = with_session(
+        browser(TestRequest::get().uri("/me"), FIREFOX, ELSEWHERE),
+        token,
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::GONE);
+
+    // The link can only be used once.
+    let response = test::call_service(&app, confirm(&code)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, open_link(&code)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn only_the_latest_link_works() {
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, enabled(), outbox.clone()).await;
+    common::register(&app, "arthur").await;
+
+    test::call_service(&app, request_link("arthur")).await;
+    common::settle().await;
+    let first = sign_in_code(&outbox);
+    test::call_service(&app, request_link("arthur")).await;
+    common::settle().await;
+    let second = sign_in_code(&outbox);
+
+    let response = test::call_service(&app, confirm(&first)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, confirm(&second)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn links_expire() {
+    let mut config = enabled();
+    config.magic_link.ttl = 1;
+
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, config, outbox.clone()).await;
+    common::register(&app, "arthur").await;
+
+    test::call_service(&app, request_link("arthur")).await;
+    common::settle().await;
+    let code = sign_in_code(&outbox);
+    sleep(Duration::from_millis(2100)).await;
+
+    let response = test::call_service(&app, confirm(&code)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn second_factor_is_still_required() {
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, enabled(), outbox.clone()).await;
+    let registration = common::register(&app, "arthur").await;
+    let request = with_session(
+        browser(TestRequest::get().uri("/me"), CHROME, HOME),
+        registration["session"]["token"].as_str().unwrap(),
+    )
+    .to_request();
+    let user: Value = test::call_and_read_body_json(&app, request).await;
+    let user_id = Uuid::parse_str(user["id"].as_str().unwrap()).unwrap();
+    db.persistent
+        .update_authentication_method_value(user_id, TOTP_AUTHENTICATION, "{}")
+        .await
+        .unwrap();
+
+    test::call_service(&app, request_link("arthur")).await;
+    common::settle().await;
+    let response = test::call_service(&app, confirm(&sign_in_code(&outbox))).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert!(response.headers().get(header::SET_COOKIE).is_none());
+    let challenge: Value = test::read_body_json(response).await;
+    assert_eq!(challenge["methods"], json!(["totp"]));
+}
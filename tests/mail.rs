@@ -0,0 +1,27 @@
+use std::fs;
+
+use accounts_rest_api::{structs::mail::Mail, traits::Mailer, util::mail::OutboxMailer};
+
+#[actix_web::test]
+async fn outbox_writes_mail_to_its_directory() {
+    let directory = std::env::temp_dir().join(format!("outbox-{}", uuid::Uuid::new_v4()));
+    let outbox = OutboxMailer::new(Some(directory.clone()));
+    let mail = Mail {
+        to: "arthur@xiler.net".to_string(),
+        subject: "Hello".to_string(),
+        body: "Don't panic.".to_string(),
+    };
+
+    outbox.send(mail.clone()).await.unwrap();
+    assert_eq!(outbox.mails(), vec![mail]);
+
+    let files: Vec<_> = fs::read_dir(&directory).unwrap().collect();
+    assert_eq!(files.len(), 1);
+    let contents = fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+    assert_eq!(
+        contents,
+        "To: arthur@xiler.net\nSubject: Hello\n\nDon't panic.\n"
+    );
+
+    fs::remove_dir_all(directory).unwrap();
+}
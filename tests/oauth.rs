@@ -0,0 +1,462 @@
+mod common;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceResponse},
+    http::{header, StatusCode},
+    test::{call_and_read_body_json, call_service, read_body_json, TestRequest},
+    web::{self, Data, Form},
+    App, HttpRequest, HttpResponse, HttpServer,
+};
+use data_encoding::BASE64URL_NOPAD;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use accounts_rest_api::{
+    config::{Config, OauthProviderConfig},
+    constants::TOTP_AUTHENTICATION,
+    util::{mail::OutboxMailer, random::random_string, totp::TotpMethod},
+};
+use common::{browser, with_session, CHROME, HOME};
+
+const CLIENT_ID: &str = "xiler-accounts";
+const CLIENT_SECRET: &str = "a secret the provider gave us";
+const STATE_COOKIE: &str = "xiler-session-oauth";
+
+/// An identity provider that hands out codes for whoever the test approves.
+#[derive(Default)]
+struct IdentityProvider {
+    /// The PKCE challenge and the claims of every code.
+    codes: Mutex<HashMap<String, (String, Value)>>,
+    tokens: Mutex<HashMap<String, Value>>,
+}
+
+impl IdentityProvider {
+    /// Let the user sign in, like the authorization page of the provider. Returns
+    /// the query the provider sends the user back with.
+    fn approve(&self, location: &str, claims: Value) -> String {
+        let (_, query) = location.split_once('?').unwrap();
+        let query: HashMap<String, String> = serde_urlencoded::from_str(query).unwrap();
+        assert_eq!(query["client_id"], CLIENT_ID);
+        assert_eq!(query["code_challenge_method"], "S256");
+
+        let code = random_string(16);
+        self.codes
+            .lock()
+            .unwrap()
+            .insert(code.clone(), (query["code_challenge"].clone(), claims));
+
+        serde_urlencoded::to_string([("state", &query["state"]), ("code", &code)]).unwrap()
+    }
+}
+
+async fn token(
+    provider: Data<IdentityProvider>,
+    form: Form<HashMap<String, String>>,
+) -> HttpResponse {
+    let grant = form
+        .get("code")
+        .and_then(|code| provider.codes.lock().unwrap().remove(code));
+
+    match grant {
+        Some((challenge, claims))
+            if form["grant_type"] == "authorization_code"
+                && form["client_id"] == CLIENT_ID
+                && form["client_secret"] == CLIENT_SECRET
+                && form["redirect_uri"].starts_with("https://accounts.xiler.net/oauth/")
+                && BASE64URL_NOPAD.encode(&Sha256::digest(form["code_verifier"].as_bytes()))
+                    == challenge =>
+        {
+            let token = random_string(32);
+            provider
+                .tokens
+                .lock()
+                .unwrap()
+                .insert(token.clone(), claims);
+            HttpResponse::Ok().json(json!({ "access_token": token, "token_type": "Bearer" }))
+        }
+        _ => HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" })),
+    }
+}
+
+async fn userinfo(provider: Data<IdentityProvider>, req: HttpRequest) -> HttpResponse {
+    let claims = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|token| provider.tokens.lock().unwrap().get(token).cloned());
+
+    match claims {
+        Some(claims) => HttpResponse::Ok().json(claims),
+        None => HttpResponse::Unauthorized().finish(),
+    }
+}
+
+/// Start the identity provider on a random port, returns the configuration that
+/// uses it as `google` (OpenID Connect) and `github` (plain OAuth 2.0).
+async fn identity_provider() -> (Data<IdentityProvider>, Config) {
+    let provider = Data::new(IdentityProvider::default());
+    let data = provider.clone();
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .route("/token", web::post().to(token))
+            .route("/userinfo", web::get().to(userinfo))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let base = format!("http://{}", server.addrs()[0]);
+    actix_web::rt::spawn(server.run());
+
+    let platform = |method| OauthProviderConfig {
+        method,
+        client_id: CLIENT_ID.to_string(),
+        client_secret: CLIENT_SECRET.to_string(),
+        authorize_url: format!("{}/authorize", base),
+        token_url: format!("{}/token", base),
+        userinfo_url: format!("{}/userinfo", base),
+        ..Default::default()
+    };
+
+    let mut config = common::config();
+    config
+        .oauth
+        .providers
+        .insert("google".to_string(), platform(1));
+    config.oauth.providers.insert(
+        "github".to_string(),
+        OauthProviderConfig {
+            scopes: vec!["read:user".to_string(), "user:email".to_string()],
+            subject_claim: "id".to_string(),
+            username_claim: "login".to_string(),
+            trust_email: true,
+            ..platform(2)
+        },
+    );
+
+    (provider, config)
+}
+
+/// Start a sign in, returns where the user is sent and the state cookie.
+async fn start<S, B>(app: &S, request: TestRequest) -> (String, String)
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let response = call_service(app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+
+    let location = response
+        .headers()
+        .get(header::LOCATION)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let cookie = response
+        .response()
+        .cookies()
+        .find(|cookie| cookie.name() == STATE_COOKIE)
+        .unwrap()
+        .value()
+        .to_string();
+
+    (location, cookie)
+}
+
+fn callback(provider: &str, query: &str, cookie: &str) -> actix_http::Request {
+    browser(
+        TestRequest::get().uri(&format!("/oauth/{}/callback?{}", provider, query)),
+        CHROME,
+        HOME,
+    )
+    .insert_header(("Cookie", format!("{}={}", STATE_COOKIE, cookie)))
+    .to_request()
+}
+
+/// Sign in with a provider as the user with these claims.
+async fn sign_in<S, B>(
+    app: &S,
+    identity_provider: &IdentityProvider,
+    provider: &str,
+    claims: Value,
+) -> ServiceResponse<B>
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let uri = format!("/oauth/{}/start", provider);
+    let (location, cookie) = start(app, TestRequest::get().uri(&uri)).await;
+    let query = identity_provider.approve(&location, claims);
+    call_service(app, callback(provider, &query, &cookie)).await
+}
+
+async fn me<S, B>(app: &S, token: &str) -> Value
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), token).to_request();
+    call_and_read_body_json(app, request).await
+}
+
+#[actix_web::test]
+async fn sign_up_and_sign_in_with_a_provider() {
+    let (identity_provider, config) = identity_provider().await;
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+
+    let (location, _) = start(&app, TestRequest::get().uri("/oauth/google/start")).await;
+    let (authorize, query) = location.split_once('?').unwrap();
+    assert!(authorize.ends_with("/authorize"));
+    let query: HashMap<String, String> = serde_urlencoded::from_str(query).unwrap();
+    assert_eq!(
+        query["redirect_uri"],
+        "https://accounts.xiler.net/oauth/google/callback"
+    );
+    assert_eq!(query["scope"], "openid email profile");
+    assert_eq!(query["response_type"], "code");
+
+    let claims = json!({
+        "sub": "g-42",
+        "email": "marvin@xiler.net",
+        "email_verified": true,
+        "preferred_username": "marvin",
+    });
+    let response = sign_in(&app, &identity_provider, "google", claims.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(header::SET_COOKIE).is_some());
+    let session: Value = read_body_json(response).await;
+
+    let user = me(&app, session["token"].as_str().unwrap()).await;
+    assert_eq!(user["username"], "marvin");
+    assert_eq!(user["email"], "marvin@xiler.net");
+    assert_eq!(user["verified"], true);
+    assert_eq!(user["authentication"], 1);
+
+    // The second time the same account is used.
+    let response = sign_in(&app, &identity_provider, "google", claims).await;
+    let session: Value = read_body_json(response).await;
+    assert_eq!(
+        me(&app, session["token"].as_str().unwrap()).await["id"],
+        user["id"]
+    );
+
+    // API clients can ask for a bearer token.
+    let (location, cookie) = start(
+        &app,
+        TestRequest::get().uri("/oauth/google/start?session=api"),
+    )
+    .await;
+    let query = identity_provider.approve(&location, json!({ "sub": "g-42" }));
+    let session: Value = call_and_read_body_json(&app, callback("google", &query, &cookie)).await;
+    assert!(session["token"].as_str().unwrap().starts_with("a1."));
+}
+
+#[actix_web::test]
+async fn callbacks_are_checked() {
+    let (identity_provider, config) = identity_provider().await;
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+    let claims = json!({ "sub": "g-42", "email": "marvin@xiler.net" });
+    let google = || TestRequest::get().uri("/oauth/google/start");
+
+    let request = TestRequest::get().uri("/oauth/myspace/start").to_request();
+    assert_eq!(
+        call_service(&app, request).await.status(),
+        StatusCode::NOT_FOUND
+    );
+
+    let response = call_service(&app, callback("google", "state=made-up&code=made-up", "")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Finished in another browser.
+    let (location, _) = start(&app, google()).await;
+    let query = identity_provider.approve(&location, claims.clone());
+    let (_, other_cookie) = start(&app, google()).await;
+    let response = call_service(&app, callback("google", &query, &other_cookie)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // The state can only be used once, and only with the provider it was made for.
+    let (location, cookie) = start(&app, google()).await;
+    let query = identity_provider.approve(&location, claims.clone());
+    let response = call_service(&app, callback("github", &query, &cookie)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = call_service(&app, callback("google", &query, &cookie)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // The user did not allow the sign in.
+    let (location, cookie) = start(&app, google()).await;
+    let approved = identity_provider.approve(&location, claims.clone());
+    let approved: HashMap<String, String> = serde_urlencoded::from_str(&approved).unwrap();
+    let denied = format!("state={}&error=access_denied", approved["state"]);
+    let response = call_service(&app, callback("google", &denied, &cookie)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // A code can not be used without the PKCE verifier of the sign in it was made for.
+    let (location, _) = start(&app, google()).await;
+    let stolen = identity_provider.approve(&location, claims.clone());
+    let stolen: HashMap<String, String> = serde_urlencoded::from_str(&stolen).unwrap();
+    let (location, cookie) = start(&app, google()).await;
+    let own = identity_provider.approve(&location, claims);
+    let own: HashMap<String, String> = serde_urlencoded::from_str(&own).unwrap();
+    let query = format!("state={}&code={}", own["state"], stolen["code"]);
+    let response = call_service(&app, callback("google", &query, &cookie)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn link_a_provider_to_an_account() {
+    let (identity_provider, config) = identity_provider().await;
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+    let arthur = common::session(&app, "arthur").await;
+    let ford = common::session(&app, "ford").await;
+    let claims = json!({ "id": 1234, "login": "arthur-dent", "email": "arthur@xiler.net" });
+    let link = |token: &str| {
+        with_session(
+            browser(TestRequest::get().uri("/oauth/github/link"), CHROME, HOME),
+            token,
+        )
+    };
+
+    // Linking goes through the provider, not through the generic endpoint.
+    let request = with_session(
+        browser(TestRequest::put().uri("/authentication/2"), CHROME, HOME),
+        &arthur,
+    )
+    .set_json(json!({ "value": "1234" }))
+    .to_request();
+    assert_eq!(
+        call_service(&app, request).await.status(),
+        StatusCode::BAD_REQUEST
+    );
+
+    let request = browser(TestRequest::get().uri("/oauth/github/link"), CHROME, HOME).to_request();
+    assert_eq!(
+        call_service(&app, request).await.status(),
+        StatusCode::BAD_REQUEST
+    );
+
+    let (location, cookie) = start(&app, link(&arthur)).await;
+    let query = identity_provider.approve(&location, claims.clone());
+    let response = call_service(&app, callback("github", &query, &cookie)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(header::SET_COOKIE).is_none());
+
+    let user = me(&app, &arthur).await;
+    assert_eq!(user["authentication"], 2);
+
+    // Arthur can now sign in with GitHub.
+    let response = sign_in(&app, &identity_provider, "github", claims.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let session: Value = read_body_json(response).await;
+    assert_eq!(
+        me(&app, session["token"].as_str().unwrap()).await["id"],
+        user["id"]
+    );
+
+    let request = link(&arthur).to_request();
+    assert_eq!(
+        call_service(&app, request).await.status(),
+        StatusCode::BAD_REQUEST
+    );
+
+    // The same GitHub account can not be linked to Ford as well.
+    let (location, cookie) = start(&app, link(&ford)).await;
+    let query = identity_provider.approve(&location, claims);
+    let response = call_service(&app, callback("github", &query, &cookie)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(me(&app, &ford).await["authentication"], 0);
+}
+
+#[actix_web::test]
+async fn new_users_get_an_account() {
+    let (identity_provider, config) = identity_provider().await;
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, config, outbox.clone()).await;
+    common::register(&app, "arthur").await;
+
+    // An existing account is not taken over by whoever has the same email address
+    // at the provider.
+    let claims = json!({ "sub": "g-1", "email": "arthur@xiler.net", "email_verified": true });
+    let response = sign_in(&app, &identity_provider, "google", claims).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = sign_in(&app, &identity_provider, "google", json!({ "sub": "g-2" })).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // An address the provider did not verify is not taken from its owner.
+    let claims = json!({
+        "sub": "g-3",
+        "email": "zaphod@xiler.net",
+        "preferred_username": "arthur",
+    });
+    let response = sign_in(&app, &identity_provider, "google", claims.clone()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(outbox.last_to("zaphod@xiler.net").is_none());
+
+    // The name is taken.
+    let mut claims = claims;
+    claims["email_verified"] = true.into();
+    let response = sign_in(&app, &identity_provider, "google", claims).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let session: Value = read_body_json(response).await;
+
+    let user = me(&app, session["token"].as_str().unwrap()).await;
+    assert!(user["username"].as_str().unwrap().starts_with("arthur-"));
+    assert_eq!(user["verified"], true);
+
+    // Without a name the address is used, GitHub addresses are trusted.
+    let claims = json!({ "id": 42, "email": "trillian@xiler.net" });
+    let response = sign_in(&app, &identity_provider, "github", claims).await;
+    let session: Value = read_body_json(response).await;
+
+    let user = me(&app, session["token"].as_str().unwrap()).await;
+    assert_eq!(user["username"], "trillian");
+    assert_eq!(user["verified"], true);
+    assert_eq!(user["authentication"], 2);
+    assert!(outbox.last_to("trillian@xiler.net").is_none());
+}
+
+#[actix_web::test]
+async fn second_factors_are_required() {
+    let (identity_provider, config) = identity_provider().await;
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+    let claims = json!({ "sub": "g-42", "email": "marvin@xiler.net", "email_verified": true });
+
+    let response = sign_in(&app, &identity_provider, "google", claims.clone()).await;
+    let session: Value = read_body_json(response).await;
+    let user = me(&app, session["token"].as_str().unwrap()).await;
+
+    let method = TotpMethod {
+        secret: "JBSWY3DPEHPK3PXP".to_string(),
+        recovery_codes: Vec::new(),
+    };
+    db.persistent
+        .update_authentication_method_value(
+            Uuid::parse_str(user["id"].as_str().unwrap()).unwrap(),
+            TOTP_AUTHENTICATION,
+            &method.to_value().unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let response = sign_in(&app, &identity_provider, "google", claims).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert!(response.headers().get(header::SET_COOKIE).is_none());
+    let challenge: Value = read_body_json(response).await;
+    assert_eq!(challenge["methods"], json!(["totp"]));
+    assert!(challenge.get("token").is_none());
+}
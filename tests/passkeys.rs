@@ -0,0 +1,455 @@
+mod common;
+
+use std::sync::Arc;
+
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    http::{header, StatusCode},
+    test::{call_and_read_body_json, call_service, read_body_json, TestRequest},
+};
+use ciborium::Value as Cbor;
+use data_encoding::BASE64URL_NOPAD;
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use rand::{thread_rng, RngCore};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use accounts_rest_api::util::mail::OutboxMailer;
+use common::{browser, with_session, CHROME, HOME, PASSWORD};
+
+const ORIGIN: &str = "https://accounts.xiler.net";
+
+fn b64(bytes: &[u8]) -> String {
+    BASE64URL_NOPAD.encode(bytes)
+}
+
+/// An authenticator that keeps a single passkey in memory.
+struct Authenticator {
+    key: SigningKey,
+    id: Vec<u8>,
+    sign_count: u32,
+    /// The authenticator counts its signatures.
+    counts: bool,
+    user_handle: String,
+}
+
+impl Authenticator {
+    fn new() -> Self {
+        let mut id = vec![0u8; 16];
+        thread_rng().fill_bytes(&mut id);
+        Self {
+            key: SigningKey::random(&mut thread_rng()),
+            id,
+            sign_count: 0,
+            counts: true,
+            user_handle: String::new(),
+        }
+    }
+
+    fn client_data(kind: &str, challenge: &str, origin: &str) -> Vec<u8> {
+        json!({ "type": kind, "challenge": challenge, "origin": origin, "crossOrigin": false })
+            .to_string()
+            .into_bytes()
+    }
+
+    fn authenticator_data(&mut self, rp_id: &str, flags: u8) -> Vec<u8> {
+        if self.counts {
+            self.sign_count += 1;
+        }
+
+        let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&self.sign_count.to_be_bytes());
+        data
+    }
+
+    fn cose_key(&self) -> Vec<u8> {
+        let point = self.key.verifying_key().to_encoded_point(false);
+        let key = Cbor::Map(vec![
+            (1.into(), 2.into()),
+            (3.into(), (-7).into()),
+            ((-1).into(), 1.into()),
+            ((-2).into(), Cbor::Bytes(point.x().unwrap().to_vec())),
+            ((-3).into(), Cbor::Bytes(point.y().unwrap().to_vec())),
+        ]);
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&key, &mut bytes).unwrap();
+        bytes
+    }
+
+    /// Create the passkey, like `navigator.credentials.create`.
+    fn create(&mut self, options: &Value, origin: &str) -> Value {
+        self.user_handle = options["user_id"].as_str().unwrap().to_string();
+
+        // User present, user verified and attested credential data.
+        let mut auth_data = self.authenticator_data(options["rp_id"].as_str().unwrap(), 0x45);
+        auth_data.extend_from_slice(&[0u8; 16]);
+        auth_data.extend_from_slice(&(self.id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&self.id);
+        auth_data.extend_from_slice(&self.cose_key());
+
+        let attestation = Cbor::Map(vec![
+            ("fmt".into(), "none".into()),
+            ("attStmt".into(), Cbor::Map(vec![])),
+            ("authData".into(), Cbor::Bytes(auth_data)),
+        ]);
+        let mut attestation_object = Vec::new();
+        ciborium::ser::into_writer(&attestation, &mut attestation_object).unwrap();
+
+        let challenge = options["challenge"].as_str().unwrap();
+        json!({
+            "id": b64(&self.id),
+            "client_data_json": b64(&Self::client_data("webauthn.create", challenge, origin)),
+            "attestation_object": b64(&attestation_object),
+            "name": "Laptop",
+        })
+    }
+
+    /// Sign a challenge, like `navigator.credentials.get`.
+    fn get(&mut self, options: &Value, origin: &str) -> Value {
+        let rp_id = options["rp_id"].as_str().unwrap();
+        self.sign(options["challenge"].as_str().unwrap(), rp_id, origin, 0x05)
+    }
+
+    fn sign(&mut self, challenge: &str, rp_id: &str, origin: &str, flags: u8) -> Value {
+        let client_data = Self::client_data("webauthn.get", challenge, origin);
+        let auth_data = self.authenticator_data(rp_id, flags);
+
+        let mut signed = auth_data.clone();
+        signed.extend_from_slice(&Sha256::digest(&client_data));
+        let signature: Signature = self.key.sign(&signed);
+
+        json!({
+            "id": b64(&self.id),
+            "client_data_json": b64(&client_data),
+            "authenticator_data": b64(&auth_data),
+            "signature": b64(signature.to_der().as_bytes()),
+            "user_handle": self.user_handle,
+        })
+    }
+}
+
+fn creation_options(token: &str) -> actix_http::Request {
+    with_session(
+        browser(TestRequest::post().uri("/me/passkeys"), CHROME, HOME),
+        token,
+    )
+    .set_json(json!({ "password": PASSWORD }))
+    .to_request()
+}
+
+async fn add_passkey<S, B>(app: &S, token: &str, authenticator: &mut Authenticator) -> StatusCode
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: actix_web::body::MessageBody,
+{
+    let options: Value = call_and_read_body_json(app, creation_options(token)).await;
+
+    let request = with_session(
+        browser(
+            TestRequest::post().uri("/me/passkeys/confirm"),
+            CHROME,
+            HOME,
+        ),
+        token,
+    )
+    .set_json(authenticator.create(&options, ORIGIN))
+    .to_request();
+    call_service(app, request).await.status()
+}
+
+async fn login_options<S, B>(app: &S) -> Value
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: actix_web::body::MessageBody,
+{
+    let request = TestRequest::post()
+        .uri("/login/passkey/challenge")
+        .to_request();
+    call_and_read_body_json(app, request).await
+}
+
+fn login(assertion: &Value) -> actix_http::Request {
+    browser(TestRequest::post().uri("/login/passkey"), CHROME, HOME)
+        .set_json(assertion)
+        .to_request()
+}
+
+#[actix_web::test]
+async fn sign_in_with_a_passkey() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+    let mut authenticator = Authenticator::new();
+
+    assert_eq!(
+        add_passkey(&app, &token, &mut authenticator).await,
+        StatusCode::OK
+    );
+
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+    let me: Value = call_and_read_body_json(&app, request).await;
+    assert_eq!(me["authentication"], 512);
+
+    // The passkey is listed when another one is added.
+    let options: Value = call_and_read_body_json(&app, creation_options(&token)).await;
+    assert_eq!(
+        options["exclude_credentials"],
+        json!([b64(&authenticator.id)])
+    );
+    assert_eq!(options["algorithms"], json!([-7]));
+
+    let options = login_options(&app).await;
+    assert_eq!(options["rp_id"], "accounts.xiler.net");
+    let response = call_service(&app, login(&authenticator.get(&options, ORIGIN))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(header::SET_COOKIE).is_some());
+    let session: Value = read_body_json(response).await;
+
+    let request = with_session(
+        browser(TestRequest::get().uri("/me"), CHROME, HOME),
+        session["token"].as_str().unwrap(),
+    )
+    .to_request();
+    let me: Value = call_and_read_body_json(&app, request).await;
+    assert_eq!(me["username"], "arthur");
+
+    // API clients get a bearer token, like with a password.
+    let mut assertion = authenticator.get(&login_options(&app).await, ORIGIN);
+    assertion["session"] = json!("api");
+    let session: Value = call_and_read_body_json(&app, login(&assertion)).await;
+    assert!(session["token"].as_str().unwrap().starts_with("a1."));
+}
+
+#[actix_web::test]
+async fn challenges_are_used_once() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+    let mut authenticator = Authenticator::new();
+    add_passkey(&app, &token, &mut authenticator).await;
+
+    let assertion = authenticator.get(&login_options(&app).await, ORIGIN);
+    let response = call_service(&app, login(&assertion)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = call_service(&app, login(&assertion)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Challenges to add a passkey can not be used to sign in.
+    let options: Value = call_and_read_body_json(&app, creation_options(&token)).await;
+    let response = call_service(&app, login(&authenticator.get(&options, ORIGIN))).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = call_service(
+        &app,
+        login(&authenticator.sign("made up", "accounts.xiler.net", ORIGIN, 0x05)),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn assertions_are_verified() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+    let mut authenticator = Authenticator::new();
+    add_passkey(&app, &token, &mut authenticator).await;
+
+    let challenge = |options: Value| options["challenge"].as_str().unwrap().to_string();
+
+    // Signed on another website, for another domain or without verifying the user.
+    let cases = [
+        ("https://xiler.example", "accounts.xiler.net", 0x05),
+        (ORIGIN, "xiler.example", 0x05),
+        (ORIGIN, "accounts.xiler.net", 0x01),
+    ];
+    for (origin, rp_id, flags) in cases {
+        let challenge = challenge(login_options(&app).await);
+        let assertion = authenticator.sign(&challenge, rp_id, origin, flags);
+        let response = call_service(&app, login(&assertion)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // Signed by another key.
+    let mut stolen = Authenticator::new();
+    stolen.id = authenticator.id.clone();
+    stolen.user_handle = authenticator.user_handle.clone();
+    let response = call_service(&app, login(&stolen.get(&login_options(&app).await, ORIGIN))).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // A passkey of another user.
+    let other = common::session(&app, "ford").await;
+    let mut other_authenticator = Authenticator::new();
+    add_passkey(&app, &other, &mut other_authenticator).await;
+    let mut assertion = authenticator.get(&login_options(&app).await, ORIGIN);
+    assertion["user_handle"] = json!(other_authenticator.user_handle);
+    let response = call_service(&app, login(&assertion)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = call_service(
+        &app,
+        login(&authenticator.get(&login_options(&app).await, ORIGIN)),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn cloned_passkeys_are_rejected() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+    let mut authenticator = Authenticator::new();
+    add_passkey(&app, &token, &mut authenticator).await;
+
+    let response = call_service(
+        &app,
+        login(&authenticator.get(&login_options(&app).await, ORIGIN)),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // A copy of the key that continues from an older count.
+    authenticator.sign_count -= 1;
+    let response = call_service(
+        &app,
+        login(&authenticator.get(&login_options(&app).await, ORIGIN)),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Authenticators that do not count their signatures always send 0.
+    let other = common::session(&app, "ford").await;
+    let mut uncounted = Authenticator::new();
+    uncounted.counts = false;
+    add_passkey(&app, &other, &mut uncounted).await;
+    for _ in 0..2 {
+        let response = call_service(
+            &app,
+            login(&uncounted.get(&login_options(&app).await, ORIGIN)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+#[actix_web::test]
+async fn registrations_are_verified() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let options = || creation_options(&token);
+    let confirm = |credential: &Value| {
+        with_session(
+            browser(
+                TestRequest::post().uri("/me/passkeys/confirm"),
+                CHROME,
+                HOME,
+            ),
+            &token,
+        )
+        .set_json(credential)
+        .to_request()
+    };
+
+    let mut authenticator = Authenticator::new();
+
+    // Created on another website.
+    let creation: Value = call_and_read_body_json(&app, options()).await;
+    let credential = authenticator.create(&creation, "https://xiler.example");
+    let response = call_service(&app, confirm(&credential)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // A challenge that was not issued to this user.
+    let creation: Value = call_and_read_body_json(&app, options()).await;
+    let mut forged = creation.clone();
+    forged["challenge"] = json!(b64(b"not a challenge"));
+    let response = call_service(&app, confirm(&authenticator.create(&forged, ORIGIN))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // The id does not match the passkey.
+    let mut credential = authenticator.create(&creation, ORIGIN);
+    credential["id"] = json!(b64(b"another id"));
+    let response = call_service(&app, confirm(&credential)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let creation: Value = call_and_read_body_json(&app, options()).await;
+    let response = call_service(&app, confirm(&authenticator.create(&creation, ORIGIN))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let creation: Value = call_and_read_body_json(&app, options()).await;
+    let response = call_service(&app, confirm(&authenticator.create(&creation, ORIGIN))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Passkeys can not be written through the generic endpoint.
+    let request = with_session(
+        browser(TestRequest::put().uri("/authentication/512"), CHROME, HOME),
+        &token,
+    )
+    .set_json(json!({ "value": "{\"passkeys\":[]}" }))
+    .to_request();
+    let response = call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn adding_a_passkey_requires_the_password() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let options = |proof: Value| {
+        with_session(
+            browser(TestRequest::post().uri("/me/passkeys"), CHROME, HOME),
+            &token,
+        )
+        .set_json(proof)
+        .to_request()
+    };
+
+    let response = call_service(&app, options(json!({}))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = call_service(&app, options(json!({ "password": "wrong password" }))).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = call_service(&app, options(json!({ "code": "not a code" }))).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = call_service(&app, options(json!({ "password": PASSWORD }))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn a_password_reset_removes_the_passkeys() {
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, common::config(), outbox.clone()).await;
+    let token = common::session(&app, "arthur").await;
+    let mut authenticator = Authenticator::new();
+    add_passkey(&app, &token, &mut authenticator).await;
+
+    let request = TestRequest::post()
+        .uri("/password/forgot")
+        .set_json(json!({ "username": "arthur" }))
+        .to_request();
+    call_service(&app, request).await;
+    common::settle().await;
+    let mail = outbox.last_to("arthur@xiler.net").unwrap();
+    let (_, code) = mail.body.split_once("/password/reset?code=").unwrap();
+    let code: String = code
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect();
+
+    let request = TestRequest::post()
+        .uri("/password/reset")
+        .set_json(json!({ "code": code, "password": "a brand new password" }))
+        .to_request();
+    let response = call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let assertion = authenticator.get(&login_options(&app).await, ORIGIN);
+    let response = call_service(&app, login(&assertion)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use actix_web::{http::StatusCode, test, test::TestRequest};
+use serde_json::{json, Value};
+
+use accounts_rest_api::util::hashing::sha256_hex;
+use common::{browser, with_session, CHROME, FIREFOX, HOME, PASSWORD};
+
+const NEW_PASSWORD: &str = "a brand new password";
+
+fn change(token: &str, body: Value) -> actix_http::Request {
+    with_session(
+        browser(TestRequest::post().uri("/me/password"), CHROME, HOME),
+        token,
+    )
+    .set_json(body)
+    .to_request()
+}
+
+fn login(user_agent: &str, password: &str) -> actix_http::Request {
+    browser(TestRequest::post().uri("/login"), user_agent, HOME)
+        .set_json(json!({ "username": "arthur", "password": password }))
+        .to_request()
+}
+
+fn me(user_agent: &str, token: &str) -> actix_http::Request {
+    with_session(
+        browser(TestRequest::get().uri("/me"), user_agent, HOME),
+        token,
+    )
+    .to_request()
+}
+
+#[actix_web::test]
+async fn change_requires_the_current_password() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let body = json!({ "current_password": "wrong password", "new_password": NEW_PASSWORD });
+    let response = test::call_service(&app, change(&token, body)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let body = json!({ "current_password": PASSWORD, "new_password": "short" });
+    let response = test::call_service(&app, change(&token, body)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let body = json!({ "current_password": PASSWORD, "new_password": NEW_PASSWORD });
+    let response = test::call_service(&app, change(&token, body)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = test::call_service(&app, login(CHROME, PASSWORD)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, login(CHROME, NEW_PASSWORD)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Other sessions stay, unless that is asked for.
+    let response = test::call_service(&app, me(CHROME, &token)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn change_can_sign_out_other_sessions() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let session: Value = test::call_and_read_body_json(&app, login(FIREFOX, PASSWORD)).await;
+    let other = session["token"].as_str().unwrap();
+
+    let body = json!({
+        "current_password": PASSWORD,
+        "new_password": NEW_PASSWORD,
+        "sign_out_others": true,
+    });
+    let response = test::call_service(&app, change(&token, body)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = test::call_service(&app, me(CHROME, &token)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = test::call_service(&app, me(FIREFOX, other)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn sessions_missing_from_the_index_are_signed_out() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let registration = common::register(&app, "arthur").await;
+    let user_id = registration["user"]["id"].as_str().unwrap();
+    let token = registration["session"]["token"].as_str().unwrap();
+
+    let session: Value = test::call_and_read_body_json(&app, login(FIREFOX, PASSWORD)).await;
+    let other = session["token"].as_str().unwrap();
+
+    // Concurrent logins can lose an id from the index.
+    let index = json!([sha256_hex(token)]).to_string();
+    db.temporary
+        .set(format!("sessions:{}", user_id), index)
+        .await
+        .unwrap();
+
+    let body = json!({
+        "current_password": PASSWORD,
+        "new_password": NEW_PASSWORD,
+        "sign_out_others": true,
+    });
+    let response = test::call_service(&app, change(token, body)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = test::call_service(&app, me(CHROME, token)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = test::call_service(&app, me(FIREFOX, other)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn the_current_password_can_not_be_guessed() {
+    let mut config = common::config();
+    config.lockout.backoff_after = 100;
+    config.lockout.account_threshold = 3;
+    config.lockout.duration = 60;
+
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+    let token = common::session(&app, "arthur").await;
+
+    for _ in 0..3 {
+        let body = json!({ "current_password": "wrong password", "new_password": NEW_PASSWORD });
+        let response = test::call_service(&app, change(&token, body)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // The account is locked for sign ins as well.
+    let body = json!({ "current_password": PASSWORD, "new_password": NEW_PASSWORD });
+    let response = test::call_service(&app, change(&token, body)).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let response = test::call_service(&app, login(CHROME, PASSWORD)).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
@@ -0,0 +1,155 @@
+mod common;
+
+use std::{collections::HashMap, str::FromStr};
+
+use actix_web::{http::StatusCode, test, test::TestRequest};
+use chrono::Duration;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use accounts_rest_api::{
+    config::Config, constants::PASSWORD_AUTHENTICATION, structs::user::FullUser,
+    types::FullDatabase,
+};
+use common::{browser, CHROME, HOME, PASSWORD};
+
+async fn stored_hash(db: &FullDatabase, id: Uuid) -> String {
+    db.persistent
+        .get_user_by_id(id)
+        .await
+        .unwrap()
+        .unwrap()
+        .authentication[&PASSWORD_AUTHENTICATION]
+        .clone()
+}
+
+async fn login_status<S, B>(app: &S, username: &str) -> StatusCode
+where
+    S: actix_web::dev::Service<
+        actix_http::Request,
+        Response = actix_web::dev::ServiceResponse<B>,
+        Error = actix_web::Error,
+    >,
+    B: actix_web::body::MessageBody,
+{
+    let request = browser(TestRequest::post().uri("/login"), CHROME, HOME)
+        .set_json(json!({ "username": username, "password": PASSWORD }))
+        .to_request();
+    test::call_service(app, request).await.status()
+}
+
+fn user_id(registration: &Value) -> Uuid {
+    Uuid::from_str(registration["user"]["id"].as_str().unwrap()).unwrap()
+}
+
+#[actix_web::test]
+async fn every_hash_has_its_own_salt() {
+    let db = common::database();
+    let app = common::init(&db).await;
+
+    let arthur = stored_hash(&db, user_id(&common::register(&app, "arthur").await)).await;
+    let ford = stored_hash(&db, user_id(&common::register(&app, "ford").await)).await;
+
+    assert!(arthur.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+    assert_ne!(arthur, ford);
+}
+
+#[actix_web::test]
+async fn legacy_hashes_are_upgraded_on_login() {
+    let db = common::database();
+    let app = common::init(&db).await;
+
+    let legacy_config = argon2::Config {
+        hash_length: 128,
+        mem_cost: 32,
+        variant: argon2::Variant::Argon2i,
+        ..argon2::Config::default()
+    };
+    let legacy = argon2::hash_encoded(
+        PASSWORD.as_bytes(),
+        b"ajsldAJKHDLAKJDjsna/AZ",
+        &legacy_config,
+    )
+    .unwrap();
+
+    let id = Uuid::new_v4();
+    db.persistent
+        .register_user(FullUser {
+            id,
+            username: "arthur".to_string(),
+            email: "arthur@xiler.net".to_string(),
+            created_at: Duration::seconds(0),
+            roles: 0,
+            authentication: HashMap::from([(PASSWORD_AUTHENTICATION, legacy.clone())]),
+            verification_token: None,
+            disabled: false,
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(login_status(&app, "arthur").await, StatusCode::OK);
+
+    let upgraded = stored_hash(&db, id).await;
+    assert!(upgraded.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+
+    // The upgraded hash is still valid for the same password.
+    assert_eq!(login_status(&app, "arthur").await, StatusCode::OK);
+    assert_eq!(stored_hash(&db, id).await, upgraded);
+}
+
+#[actix_web::test]
+async fn changed_parameters_trigger_a_rehash() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let id = user_id(&common::register(&app, "arthur").await);
+
+    let mut stronger = common::config();
+    stronger.hashing.time_cost = 2;
+    let app = common::init_with(&db, stronger).await;
+
+    assert_eq!(login_status(&app, "arthur").await, StatusCode::OK);
+    assert!(stored_hash(&db, id)
+        .await
+        .starts_with("$argon2id$v=19$m=1024,t=2,p=1$"));
+}
+
+#[actix_web::test]
+async fn pepper_is_required_to_verify() {
+    fn peppered() -> Config {
+        let mut config = common::config();
+        config.hashing.pepper = Some("don't panic".to_string());
+        config
+    }
+
+    let db = common::database();
+    let app = common::init_with(&db, peppered()).await;
+    common::register(&app, "arthur").await;
+    assert_eq!(login_status(&app, "arthur").await, StatusCode::OK);
+
+    let without_pepper = common::init(&db).await;
+    assert_eq!(
+        login_status(&without_pepper, "arthur").await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[actix_web::test]
+async fn adding_a_pepper_upgrades_existing_hashes() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let id = user_id(&common::register(&app, "arthur").await);
+    let unpeppered = stored_hash(&db, id).await;
+
+    let mut config = common::config();
+    config.hashing.pepper = Some("don't panic".to_string());
+    let app = common::init_with(&db, config).await;
+
+    assert_eq!(login_status(&app, "arthur").await, StatusCode::OK);
+    assert_ne!(stored_hash(&db, id).await, unpeppered);
+
+    let without_pepper = common::init(&db).await;
+    assert_eq!(
+        login_status(&without_pepper, "arthur").await,
+        StatusCode::UNAUTHORIZED
+    );
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use actix_web::{http::StatusCode, rt::time::sleep, test, test::TestRequest};
+use serde_json::json;
+
+use accounts_rest_api::util::mail::OutboxMailer;
+use common::{browser, with_session, CHROME, HOME, PASSWORD};
+
+const EMAIL: &str = "arthur@xiler.net";
+const NEW_PASSWORD: &str = "a brand new password";
+
+/// The code of the last reset link that was sent to an address.
+fn reset_code(outbox: &OutboxMailer) -> String {
+    let mail = outbox.last_to(EMAIL).expect("a reset email was sent");
+    assert_eq!(mail.subject, "Reset your password");
+    let (_, code) = mail.body.split_once("/password/reset?code=").unwrap();
+    code.chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect()
+}
+
+fn forgot(username: &str) -> actix_http::Request {
+    TestRequest::post()
+        .uri("/password/forgot")
+        .set_json(json!({ "username": username }))
+        .to_request()
+}
+
+fn reset(code: &str, password: &str) -> actix_http::Request {
+    TestRequest::post()
+        .uri("/password/reset")
+        .set_json(json!({ "code": code, "password": password }))
+        .to_request()
+}
+
+fn login(password: &str) -> actix_http::Request {
+    browser(TestRequest::post().uri("/login"), CHROME, HOME)
+        .set_json(json!({ "username": "arthur", "password": password }))
+        .to_request()
+}
+
+#[actix_web::test]
+async fn forgot_does_not_reveal_accounts() {
+    let mut config = common::config();
+    config.password_reset.resend_interval = 0;
+
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, config, outbox.clone()).await;
+    common::register(&app, "arthur").await;
+    let sent = outbox.mails().len();
+
+    let unknown = test::call_service(&app, forgot("zaphod")).await;
+    common::settle().await;
+    assert_eq!(unknown.status(), StatusCode::ACCEPTED);
+    let unknown = test::read_body(unknown).await;
+    assert_eq!(outbox.mails().len(), sent);
+
+    for username in ["arthur", EMAIL] {
+        let known = test::call_service(&app, forgot(username)).await;
+        common::settle().await;
+        assert_eq!(known.status(), StatusCode::ACCEPTED);
+        assert_eq!(test::read_body(known).await, unknown);
+    }
+    assert_eq!(outbox.mails().len(), sent + 2);
+    assert!(outbox
+        .last_to(EMAIL)
+        .unwrap()
+        .body
+        .contains("https://accounts.xiler.net/password/reset?code="));
+}
+
+#[actix_web::test]
+async fn reset_sets_the_password_and_signs_out() {
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, common::config(), outbox.clone()).await;
+    let token = common::session(&app, "arthur").await;
+
+    test::call_service(&app, forgot("arthur")).await;
+    common::settle().await;
+    let code = reset_code(&outbox);
+
+    // A password that breaks the rules does not use up the code.
+    let response = test::call_service(&app, reset(&code, "short")).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = test::call_service(&app, reset("wrong", NEW_PASSWORD)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = test::call_service(&app, reset(&code, NEW_PASSWORD)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = test::call_service(&app, reset(&code, NEW_PASSWORD)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = test::call_service(&app, login(PASSWORD)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, login(NEW_PASSWORD)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn links_expire_and_are_throttled() {
+    let mut config = common::config();
+    config.password_reset.ttl = 1;
+    config.password_reset.resend_interval = 1;
+
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, config, outbox.clone()).await;
+    common::register(&app, "arthur").await;
+
+    test::call_service(&app, forgot("arthur")).await;
+    common::settle().await;
+    let code = reset_code(&outbox);
+    let sent = outbox.mails().len();
+
+    // Asking again right away does not send another link.
+    let response = test::call_service(&app, forgot("arthur")).await;
+    common::settle().await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert_eq!(outbox.mails().len(), sent);
+
+    sleep(Duration::from_millis(2100)).await;
+
+    let response = test::call_service(&app, reset(&code, NEW_PASSWORD)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    test::call_service(&app, forgot("arthur")).await;
+    common::settle().await;
+    assert_eq!(outbox.mails().len(), sent + 1);
+    let response = test::call_service(&app, reset(&reset_code(&outbox), NEW_PASSWORD)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,237 @@
+mod common;
+
+use std::time::Duration;
+
+use actix_web::{
+    body::MessageBody,
+    dev::ServiceResponse,
+    http::{header, StatusCode},
+    rt::time::sleep,
+    test::{self, TestRequest},
+};
+use serde_json::json;
+
+use accounts_rest_api::{
+    config::{Config, RateLimit},
+    traits::RateLimitStore,
+    types::FullDatabase,
+    util::rate_limit::{
+        MemoryRateLimitStore, RateLimitKey, RateLimitStoreKind, MAX_MEMORY_BUCKETS,
+    },
+};
+use common::{browser, registration, with_session, CHROME, ELSEWHERE, HOME};
+
+fn limit(requests: u32, per: usize, key: RateLimitKey) -> RateLimit {
+    RateLimit { requests, per, key }
+}
+
+fn register(username: &str, peer: &str) -> actix_http::Request {
+    browser(TestRequest::post().uri("/register"), CHROME, peer)
+        .set_json(registration(username))
+        .to_request()
+}
+
+fn login(uri: &str) -> actix_http::Request {
+    browser(TestRequest::post().uri(uri), CHROME, HOME)
+        .set_json(json!({ "username": "arthur", "password": "wrong password" }))
+        .to_request()
+}
+
+fn header<'a>(response: &'a ServiceResponse<impl MessageBody>, name: &str) -> &'a str {
+    response.headers().get(name).unwrap().to_str().unwrap()
+}
+
+async fn registrations_are_limited(db: &FullDatabase, config: Config) {
+    let app = common::init_with(db, config).await;
+
+    let response = test::call_service(&app, register("arthur", HOME)).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(header(&response, "RateLimit-Limit"), "2");
+    assert_eq!(header(&response, "RateLimit-Remaining"), "1");
+    assert_eq!(header(&response, "RateLimit-Policy"), "2;w=3600");
+
+    let response = test::call_service(&app, register("ford", HOME)).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(header(&response, "RateLimit-Remaining"), "0");
+
+    let response = test::call_service(&app, register("zaphod", HOME)).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(header(&response, "RateLimit-Remaining"), "0");
+    let retry_after: u64 = header(&response, header::RETRY_AFTER.as_str())
+        .parse()
+        .unwrap();
+    assert!((1..=1800).contains(&retry_after));
+    let reset: u64 = header(&response, "RateLimit-Reset").parse().unwrap();
+    assert!((1..=3600).contains(&reset));
+
+    // Every address has its own limit.
+    let response = test::call_service(&app, register("zaphod", ELSEWHERE)).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+#[actix_web::test]
+async fn registrations_are_limited_per_address() {
+    let mut config = common::config();
+    config.rate_limit.register = limit(2, 3600, RateLimitKey::Ip);
+
+    registrations_are_limited(&common::database(), config).await;
+}
+
+#[actix_web::test]
+async fn limits_can_be_kept_in_temporary_storage() {
+    let mut config = common::config();
+    config.rate_limit.store = RateLimitStoreKind::Temporary;
+    config.rate_limit.register = limit(2, 3600, RateLimitKey::Ip);
+
+    let db = common::database();
+    registrations_are_limited(&db, config).await;
+
+    let bucket = db
+        .temporary
+        .get("rate-limit:register:ip:192.168.1.10".to_string())
+        .await
+        .unwrap();
+    assert!(bucket.is_some());
+}
+
+#[actix_web::test]
+async fn every_way_to_sign_in_shares_the_login_limit() {
+    let mut config = common::config();
+    config.rate_limit.login = limit(2, 60, RateLimitKey::Ip);
+
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+
+    let response = test::call_service(&app, login("/login")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, login("/login/passkey/challenge")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = test::call_service(&app, login("/login/mfa")).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // Other resources are not limited by it.
+    let response = test::call_service(&app, register("arthur", HOME)).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+#[actix_web::test]
+async fn password_resets_share_a_limit() {
+    let mut config = common::config();
+    config.rate_limit.password_reset = limit(2, 60, RateLimitKey::Ip);
+
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+    let request = |uri: &str| {
+        browser(TestRequest::post().uri(uri), CHROME, HOME)
+            .set_json(
+                json!({ "username": "arthur", "code": "guessed", "password": "a new password" }),
+            )
+            .to_request()
+    };
+
+    let response = test::call_service(&app, request("/password/forgot")).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let response = test::call_service(&app, request("/password/reset")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, request("/password/reset")).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let response = test::call_service(&app, request("/password/forgot")).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[actix_web::test]
+async fn buckets_fill_up_again() {
+    let mut config = common::config();
+    config.rate_limit.login = limit(1, 1, RateLimitKey::Ip);
+
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+
+    let response = test::call_service(&app, login("/login")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, login("/login")).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(header(&response, header::RETRY_AFTER.as_str()), "1");
+
+    sleep(Duration::from_millis(1100)).await;
+    let response = test::call_service(&app, login("/login")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn limits_can_be_counted_per_user() {
+    let mut config = common::config();
+    config.verification.resend_interval = 0;
+    config.rate_limit.verify = limit(1, 3600, RateLimitKey::User);
+
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+    let arthur = common::session(&app, "arthur").await;
+    let ford = common::session(&app, "ford").await;
+    let resend = |token: &str, peer: &str| {
+        with_session(
+            browser(TestRequest::post().uri("/verify/resend"), CHROME, peer),
+            token,
+        )
+        .to_request()
+    };
+
+    let response = test::call_service(&app, resend(&arthur, HOME)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Another network does not help, another user on the same network has their own.
+    let response = test::call_service(&app, resend(&arthur, ELSEWHERE)).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let response = test::call_service(&app, resend(&ford, HOME)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn limits_can_be_turned_off() {
+    let mut config = common::config();
+    config.rate_limit.register = limit(0, 3600, RateLimitKey::Ip);
+
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+
+    for username in ["arthur", "ford", "zaphod", "trillian", "marvin", "eddie"] {
+        let response = test::call_service(&app, register(username, HOME)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(response.headers().get("RateLimit-Limit").is_none());
+    }
+}
+
+#[actix_web::test]
+async fn memory_buckets_are_capped() {
+    let store = MemoryRateLimitStore::new();
+    let limit = limit(1, 60 * 60, RateLimitKey::Ip);
+
+    assert!(
+        store
+            .take("arthur".to_string(), &limit)
+            .await
+            .unwrap()
+            .allowed
+    );
+    assert!(
+        !store
+            .take("arthur".to_string(), &limit)
+            .await
+            .unwrap()
+            .allowed
+    );
+
+    // None of the buckets are full, so the least recently used ones make room.
+    for client in 0..MAX_MEMORY_BUCKETS {
+        let decision = store.take(client.to_string(), &limit).await.unwrap();
+        assert!(decision.allowed);
+    }
+
+    assert!(
+        store
+            .take("arthur".to_string(), &limit)
+            .await
+            .unwrap()
+            .allowed
+    );
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use std::sync::Arc;
+
+use actix_web::{
+    http::StatusCode,
+    test::{self, TestRequest},
+    web::{self, Data},
+    App, HttpResponse,
+};
+use serde_json::Value;
+use uuid::Uuid;
+
+use accounts_rest_api::{
+    middleware::{AuthenticationService, RequireRole},
+    structs::{
+        role::{Permission, Role, Roles},
+        user::FullUser,
+    },
+    types::{FullDatabase, RiskScorer},
+    util::{risk::WeightedRiskScorer, signing::TokenSigner},
+};
+use common::{registration, with_bearer};
+
+#[test]
+fn roles_are_decoded_from_the_bitmask() {
+    let roles = Roles::from_bits(0b101);
+    assert_eq!(
+        roles.iter().collect::<Vec<Role>>(),
+        [Role::Support, Role::Admin]
+    );
+    assert!(!roles.contains(Role::Moderator));
+    assert_eq!(
+        [Role::Support, Role::Admin].into_iter().collect::<Roles>(),
+        roles
+    );
+
+    // Unknown bits are kept, but do not grant anything.
+    let unknown = Roles::from_bits(0b1000);
+    assert_eq!(unknown.bits(), 0b1000);
+    assert_eq!(unknown.iter().count(), 0);
+    assert!(!unknown.allows(Permission::ViewUsers));
+}
+
+#[test]
+fn roles_grant_permissions() {
+    let support = Roles::from_bits(Role::Support.bit());
+    assert!(support.allows(Permission::ViewUsers));
+    assert!(!support.allows(Permission::DisableUsers));
+
+    let moderator = Roles::from_bits(Role::Moderator.bit());
+    assert!(moderator.allows(Permission::RevokeSessions));
+    assert!(!moderator.allows(Permission::ManageRoles));
+
+    let admin = Roles::from_bits(Role::Admin.bit());
+    assert!(admin.allows(Permission::DeleteUsers));
+    assert!(admin.allows(Permission::ManageRoles));
+}
+
+/// Register a user with an API session and give them roles, returns their token.
+async fn staff(db: &FullDatabase, username: &str, roles: &[Role]) -> String {
+    let app = common::init(db).await;
+    let mut body = registration(username);
+    body["session"] = "api".into();
+    let request = TestRequest::post()
+        .uri("/register")
+        .set_json(body)
+        .to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+
+    let id = Uuid::parse_str(response["user"]["id"].as_str().unwrap()).unwrap();
+    let roles: Roles = roles.iter().copied().collect();
+    db.persistent.set_roles(id, roles.bits()).await.unwrap();
+
+    response["session"]["token"].as_str().unwrap().to_string()
+}
+
+async fn whoami(user: FullUser) -> HttpResponse {
+    HttpResponse::Ok().body(user.username)
+}
+
+#[actix_web::test]
+async fn resources_can_require_roles_and_permissions() {
+    let db = common::database();
+    let config = Data::new(Arc::new(common::config()));
+    let scorer: RiskScorer = Arc::new(WeightedRiskScorer::new(&config.risk));
+    let signer = Data::new(Arc::new(TokenSigner::new(&config.tokens)));
+    let authenticated = {
+        let db = db.clone();
+        move || {
+            AuthenticationService::new(db.clone(), config.clone(), scorer.clone(), signer.clone())
+        }
+    };
+
+    let app = test::init_service(
+        App::new()
+            .service(
+                web::resource("/admin")
+                    .wrap(RequireRole::new(Role::Admin))
+                    .wrap(authenticated())
+                    .to(whoami),
+            )
+            .service(
+                web::resource("/users")
+                    .wrap(RequireRole::permission(Permission::ViewUsers))
+                    .wrap(authenticated())
+                    .to(whoami),
+            )
+            .service(
+                web::resource("/unguarded")
+                    .wrap(RequireRole::new(Role::Support))
+                    .to(whoami),
+            ),
+    )
+    .await;
+
+    let arthur = staff(&db, "arthur", &[]).await;
+    let ford = staff(&db, "ford", &[Role::Support]).await;
+    let zaphod = staff(&db, "zaphod", &[Role::Admin]).await;
+    let call =
+        |uri: &str, token: &str| with_bearer(TestRequest::get().uri(uri), token).to_request();
+
+    for (uri, token, status) in [
+        ("/admin", &arthur, StatusCode::FORBIDDEN),
+        ("/admin", &ford, StatusCode::FORBIDDEN),
+        ("/admin", &zaphod, StatusCode::OK),
+        ("/users", &arthur, StatusCode::FORBIDDEN),
+        ("/users", &ford, StatusCode::OK),
+        ("/users", &zaphod, StatusCode::OK),
+    ] {
+        let response = test::call_service(&app, call(uri, token)).await;
+        assert_eq!(response.status(), status, "{} as {}", uri, token);
+    }
+
+    let response: Value = test::call_and_read_body_json(&app, call("/admin", &arthur)).await;
+    assert_eq!(
+        response["message"],
+        "You do not have permission to do this."
+    );
+
+    // Without a session there is nobody to check.
+    let response = test::call_service(&app, TestRequest::get().uri("/admin").to_request()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Roles are never granted when the authentication was forgotten.
+    let response = test::call_service(&app, call("/unguarded", &ford)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
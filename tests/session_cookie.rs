@@ -0,0 +1,109 @@
+mod common;
+
+use actix_web::{
+    cookie::{Cookie, SameSite},
+    dev::ServiceResponse,
+    http::{header, StatusCode},
+    test,
+    test::TestRequest,
+};
+use serde_json::{json, Value};
+
+use accounts_rest_api::config::SameSitePolicy;
+use common::{browser, registration, with_session, CHROME, HOME, PASSWORD};
+
+/// The session cookie that was set by a response.
+fn session_cookie<B>(response: &ServiceResponse<B>) -> Cookie<'static> {
+    let header = response
+        .headers()
+        .get(header::SET_COOKIE)
+        .expect("the response sets a cookie");
+    Cookie::parse_encoded(header.to_str().unwrap().to_string()).unwrap()
+}
+
+#[actix_web::test]
+async fn register_and_login_set_the_session_cookie() {
+    let db = common::database();
+    let app = common::init(&db).await;
+
+    let request = browser(TestRequest::post().uri("/register"), CHROME, HOME)
+        .set_json(registration("arthur"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    let cookie = session_cookie(&response);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(cookie.value(), body["session"]["token"]);
+
+    let request = browser(TestRequest::post().uri("/login"), CHROME, HOME)
+        .set_json(json!({ "username": "arthur", "password": PASSWORD }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    let cookie = session_cookie(&response);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(cookie.value(), body["token"]);
+
+    assert_eq!(cookie.name(), "xiler-session");
+    assert_eq!(cookie.path(), Some("/"));
+    assert_eq!(cookie.http_only(), Some(true));
+    assert_eq!(cookie.secure(), Some(true));
+    assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+    assert_eq!(
+        cookie.max_age().unwrap().whole_seconds(),
+        common::config().session.ttl as i64
+    );
+    assert_eq!(cookie.domain(), None);
+
+    // The cookie alone is enough to use the session.
+    let request = browser(TestRequest::get().uri("/me"), CHROME, HOME)
+        .cookie(cookie)
+        .to_request();
+    let me: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(me["username"], "arthur");
+}
+
+#[actix_web::test]
+async fn cookie_follows_the_configuration() {
+    let mut config = common::config();
+    config.session.ttl = 3600;
+    config.session.cookie_domain = Some(".xiler.net".to_string());
+    config.session.same_site = SameSitePolicy::Strict;
+    config.session.secure = false;
+
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+
+    let request = browser(TestRequest::post().uri("/register"), CHROME, HOME)
+        .set_json(registration("arthur"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    let cookie = session_cookie(&response);
+
+    assert_eq!(cookie.domain(), Some("xiler.net"));
+    assert_eq!(cookie.same_site(), Some(SameSite::Strict));
+    assert_eq!(cookie.secure(), None);
+    assert_eq!(cookie.max_age().unwrap().whole_seconds(), 3600);
+}
+
+#[actix_web::test]
+async fn logout_and_delete_clear_the_cookie() {
+    let db = common::database();
+    let app = common::init(&db).await;
+
+    for (username, uri) in [("arthur", "/logout"), ("ford", "/me")] {
+        let token = common::session(&app, username).await;
+
+        let request = with_session(
+            browser(TestRequest::delete().uri(uri), CHROME, HOME),
+            &token,
+        )
+        .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let cookie = session_cookie(&response);
+        assert_eq!(cookie.name(), "xiler-session");
+        assert_eq!(cookie.value(), "");
+        assert_eq!(cookie.path(), Some("/"));
+        assert_eq!(cookie.max_age().unwrap().whole_seconds(), 0);
+    }
+}
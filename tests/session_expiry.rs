@@ -0,0 +1,71 @@
+mod common;
+
+use std::time::Duration;
+
+use actix_web::{http::StatusCode, rt::time::sleep, test, test::TestRequest};
+use serde_json::{json, Value};
+
+use common::{browser, with_session, CHROME, HOME, PASSWORD};
+
+#[actix_web::test]
+async fn unused_sessions_expire_after_the_idle_timeout() {
+    let mut config = common::config();
+    config.session.idle_timeout = 2;
+
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+    let registration = common::register(&app, "arthur").await;
+    assert_eq!(registration["session"]["ttl"], 2);
+    let token = registration["session"]["token"].as_str().unwrap();
+
+    sleep(Duration::from_millis(2100)).await;
+
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), token).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn used_sessions_slide_until_their_lifetime_ends() {
+    let mut config = common::config();
+    config.session.idle_timeout = 3;
+    config.session.ttl = 5;
+
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+    common::register(&app, "arthur").await;
+
+    let request = browser(TestRequest::post().uri("/login"), CHROME, HOME)
+        .set_json(json!({ "username": "arthur", "password": PASSWORD }))
+        .to_request();
+    let session: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(session["ttl"], 3);
+    let token = session["token"].as_str().unwrap();
+
+    let sessions = || {
+        with_session(
+            browser(TestRequest::get().uri("/sessions"), CHROME, HOME),
+            token,
+        )
+        .to_request()
+    };
+
+    // Every use extends the session, past the initial idle timeout.
+    for _ in 0..3 {
+        sleep(Duration::from_millis(1200)).await;
+        let response = test::call_service(&app, sessions()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let listed: Value = test::call_and_read_body_json(&app, sessions()).await;
+    let created_at = listed[0]["created_at"].as_u64().unwrap();
+    let expires_at = listed[0]["expires_at"].as_u64().unwrap();
+    assert!(expires_at > created_at + 3);
+    assert!(expires_at <= created_at + 5);
+
+    // The session ends at the end of its lifetime, even though it is still used.
+    sleep(Duration::from_millis(1500)).await;
+    let response = test::call_service(&app, sessions()).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
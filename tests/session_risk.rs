@@ -0,0 +1,145 @@
+mod common;
+
+use std::sync::Arc;
+
+use actix_web::{
+    http::StatusCode,
+    test::{call_service, TestRequest},
+};
+
+use accounts_rest_api::{
+    config::RiskConfig,
+    structs::{
+        cookie::Fingerprint,
+        risk::{Mismatch, RiskAssessment, RiskOutcome},
+        user_agent::UserAgentPlatform,
+    },
+    traits::SessionRiskScorer,
+    types::RiskScorer,
+    util::risk::WeightedRiskScorer,
+};
+use common::{browser, with_session, CHROME, ELSEWHERE, HOME};
+
+/// Chrome after an update, only the version of the browser changed.
+const UPDATED_CHROME: &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/107.0.0.0 Safari/537.36";
+
+fn platform(name: &str) -> UserAgentPlatform {
+    UserAgentPlatform {
+        name: name.to_string(),
+        version: "1".to_string(),
+        details: "details".to_string(),
+    }
+}
+
+fn fingerprint(platforms: &[&str], extensions: &[&str]) -> Fingerprint {
+    Fingerprint {
+        ip: "ip".to_string(),
+        platforms: platforms.iter().map(|name| platform(name)).collect(),
+        extensions: extensions.iter().map(|e| e.to_string()).collect(),
+    }
+}
+
+#[test]
+fn missing_and_extra_entries_are_mismatches() {
+    let scorer = WeightedRiskScorer::new(&RiskConfig::default());
+    let expected = fingerprint(&["Mozilla", "AppleWebKit"], &["Chrome"]);
+
+    let identical = scorer.assess(
+        &expected,
+        &fingerprint(&["Mozilla", "AppleWebKit"], &["Chrome"]),
+    );
+    assert_eq!(identical.score, 1.0);
+    assert_eq!(identical.outcome, RiskOutcome::Allow);
+
+    let fewer = scorer.assess(&expected, &fingerprint(&["Mozilla"], &["Chrome"]));
+    assert_eq!(fewer.mismatches, vec![Mismatch::MissingPlatform(1)]);
+    // 3 of the 1 + 2 * 3 + 1 weights do not match.
+    assert_eq!(fewer.score, 1.0 - 3.0 / 8.0);
+    assert_eq!(fewer.outcome, RiskOutcome::StepUp);
+
+    let more = scorer.assess(
+        &expected,
+        &fingerprint(&["Mozilla", "AppleWebKit"], &["Chrome", "Safari"]),
+    );
+    assert_eq!(more.mismatches, vec![Mismatch::ExtraExtension(1)]);
+    assert_eq!(more.score, 1.0 - 1.0 / 9.0);
+}
+
+#[test]
+fn weights_and_thresholds_are_configurable() {
+    let expected = fingerprint(&["Mozilla"], &["Chrome"]);
+    let mut moved = fingerprint(&["Mozilla"], &["Chrome"]);
+    moved.ip = "another ip".to_string();
+
+    let scorer = WeightedRiskScorer::new(&RiskConfig::default());
+    let assessment = scorer.assess(&expected, &moved);
+    assert_eq!(assessment.mismatches, vec![Mismatch::Ip]);
+    assert_eq!(assessment.score, 0.8);
+    assert_eq!(assessment.outcome, RiskOutcome::StepUp);
+
+    let scorer = WeightedRiskScorer::new(&RiskConfig {
+        ip: 0.0,
+        ..RiskConfig::default()
+    });
+    let assessment = scorer.assess(&expected, &moved);
+    assert_eq!(assessment.score, 1.0);
+    assert_eq!(assessment.outcome, RiskOutcome::Allow);
+
+    let scorer = WeightedRiskScorer::new(&RiskConfig {
+        step_up_threshold: 0.9,
+        revoke_threshold: 0.9,
+        ..RiskConfig::default()
+    });
+    assert_eq!(
+        scorer.assess(&expected, &moved).outcome,
+        RiskOutcome::Revoke
+    );
+}
+
+#[actix_web::test]
+async fn doubtful_sessions_require_signing_in_again() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    // Another network and another browser version.
+    let request = with_session(
+        browser(TestRequest::get().uri("/me"), UPDATED_CHROME, ELSEWHERE),
+        &token,
+    )
+    .to_request();
+    let response = call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // The session is kept for its owner.
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+    let response = call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+/// A scorer that trusts nobody.
+struct Paranoid;
+
+impl SessionRiskScorer for Paranoid {
+    fn assess(&self, _expected: &Fingerprint, _actual: &Fingerprint) -> RiskAssessment {
+        RiskAssessment {
+            score: 0.0,
+            outcome: RiskOutcome::Revoke,
+            mismatches: vec![],
+        }
+    }
+}
+
+#[actix_web::test]
+async fn scorer_is_pluggable() {
+    let db = common::database();
+    let scorer: RiskScorer = Arc::new(Paranoid);
+    let app = common::init_with_scorer(&db, common::config(), scorer).await;
+    let token = common::session(&app, "arthur").await;
+
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+    let response = call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::GONE);
+}
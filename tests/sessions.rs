@@ -0,0 +1,305 @@
+mod common;
+
+use std::sync::Arc;
+
+use actix_web::{
+    body::MessageBody,
+    cookie::Cookie,
+    dev::{Service, ServiceResponse},
+    http::{header, StatusCode},
+    test,
+    test::TestRequest,
+    web::Data,
+};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use accounts_rest_api::{
+    errors::{StorageError, StorageResult},
+    traits::TemporaryStorageProvider,
+    util::{data::InMemoryDataProvider, hashing::xx_hash, Database},
+};
+use common::{browser, with_bearer, with_session, CHROME, ELSEWHERE, FIREFOX, HOME, PASSWORD};
+
+/// Log in from another client and return the token.
+async fn login<S, B>(app: &S, request: TestRequest, session: &str) -> String
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let request = request
+        .uri("/login")
+        .set_json(json!({ "username": "arthur", "password": PASSWORD, "session": session }))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(app, request).await;
+    body["token"].as_str().unwrap().to_string()
+}
+
+/// Register a user and return an API session of them.
+async fn login_as<S, B>(app: &S, username: &str) -> String
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let mut body = common::registration(username);
+    body["session"] = json!("api");
+    let request = TestRequest::post()
+        .uri("/register")
+        .peer_addr(HOME.parse().unwrap())
+        .set_json(body)
+        .to_request();
+    let response: Value = test::call_and_read_body_json(app, request).await;
+    response["session"]["token"].as_str().unwrap().to_string()
+}
+
+#[actix_web::test]
+async fn sessions_are_listed_with_their_details() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+    login(
+        &app,
+        browser(TestRequest::post(), FIREFOX, ELSEWHERE),
+        "browser",
+    )
+    .await;
+
+    let request = with_session(
+        browser(TestRequest::get().uri("/sessions"), CHROME, HOME),
+        &token,
+    )
+    .to_request();
+    let sessions: Value = test::call_and_read_body_json(&app, request).await;
+    let sessions = sessions.as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+
+    let current: Vec<&Value> = sessions.iter().filter(|s| s["current"] == true).collect();
+    assert_eq!(current.len(), 1);
+    let current = current[0];
+    assert_eq!(current["kind"], "browser");
+    assert_eq!(current["platforms"][0]["name"], "Mozilla");
+    assert!(current["created_at"].as_u64().unwrap() > 0);
+    assert!(current["last_used_at"].as_u64().unwrap() >= current["created_at"].as_u64().unwrap());
+
+    // Neither the token nor the address are exposed.
+    for session in sessions {
+        assert_ne!(session["id"], token);
+        assert!(!session["ip"].as_str().unwrap().contains("192.168"));
+        // An unkeyed hash of an address is easily reversed.
+        assert_ne!(session["ip"], xx_hash("192.168.1.10"));
+    }
+}
+
+#[actix_web::test]
+async fn revoking_one_session_keeps_the_others() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+    let other = login(&app, TestRequest::post(), "api").await;
+
+    let request = with_bearer(TestRequest::get().uri("/sessions"), &other).to_request();
+    let sessions: Value = test::call_and_read_body_json(&app, request).await;
+    let id = sessions
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|s| s["current"] == true)
+        .unwrap()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let request = with_session(
+        browser(
+            TestRequest::delete().uri(&format!("/sessions/{}", id)),
+            CHROME,
+            HOME,
+        ),
+        &token,
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    // Another session was removed, so the cookie of this one stays.
+    assert!(response.headers().get(header::SET_COOKIE).is_none());
+
+    let request = with_bearer(TestRequest::get().uri("/me"), &other).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The session is gone, so it can not be revoked twice.
+    let request = with_session(
+        browser(
+            TestRequest::delete().uri(&format!("/sessions/{}", id)),
+            CHROME,
+            HOME,
+        ),
+        &token,
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn sessions_of_other_users_can_not_be_revoked() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let arthur = login_as(&app, "arthur").await;
+    let ford = login_as(&app, "ford").await;
+
+    let request = with_bearer(TestRequest::get().uri("/sessions"), &ford).to_request();
+    let sessions: Value = test::call_and_read_body_json(&app, request).await;
+    let id = sessions[0]["id"].as_str().unwrap();
+
+    let request = with_bearer(
+        TestRequest::delete().uri(&format!("/sessions/{}", id)),
+        &arthur,
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let request = with_bearer(TestRequest::get().uri("/me"), &ford).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn current_session_signs_out_this_device_only() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+    let other = login(&app, TestRequest::post(), "api").await;
+
+    let request = with_session(
+        browser(TestRequest::delete().uri("/sessions/current"), CHROME, HOME),
+        &token,
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let cookie = response.headers().get(header::SET_COOKIE).unwrap();
+    let cookie = Cookie::parse_encoded(cookie.to_str().unwrap().to_string()).unwrap();
+    assert_eq!(cookie.value(), "");
+
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = with_bearer(TestRequest::get().uri("/sessions"), &other).to_request();
+    let sessions: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(sessions.as_array().unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn logout_signs_out_everywhere() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+    let other = login(&app, TestRequest::post(), "api").await;
+
+    let request = with_session(
+        browser(TestRequest::delete().uri("/logout"), CHROME, HOME),
+        &token,
+    )
+    .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = with_bearer(TestRequest::get().uri("/me"), &other).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // A new session starts with a clean list.
+    let other = login(&app, TestRequest::post(), "api").await;
+    let request = with_bearer(TestRequest::get().uri("/sessions"), &other).to_request();
+    let sessions: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(sessions.as_array().unwrap().len(), 1);
+}
+
+/// Temporary storage that can not read values longer than a reply buffer, like
+/// the Firefly client.
+struct ShortReplies {
+    inner: InMemoryDataProvider,
+    max_buffer: usize,
+}
+
+#[async_trait]
+impl TemporaryStorageProvider for ShortReplies {
+    async fn get(&self, key: String) -> StorageResult<Option<String>> {
+        match self.inner.get(key).await? {
+            Some(value) if value.len() > self.max_buffer => Err(StorageError::Unavailable(
+                "the reply did not fit the buffer".to_string(),
+            )),
+            value => Ok(value),
+        }
+    }
+
+    async fn set(&self, key: String, value: String) -> StorageResult<()> {
+        self.inner.set(key, value).await
+    }
+
+    async fn set_with_ttl(&self, key: String, value: String, ttl: usize) -> StorageResult<()> {
+        self.inner.set_with_ttl(key, value, ttl).await
+    }
+
+    async fn set_if_absent(&self, key: String, value: String, ttl: usize) -> StorageResult<bool> {
+        self.inner.set_if_absent(key, value, ttl).await
+    }
+
+    async fn delete(&self, key: String) -> StorageResult<()> {
+        self.inner.delete(key).await
+    }
+
+    async fn drop_all(&self, value: String) -> StorageResult<()> {
+        self.inner.drop_all(value).await
+    }
+}
+
+#[actix_web::test]
+async fn the_oldest_sessions_make_room() {
+    let mut config = common::config();
+    config.rate_limit.login.requests = 0;
+    config.session.max_sessions = 5;
+    config.firefly.max_buffer = 512 + 5 * 67;
+    config.validate().unwrap();
+
+    let db = Data::new(Arc::new(Database::new(
+        Box::new(InMemoryDataProvider::new()),
+        Box::new(ShortReplies {
+            inner: InMemoryDataProvider::new(),
+            max_buffer: config.firefly.max_buffer,
+        }),
+    )));
+    let app = common::init_with(&db, config).await;
+    let first = common::session(&app, "arthur").await;
+
+    let mut tokens = Vec::new();
+    for _ in 0..12 {
+        tokens.push(login(&app, TestRequest::post(), "api").await);
+    }
+
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &first).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let request = with_bearer(TestRequest::get().uri("/me"), &tokens[11]).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = with_bearer(TestRequest::get().uri("/sessions"), &tokens[11]).to_request();
+    let sessions: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(sessions.as_array().unwrap().len(), 5);
+
+    let request = with_bearer(TestRequest::delete().uri("/logout"), &tokens[11]).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
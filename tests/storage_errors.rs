@@ -0,0 +1,92 @@
+mod common;
+
+use std::sync::Arc;
+
+use actix_web::{http::StatusCode, test, test::TestRequest, web::Data};
+use async_trait::async_trait;
+use serde_json::Value;
+
+use accounts_rest_api::{
+    errors::{StorageError, StorageResult},
+    traits::TemporaryStorageProvider,
+    util::{data::InMemoryDataProvider, Database},
+};
+use common::{browser, registration, CHROME, HOME};
+
+/// A temporary storage that can never be reached.
+struct UnreachableStorage;
+
+#[async_trait]
+impl TemporaryStorageProvider for UnreachableStorage {
+    async fn get(&self, _key: String) -> StorageResult<Option<String>> {
+        Err(StorageError::Unavailable("connection refused".to_string()))
+    }
+
+    async fn set(&self, _key: String, _value: String) -> StorageResult<()> {
+        Err(StorageError::Unavailable("connection refused".to_string()))
+    }
+
+    async fn set_with_ttl(&self, _key: String, _value: String, _ttl: usize) -> StorageResult<()> {
+        Err(StorageError::Unavailable("connection refused".to_string()))
+    }
+
+    async fn set_if_absent(
+        &self,
+        _key: String,
+        _value: String,
+        _ttl: usize,
+    ) -> StorageResult<bool> {
+        Err(StorageError::Unavailable("connection refused".to_string()))
+    }
+
+    async fn delete(&self, _key: String) -> StorageResult<()> {
+        Err(StorageError::Unavailable("connection refused".to_string()))
+    }
+
+    async fn drop_all(&self, _value: String) -> StorageResult<()> {
+        Err(StorageError::Unavailable("connection refused".to_string()))
+    }
+}
+
+#[actix_web::test]
+async fn unavailable_storage_is_an_internal_error() {
+    let db = Data::new(Arc::new(Database::new(
+        Box::new(InMemoryDataProvider::new()),
+        Box::new(UnreachableStorage),
+    )));
+    let app = common::init(&db).await;
+
+    let request = browser(TestRequest::post().uri("/register"), CHROME, HOME)
+        .set_json(registration("arthur"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    // The reason of the failure is not leaked to the client.
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["message"], "Failed to access storage.");
+}
+
+#[actix_web::test]
+async fn taken_username_and_email_are_reported() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    common::register(&app, "arthur").await;
+
+    let mut taken_email = registration("ford");
+    taken_email["email"] = "arthur@xiler.net".into();
+
+    for (body, message) in [
+        (registration("arthur"), "User already exists"),
+        (taken_email, "Email already exists"),
+    ] {
+        let request = browser(TestRequest::post().uri("/register"), CHROME, HOME)
+            .set_json(body)
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["message"], message);
+    }
+}
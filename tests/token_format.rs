@@ -0,0 +1,170 @@
+mod common;
+
+use std::str::FromStr;
+
+use actix_web::{
+    http::StatusCode,
+    test::{call_service, TestRequest},
+};
+use uuid::Uuid;
+
+use accounts_rest_api::{
+    config::Config,
+    util::{
+        parse::parse_user_agent,
+        sessions::{legacy_fingerprint, store},
+    },
+};
+use common::{browser, with_session, CHROME, HOME};
+
+const OLD_SECRET: &str = "an old secret that is long enough to sign";
+const NEW_SECRET: &str = "a new secret that is also long enough to sign";
+
+/// A configuration that signs with `signing_key`, out of the given keys.
+fn config_with_keys(signing_key: &str, keys: &[(&str, &str)]) -> Config {
+    let mut config = common::config();
+    config.tokens.signing_key = Some(signing_key.to_string());
+    config.tokens.keys = keys
+        .iter()
+        .map(|(id, secret)| (id.to_string(), secret.to_string()))
+        .collect();
+    config
+}
+
+/// Replace one of the dot separated parts of a token.
+fn replace_part(token: &str, index: usize, value: &str) -> String {
+    let mut parts: Vec<&str> = token.split('.').collect();
+    parts[index] = value;
+    parts.join(".")
+}
+
+#[actix_web::test]
+async fn browser_sessions_are_signed() {
+    let db = common::database();
+    let app = common::init_with(&db, config_with_keys("k1", &[("k1", OLD_SECRET)])).await;
+    let token = common::session(&app, "arthur").await;
+
+    let parts: Vec<&str> = token.split('.').collect();
+    assert_eq!(parts.len(), 7);
+    assert_eq!(parts[0], "s2");
+    assert_eq!(parts[1], "k1");
+    assert_eq!(parts[5].len(), 48);
+
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+    assert_eq!(call_service(&app, request).await.status(), StatusCode::OK);
+
+    // Changing the fingerprint, the random part or the key id breaks the signature.
+    let flipped = if parts[6].starts_with('0') { "1" } else { "0" };
+    for tampered in [
+        replace_part(&token, 2, "0000000000000000"),
+        replace_part(&token, 5, &"a".repeat(48)),
+        replace_part(&token, 6, &format!("{}{}", flipped, &parts[6][1..])),
+        replace_part(&token, 1, "k2"),
+    ] {
+        let request = with_session(
+            browser(TestRequest::get().uri("/me"), CHROME, HOME),
+            &tampered,
+        )
+        .to_request();
+        assert_eq!(
+            call_service(&app, request).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
+
+#[actix_web::test]
+async fn rotated_keys_keep_sessions_until_removed() {
+    let db = common::database();
+    let app = common::init_with(&db, config_with_keys("k1", &[("k1", OLD_SECRET)])).await;
+    let old_token = common::session(&app, "arthur").await;
+
+    // Sign with a new key, while the old one is still accepted.
+    let app = common::init_with(
+        &db,
+        config_with_keys("k2", &[("k1", OLD_SECRET), ("k2", NEW_SECRET)]),
+    )
+    .await;
+    let new_token = common::session(&app, "ford").await;
+    assert_eq!(new_token.split('.').nth(1), Some("k2"));
+
+    for token in [&old_token, &new_token] {
+        let request =
+            with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), token).to_request();
+        assert_eq!(call_service(&app, request).await.status(), StatusCode::OK);
+    }
+
+    // Once the old key is removed its sessions end.
+    let app = common::init_with(&db, config_with_keys("k2", &[("k2", NEW_SECRET)])).await;
+    let request = with_session(
+        browser(TestRequest::get().uri("/me"), CHROME, HOME),
+        &old_token,
+    )
+    .to_request();
+    assert_eq!(
+        call_service(&app, request).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+
+    let request = with_session(
+        browser(TestRequest::get().uri("/me"), CHROME, HOME),
+        &new_token,
+    )
+    .to_request();
+    assert_eq!(call_service(&app, request).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn legacy_sessions_are_accepted_during_the_migration() {
+    let db = common::database();
+    let mut config = common::config();
+    config.tokens.accept_s1 = true;
+    let app = common::init_with(&db, config.clone()).await;
+    let registration = common::register(&app, "arthur").await;
+    let user_id = Uuid::from_str(registration["user"]["id"].as_str().unwrap()).unwrap();
+
+    // An unsigned token, as issued before tokens were signed.
+    let ip = HOME.split(':').next().unwrap();
+    let fingerprint = legacy_fingerprint(ip, &parse_user_agent(CHROME.to_string()));
+    let platforms = fingerprint
+        .platforms
+        .iter()
+        .map(|p| format!("{}-{}-{}", p.name, p.version, p.details))
+        .collect::<Vec<String>>()
+        .join("||");
+    let token = format!(
+        "s1.{}.{}.{}.{}",
+        fingerprint.ip,
+        platforms,
+        fingerprint.extensions.join("_"),
+        "a".repeat(32)
+    );
+
+    // Older versions stored the user under the token itself.
+    db.temporary
+        .set(token.clone(), user_id.to_string())
+        .await
+        .unwrap();
+
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+    assert_eq!(call_service(&app, request).await.status(), StatusCode::OK);
+
+    // The session was moved, so it is listed and the token is not stored anymore.
+    assert!(db.temporary.get(token.clone()).await.unwrap().is_none());
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+    assert_eq!(call_service(&app, request).await.status(), StatusCode::OK);
+    assert_eq!(store::list(&db, user_id).await.unwrap().len(), 2);
+
+    // After the migration window they are refused.
+    config.tokens.accept_s1 = false;
+    let app = common::init_with(&db, config).await;
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+    assert_eq!(
+        call_service(&app, request).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+}
@@ -0,0 +1,341 @@
+mod common;
+
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    http::{header, StatusCode},
+    test::{call_and_read_body_json, call_service, TestRequest},
+};
+use chrono::Utc;
+use data_encoding::BASE32_NOPAD;
+use serde_json::{json, Value};
+
+use accounts_rest_api::util::totp::{code_at, step_at, DIGITS};
+use common::{browser, with_session, CHROME, HOME, PASSWORD};
+
+/// The code of a secret, `steps` periods from now.
+fn code(secret: &str, steps: u64) -> String {
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).unwrap();
+    let step = step_at(Utc::now().timestamp() as u64) + steps;
+    format!("{:06}", code_at(&secret, step, DIGITS))
+}
+
+fn login() -> actix_http::Request {
+    browser(TestRequest::post().uri("/login"), CHROME, HOME)
+        .set_json(json!({ "username": "arthur", "password": PASSWORD }))
+        .to_request()
+}
+
+fn mfa(token: &str, code: &str) -> actix_http::Request {
+    browser(TestRequest::post().uri("/login/mfa"), CHROME, HOME)
+        .set_json(json!({ "mfa_token": token, "code": code }))
+        .to_request()
+}
+
+/// Register a user with an authenticator app, returns the secret, the code that
+/// confirmed it and the recovery codes.
+async fn enrolled<S, B>(app: &S) -> (String, String, Vec<String>)
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: actix_web::body::MessageBody,
+{
+    let token = common::session(app, "arthur").await;
+
+    let request = with_session(
+        browser(TestRequest::post().uri("/me/totp"), CHROME, HOME),
+        &token,
+    )
+    .to_request();
+    let enrollment: Value = call_and_read_body_json(app, request).await;
+    let secret = enrollment["secret"].as_str().unwrap().to_string();
+
+    let confirmed = code(&secret, 0);
+    let request = with_session(
+        browser(TestRequest::post().uri("/me/totp/confirm"), CHROME, HOME),
+        &token,
+    )
+    .set_json(json!({ "code": confirmed }))
+    .to_request();
+    let confirmation: Value = call_and_read_body_json(app, request).await;
+    let recovery_codes = confirmation["recovery_codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|code| code.as_str().unwrap().to_string())
+        .collect();
+
+    (secret, confirmed, recovery_codes)
+}
+
+/// Sign in with the password, returns the token for the second step.
+async fn mfa_token<S, B>(app: &S) -> String
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: actix_web::body::MessageBody,
+{
+    let response = call_service(app, login()).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let challenge: Value = actix_web::test::read_body_json(response).await;
+    challenge["mfa_token"].as_str().unwrap().to_string()
+}
+
+#[test]
+fn rfc_6238_test_vectors() {
+    let secret = b"12345678901234567890";
+    for (time, expected) in [
+        (59, 94287082),
+        (1111111109, 7081804),
+        (1111111111, 14050471),
+        (1234567890, 89005924),
+        (2000000000, 69279037),
+    ] {
+        assert_eq!(code_at(secret, step_at(time), 8), expected);
+    }
+}
+
+#[actix_web::test]
+async fn enroll_with_a_first_code() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let token = common::session(&app, "arthur").await;
+
+    let start = || {
+        with_session(
+            browser(TestRequest::post().uri("/me/totp"), CHROME, HOME),
+            &token,
+        )
+        .to_request()
+    };
+    let confirm = |code: &str| {
+        with_session(
+            browser(TestRequest::post().uri("/me/totp/confirm"), CHROME, HOME),
+            &token,
+        )
+        .set_json(json!({ "code": code }))
+        .to_request()
+    };
+
+    let response = call_service(&app, confirm("123456")).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let enrollment: Value = call_and_read_body_json(&app, start()).await;
+    let secret = enrollment["secret"].as_str().unwrap();
+    assert_eq!(
+        enrollment["uri"],
+        format!(
+            "otpauth://totp/Xiler:arthur?secret={}&issuer=Xiler&algorithm=SHA1&digits=6&period=30",
+            secret
+        )
+    );
+
+    let wrong = if code(secret, 0) == "000000" {
+        "111111"
+    } else {
+        "000000"
+    };
+    let response = call_service(&app, confirm(wrong)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let confirmation: Value = call_and_read_body_json(&app, confirm(&code(secret, 0))).await;
+    assert_eq!(confirmation["recovery_codes"].as_array().unwrap().len(), 10);
+
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+    let me: Value = call_and_read_body_json(&app, request).await;
+    assert_eq!(me["authentication"], 256);
+
+    let response = call_service(&app, start()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // The secret can not be replaced through the generic endpoint.
+    let request = with_session(
+        browser(TestRequest::put().uri("/authentication/256"), CHROME, HOME),
+        &token,
+    )
+    .set_json(json!({ "value": "{}" }))
+    .to_request();
+    let response = call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn login_requires_the_second_factor() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let (secret, confirmed, _) = enrolled(&app).await;
+
+    let response = call_service(&app, login()).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert!(response.headers().get(header::SET_COOKIE).is_none());
+    let challenge: Value = actix_web::test::read_body_json(response).await;
+    assert_eq!(challenge["methods"], json!(["totp"]));
+    assert!(challenge.get("token").is_none());
+    let token = challenge["mfa_token"].as_str().unwrap();
+
+    // The code that confirmed the enrollment can not be used again.
+    let response = call_service(&app, mfa(token, &confirmed)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = call_service(&app, mfa(token, &code(&secret, 1))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(header::SET_COOKIE).is_some());
+    let session: Value = actix_web::test::read_body_json(response).await;
+
+    let request = with_session(
+        browser(TestRequest::get().uri("/me"), CHROME, HOME),
+        session["token"].as_str().unwrap(),
+    )
+    .to_request();
+    let response = call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The token is used up.
+    let response = call_service(&app, mfa(token, "123456")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn recovery_codes_work_once() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let (_, _, recovery_codes) = enrolled(&app).await;
+
+    let token = mfa_token(&app).await;
+    let response = call_service(&app, mfa(&token, &recovery_codes[0].to_uppercase())).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let token = mfa_token(&app).await;
+    let response = call_service(&app, mfa(&token, &recovery_codes[0])).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = call_service(&app, mfa(&token, &recovery_codes[1])).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn too_many_wrong_codes_end_the_sign_in() {
+    let mut config = common::config();
+    config.mfa.max_attempts = 2;
+
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+    let (secret, _, _) = enrolled(&app).await;
+
+    let token = mfa_token(&app).await;
+    for _ in 0..2 {
+        let response = call_service(&app, mfa(&token, "not a code")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let response = call_service(&app, mfa(&token, &code(&secret, 1))).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn removing_the_app_needs_a_code_or_the_password() {
+    let db = common::database();
+    let app = common::init(&db).await;
+    let (_, _, recovery_codes) = enrolled(&app).await;
+
+    let token = mfa_token(&app).await;
+    let session: Value = call_and_read_body_json(&app, mfa(&token, &recovery_codes[0])).await;
+    let session = session["token"].as_str().unwrap();
+    let remove = |body: Value| {
+        with_session(
+            browser(TestRequest::delete().uri("/me/totp"), CHROME, HOME),
+            session,
+        )
+        .set_json(body)
+        .to_request()
+    };
+
+    // A session alone is not enough.
+    let request = with_session(
+        browser(
+            TestRequest::delete().uri("/authentication/256"),
+            CHROME,
+            HOME,
+        ),
+        session,
+    )
+    .to_request();
+    let response = call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = call_service(&app, remove(json!({}))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = call_service(&app, remove(json!({ "code": "not a code" }))).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = call_service(&app, remove(json!({ "password": "not the password" }))).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = call_service(&app, remove(json!({ "password": PASSWORD }))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = call_service(&app, login()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn wrong_codes_count_as_failed_sign_ins() {
+    let mut config = common::config();
+    config.lockout.backoff_after = 100;
+    config.lockout.account_threshold = 3;
+    config.lockout.duration = 60;
+
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+    enrolled(&app).await;
+
+    let wrong_password = || {
+        browser(TestRequest::post().uri("/login"), CHROME, HOME)
+            .set_json(json!({ "username": "arthur", "password": "wrong" }))
+            .to_request()
+    };
+    for _ in 0..2 {
+        let response = call_service(&app, wrong_password()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // The password alone does not forget the failures, so this code locks the account.
+    let token = mfa_token(&app).await;
+    let response = call_service(&app, mfa(&token, "not a code")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = call_service(&app, login()).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let response = call_service(&app, mfa(&token, "not a code")).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[actix_web::test]
+async fn codes_to_remove_the_app_can_not_be_guessed() {
+    let mut config = common::config();
+    config.lockout.backoff_after = 100;
+    config.lockout.account_threshold = 3;
+    config.lockout.duration = 60;
+
+    let db = common::database();
+    let app = common::init_with(&db, config).await;
+    let (_, _, recovery_codes) = enrolled(&app).await;
+
+    let token = mfa_token(&app).await;
+    let session: Value = call_and_read_body_json(&app, mfa(&token, &recovery_codes[0])).await;
+    let session = session["token"].as_str().unwrap();
+    let remove = |code: &str| {
+        with_session(
+            browser(TestRequest::delete().uri("/me/totp"), CHROME, HOME),
+            session,
+        )
+        .set_json(json!({ "code": code }))
+        .to_request()
+    };
+
+    for _ in 0..3 {
+        let response = call_service(&app, remove("not a code")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // Even the right code has to wait now.
+    let response = call_service(&app, remove(&recovery_codes[1])).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use actix_web::{
+    http::{header, StatusCode},
+    rt::time::sleep,
+    test,
+    test::TestRequest,
+};
+use serde_json::Value;
+
+use accounts_rest_api::util::mail::OutboxMailer;
+use common::{browser, verification_code, with_session, CHROME, HOME};
+
+const EMAIL: &str = "arthur@xiler.net";
+
+#[actix_web::test]
+async fn verify_with_the_emailed_link() {
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, common::config(), outbox.clone()).await;
+    let token = common::session(&app, "arthur").await;
+
+    let mail = outbox.last_to(EMAIL).unwrap();
+    assert!(mail
+        .body
+        .contains("https://accounts.xiler.net/verify?code="));
+    let code = verification_code(&outbox, EMAIL);
+    assert_eq!(code.len(), 64);
+
+    let request = browser(TestRequest::get().uri("/verify?code=wrong"), CHROME, HOME).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // The link works without a session, it may be opened in another browser.
+    let uri = format!("/verify?code={}", code);
+    let request = TestRequest::get().uri(&uri).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request =
+        with_session(browser(TestRequest::get().uri("/me"), CHROME, HOME), &token).to_request();
+    let me: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(me["verified"], true);
+
+    // A code can only be used once.
+    let request = TestRequest::get().uri(&uri).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn links_expire() {
+    let mut config = common::config();
+    config.verification.ttl = 1;
+
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, config, outbox.clone()).await;
+    common::register(&app, "arthur").await;
+    let code = verification_code(&outbox, EMAIL);
+
+    sleep(Duration::from_millis(2100)).await;
+
+    let uri = format!("/verify?code={}", code);
+    let request = TestRequest::get().uri(&uri).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn resend_is_throttled_and_replaces_the_link() {
+    let mut config = common::config();
+    config.verification.resend_interval = 1;
+
+    let db = common::database();
+    let outbox = Arc::new(OutboxMailer::new(None));
+    let app = common::init_with_mailer(&db, config, outbox.clone()).await;
+    let token = common::session(&app, "arthur").await;
+    let first = verification_code(&outbox, EMAIL);
+
+    let resend = || {
+        with_session(
+            browser(TestRequest::post().uri("/verify/resend"), CHROME, HOME),
+            &token,
+        )
+        .to_request()
+    };
+
+    // Registering already sent a link.
+    let response = test::call_service(&app, resend()).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "1");
+    assert_eq!(outbox.mails().len(), 1);
+
+    sleep(Duration::from_millis(1100)).await;
+
+    let response = test::call_service(&app, resend()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(outbox.mails().len(), 2);
+    let second = verification_code(&outbox, EMAIL);
+    assert_ne!(first, second);
+
+    let request = TestRequest::get()
+        .uri(&format!("/verify?code={}", first))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = TestRequest::get()
+        .uri(&format!("/verify?code={}", second))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    sleep(Duration::from_millis(1100)).await;
+
+    let response = test::call_service(&app, resend()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn resend_requires_a_session() {
+    let db = common::database();
+    let app = common::init(&db).await;
+
+    let request = browser(TestRequest::post().uri("/verify/resend"), CHROME, HOME).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,95 @@
+use std::{
+    rc::Rc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use actix_web::rt;
+
+use accounts_rest_api::util::workers::{WorkerError, WorkerPool};
+
+#[actix_web::test]
+async fn rejects_jobs_when_the_queue_is_full() {
+    let pool = Rc::new(WorkerPool::new(1, 1));
+    let (release, wait) = mpsc::channel::<()>();
+    let wait = Arc::new(Mutex::new(wait));
+
+    let running = {
+        let (pool, wait) = (pool.clone(), wait.clone());
+        rt::spawn(async move { pool.run(move || wait.lock().unwrap().recv().unwrap()).await })
+    };
+    let queued = {
+        let pool = pool.clone();
+        rt::spawn(async move { pool.run(|| 42).await })
+    };
+    rt::time::sleep(Duration::from_millis(50)).await;
+
+    assert_eq!(pool.run(|| 0).await, Err(WorkerError::QueueFull));
+
+    release.send(()).unwrap();
+    assert_eq!(running.await.unwrap(), Ok(()));
+    assert_eq!(queued.await.unwrap(), Ok(42));
+
+    // Capacity is available again once the jobs are done.
+    assert_eq!(pool.run(|| 1).await, Ok(1));
+}
+
+#[actix_web::test]
+async fn limits_the_amount_of_concurrent_jobs() {
+    let pool = Rc::new(WorkerPool::new(2, 16));
+    let active = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+
+    let jobs: Vec<_> = (0..8)
+        .map(|_| {
+            let (pool, active, peak) = (pool.clone(), active.clone(), peak.clone());
+            rt::spawn(async move {
+                pool.run(move || {
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(20));
+                    active.fetch_sub(1, Ordering::SeqCst);
+                })
+                .await
+            })
+        })
+        .collect();
+
+    for job in jobs {
+        assert_eq!(job.await.unwrap(), Ok(()));
+    }
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+}
+
+#[actix_web::test]
+async fn cancelled_jobs_keep_their_slot_until_they_finished() {
+    let pool = Rc::new(WorkerPool::new(1, 0));
+    let (release, wait) = mpsc::channel::<()>();
+    let (started, running) = mpsc::channel::<()>();
+
+    let cancelled = {
+        let pool = pool.clone();
+        rt::spawn(async move {
+            pool.run(move || {
+                started.send(()).unwrap();
+                wait.recv().unwrap()
+            })
+            .await
+        })
+    };
+    rt::task::spawn_blocking(move || running.recv().unwrap())
+        .await
+        .unwrap();
+
+    // The request is gone, but the job is still running on the worker.
+    cancelled.abort();
+    let _ = cancelled.await;
+    assert_eq!(pool.run(|| 0).await, Err(WorkerError::QueueFull));
+
+    release.send(()).unwrap();
+    rt::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(pool.run(|| 1).await, Ok(1));
+}